@@ -0,0 +1,433 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+//! Turns a [Header] into Rust source that uses objr's binding macros.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use crate::model::*;
+
+///Selectors declared by objr's `NSObjectSelectors`.  Declaring them again would make `Sel::init()` etc. ambiguous.
+const NSOBJECT_SELECTORS: &[&str] = &["alloc", "description", "respondsToSelector:", "init", "conformsToProtocol:", "dealloc", "copy"];
+
+///Names that the objr macros and traits already use for items we call.  A method with one of these names would shadow them.
+const RESERVED_NAMES: &[&str] = &["class", "nullable", "assume_nonnil", "cast", "cast_mut", "is_kind_of_class"];
+
+const KEYWORDS: &[&str] = &["as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let",
+    "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe",
+    "use", "where", "while", "async", "await", "dyn", "abstract", "become", "box", "do", "final", "macro", "override", "priv", "typeof", "unsized",
+    "virtual", "yield", "try", "union"];
+
+///Converts a selector to the name of its `Sel` function.
+///
+/// This must match `sel_to_rust_name` in objr_procmacro.
+pub fn sel_to_rust_name(selector: &str) -> String {
+    let mut name = selector.replace(':', "_");
+    if selector.matches(':').count() > 1 {
+        name.pop();
+    }
+    name
+}
+
+///Rust type for a C type, if it is a primitive we know
+fn primitive(c_type: &str) -> Option<&'static str> {
+    Some(match c_type {
+        "BOOL" | "bool" | "_Bool" => "bool",
+        "NSInteger" | "long" | "long long" | "long int" | "int64_t" | "CFIndex" => "i64",
+        "NSUInteger" | "unsigned long" | "unsigned long long" | "unsigned long int" | "uint64_t" | "size_t" => "u64",
+        "int" | "int32_t" | "signed" | "signed int" => "i32",
+        "unsigned" | "unsigned int" | "uint32_t" => "u32",
+        "short" | "int16_t" => "i16",
+        "unsigned short" | "uint16_t" | "unichar" => "u16",
+        "char" | "signed char" | "int8_t" => "i8",
+        "unsigned char" | "uint8_t" => "u8",
+        "double" | "CGFloat" | "NSTimeInterval" | "CFTimeInterval" => "f64",
+        "float" => "f32",
+        "SEL" => "Sel",
+        _ => return None,
+    })
+}
+
+///Whether the selector belongs to a method family that returns +1, per the ARC naming conventions
+fn in_family(selector: &str, family: &str) -> bool {
+    let selector = selector.trim_start_matches('_');
+    selector.starts_with(family) && !selector[family.len()..].starts_with(|c: char| c.is_ascii_lowercase())
+}
+
+fn returns_retained(selector: &str) -> bool {
+    ["alloc", "new", "copy", "mutableCopy", "init"].iter().any(|family| in_family(selector, family))
+}
+
+///Converts a C identifier to one we can use for a Rust argument
+fn argument_name(name: &str) -> String {
+    match name {
+        //`pool` is taken by the autorelease pool argument
+        "self" | "Self" | "super" | "crate" | "pool" => format!("{}_", name),
+        name if KEYWORDS.contains(&name) => format!("r#{}", name),
+        name => name.to_owned(),
+    }
+}
+
+///Types declared in the header, which affect how we spell other types
+struct Known<'a> {
+    ///enum name to Rust raw type
+    enums: HashMap<&'a str, &'static str>,
+    protocols: HashSet<&'a str>,
+}
+
+///How a value crosses into ObjC
+struct Conversion {
+    rust_type: String,
+    ///Expression that converts the Rust argument named `{}` to the value we pass
+    pass: &'static str,
+}
+
+///Where a method is being emitted
+struct Scope<'a> {
+    ///Name of the type we're implementing
+    name: &'a str,
+    ///`objc_instance!` rather than `objc_class!`, so there is no `class()`
+    protocol: bool,
+    type_parameters: &'a [String],
+}
+
+impl<'a> Known<'a> {
+    fn object_type(&self, kind: &ObjectKind, scope: &Scope) -> String {
+        match kind {
+            ObjectKind::Instancetype => "Self".to_owned(),
+            ObjectKind::Id(protocols) => match protocols.as_slice() {
+                [protocol] if self.protocols.contains(protocol.as_str()) => protocol.clone(),
+                _ => "NSObject".to_owned(),
+            },
+            ObjectKind::Class(name) if scope.type_parameters.contains(name) => "NSObject".to_owned(),
+            ObjectKind::Class(name) if name == scope.name => "Self".to_owned(),
+            ObjectKind::Class(name) => name.clone(),
+        }
+    }
+
+    fn argument(&self, t: &Type, scope: &Scope) -> Result<Conversion, String> {
+        match t {
+            Type::Value(c_type) => {
+                if let Some(primitive) = primitive(c_type) {
+                    Ok(Conversion { rust_type: primitive.to_owned(), pass: "{}" })
+                }
+                else if self.enums.contains_key(c_type.as_str()) {
+                    Ok(Conversion { rust_type: c_type.clone(), pass: "{}.field()" })
+                }
+                else {
+                    Err(format!("unsupported type `{}`", c_type))
+                }
+            }
+            Type::VoidPointer { mutable: true } => Ok(Conversion { rust_type: "*mut std::ffi::c_void".to_owned(), pass: "{}" }),
+            Type::VoidPointer { mutable: false } => Ok(Conversion { rust_type: "*const std::ffi::c_void".to_owned(), pass: "{}" }),
+            Type::Object { kind, nullable } => {
+                let object = self.object_type(kind, scope);
+                let rust_type = if *nullable { format!("Option<&{}>", object) } else { format!("&{}", object) };
+                Ok(Conversion { rust_type, pass: "{}.assume_nonmut_perform()" })
+            }
+            Type::Void => Err("`void` argument".to_owned()),
+            Type::ErrorOut => Err("`NSError **` that is not the last argument".to_owned()),
+            Type::Unsupported(written) => Err(format!("unsupported type `{}`", written)),
+        }
+    }
+}
+
+///Emits Rust source for the header.
+///
+/// Selectors are declared in a single trait named `selector_trait`.
+pub fn emit(header: &Header, selector_trait: &str) -> String {
+    let known = Known {
+        enums: header.enums.iter().filter_map(|e| primitive(&e.raw_type).map(|p| (e.name.as_str(), p))).collect(),
+        protocols: header.protocols.iter().map(|p| p.name.as_str()).filter(|p| !header.classes.iter().any(|c| c.name == *p)).collect(),
+    };
+    let mut out = String::new();
+    let mut selectors: Vec<&str> = Vec::new();
+    let mut impls = String::new();
+
+    writeln!(out, "//Generated by objr-bindgen.").unwrap();
+    writeln!(out, "use objr::bindings::*;").unwrap();
+
+    for e in &header.enums {
+        emit_enum(&mut out, e);
+    }
+
+    for class in header.classes.iter().filter(|c| c.category.is_none()) {
+        writeln!(out).unwrap();
+        let doc = match &class.superclass {
+            Some(superclass) => format!("@interface {} : {}", class.name, superclass),
+            None => format!("@interface {}", class.name),
+        };
+        writeln!(out, "objc_class! {{\n    ///`{}`\n    pub struct {} {{\n        @class({})\n    }}\n}}", doc, class.name, class.name).unwrap();
+    }
+    for protocol in &header.protocols {
+        writeln!(out).unwrap();
+        if !known.protocols.contains(protocol.name.as_str()) {
+            writeln!(out, "//skipped `@protocol {}`, which has the same name as a class", protocol.name).unwrap();
+            continue;
+        }
+        writeln!(out, "objc_instance! {{\n    ///`@protocol {}`\n    pub struct {};\n}}", protocol.name, protocol.name).unwrap();
+    }
+
+    let interfaces = header.classes.iter().map(|c| (c, false)).chain(header.protocols.iter().filter(|p| known.protocols.contains(p.name.as_str())).map(|p| (p, true)));
+    for (interface, protocol) in interfaces {
+        let scope = Scope { name: &interface.name, protocol, type_parameters: &interface.type_parameters };
+        emit_impl(&mut impls, &mut selectors, &known, &scope, interface);
+    }
+
+    if !selectors.is_empty() {
+        writeln!(out, "\nobjc_selector_group! {{\n    pub trait {} {{", selector_trait).unwrap();
+        for selector in &selectors {
+            writeln!(out, "        @selector(\"{}\")", selector).unwrap();
+        }
+        writeln!(out, "    }}\n    impl {} for Sel {{}}\n}}", selector_trait).unwrap();
+    }
+    out.push_str(&impls);
+    out
+}
+
+fn emit_enum(out: &mut String, e: &Enum) {
+    writeln!(out).unwrap();
+    let raw_type = match primitive(&e.raw_type) {
+        Some(raw_type) => raw_type,
+        None => {
+            writeln!(out, "//skipped enum `{}` with unsupported type `{}`", e.name, e.raw_type).unwrap();
+            return;
+        }
+    };
+    let mut members = Vec::new();
+    for member in &e.members {
+        match &member.value {
+            Some(EnumValue::Evaluated(value)) if fits(*value, raw_type) => {
+                let power = value.trailing_zeros();
+                let spelled = if e.options && *value > 1 && value.count_ones() == 1 { format!("1 << {}", power) } else { value.to_string() };
+                members.push(format!("        {} = {}", member.name, spelled));
+            }
+            Some(EnumValue::Evaluated(value)) => writeln!(out, "//skipped `{}`, {} doesn't fit in {}", member.name, value, raw_type).unwrap(),
+            Some(EnumValue::Unevaluated(expression)) => writeln!(out, "//skipped `{} = {}`", member.name, expression).unwrap(),
+            None => writeln!(out, "//skipped `{}`", member.name).unwrap(),
+        }
+    }
+    writeln!(out, "objc_enum! {{\n    #[derive(Copy,Clone,Debug,PartialEq,Eq,Hash)]\n    pub struct {}<{}>;\n    impl {} {{", e.name, raw_type, e.name).unwrap();
+    writeln!(out, "{}", members.join(",\n")).unwrap();
+    writeln!(out, "    }}\n}}").unwrap();
+}
+
+fn fits(value: i128, raw_type: &str) -> bool {
+    let (min, max) = match raw_type {
+        "i64" => (i64::MIN as i128, i64::MAX as i128),
+        "u64" => (0, u64::MAX as i128),
+        "i32" => (i32::MIN as i128, i32::MAX as i128),
+        "u32" => (0, u32::MAX as i128),
+        "i16" => (i16::MIN as i128, i16::MAX as i128),
+        "u16" => (0, u16::MAX as i128),
+        "i8" => (i8::MIN as i128, i8::MAX as i128),
+        "u8" => (0, u8::MAX as i128),
+        _ => return false,
+    };
+    (min..=max).contains(&value)
+}
+
+///Picks a Rust name for each method.  We prefer the first part of the selector, falling back to the whole selector for collisions.
+fn method_names(methods: &[Method]) -> Vec<Option<String>> {
+    let short = |m: &Method| m.selector.split(':').next().unwrap().to_owned();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for method in methods {
+        *counts.entry(short(method)).or_default() += 1;
+    }
+    let mut used = HashSet::new();
+    methods.iter().map(|method| {
+        let short = short(method);
+        let full = sel_to_rust_name(&method.selector).trim_end_matches('_').to_owned();
+        let candidates = if counts[&short] == 1 { vec![short, full] } else { vec![full] };
+        let name = candidates.into_iter().find(|c| !RESERVED_NAMES.contains(&c.as_str()) && !used.contains(c))?;
+        used.insert(name.clone());
+        Some(name)
+    }).collect()
+}
+
+fn emit_impl<'a>(out: &mut String, selectors: &mut Vec<&'a str>, known: &Known, scope: &Scope, interface: &'a Interface) {
+    let names = method_names(&interface.methods);
+    let mut body = String::new();
+    for (method, name) in interface.methods.iter().zip(names) {
+        let sign = if method.class_method { '+' } else { '-' };
+        let result = match name {
+            Some(name) => emit_method(known, scope, method, &name),
+            None => Err("no unique name".to_owned()),
+        };
+        match result {
+            Ok(source) => {
+                writeln!(body, "    ///Calls `{}[{} {}]`", sign, interface.name, method.selector).unwrap();
+                body.push_str(&source);
+                if !NSOBJECT_SELECTORS.contains(&method.selector.as_str()) && !selectors.contains(&method.selector.as_str()) {
+                    selectors.push(&method.selector);
+                }
+            }
+            Err(reason) => writeln!(body, "    //skipped `{}[{} {}]`: {}", sign, interface.name, method.selector, reason).unwrap(),
+        }
+    }
+    if body.is_empty() {
+        return;
+    }
+    writeln!(out).unwrap();
+    if let Some(category) = interface.category.as_ref().filter(|c| !c.is_empty()) {
+        writeln!(out, "//`@interface {} ({})`", interface.name, category).unwrap();
+    }
+    writeln!(out, "#[allow(non_snake_case)]\nimpl {} {{", interface.name).unwrap();
+    out.push_str(&body);
+    writeln!(out, "}}").unwrap();
+}
+
+fn emit_method(known: &Known, scope: &Scope, method: &Method, name: &str) -> Result<String, String> {
+    if method.variadic {
+        return Err("variadic".to_owned());
+    }
+    let sel_name = sel_to_rust_name(&method.selector);
+    if KEYWORDS.contains(&sel_name.as_str()) {
+        return Err("selector is a Rust keyword".to_owned());
+    }
+    let mut arguments = method.arguments.as_slice();
+    let error = matches!(arguments.last(), Some((_, Type::ErrorOut)));
+    if error {
+        arguments = &arguments[..arguments.len() - 1];
+    }
+    let init = !method.class_method && in_family(&method.selector, "init") && matches!(method.return_type, Type::Object { .. });
+    if (init || method.class_method) && scope.protocol {
+        return Err("needs a class".to_owned());
+    }
+
+    let mut parameters = Vec::new();
+    let mut passed = Vec::new();
+    for (argument, t) in arguments {
+        let conversion = known.argument(t, scope)?;
+        let argument = argument_name(argument);
+        parameters.push(format!("{}: {}", argument, conversion.rust_type));
+        passed.push(conversion.pass.replace("{}", &argument));
+    }
+    let args = if passed.is_empty() { "()".to_owned() } else { format!("({},)", passed.join(", ")) };
+
+    let (receiver_type, receiver) = if init {
+        ("Self".to_owned(), "Self::class().alloc(pool)")
+    }
+    else if method.class_method {
+        ("Class::<Self>".to_owned(), "Self::class().assume_nonmut_perform()")
+    }
+    else {
+        ("Self".to_owned(), "self.assume_nonmut_perform()")
+    };
+    let perform = |function: &str| format!("{}::{}({}, Sel::{}(), pool, {})", receiver_type, function, receiver, sel_name, args);
+
+    let mut lifetime = "";
+    let (return_type, expression) = match &method.return_type {
+        Type::Value(c_type) if error => {
+            if primitive(c_type) != Some("bool") {
+                return Err("error with a return type other than `BOOL`".to_owned());
+            }
+            lifetime = "<'a>";
+            ("Result<(), AutoreleasedCell<'a, NSError>>".to_owned(), perform("perform_bool_result"))
+        }
+        Type::Void | Type::Value(_) | Type::VoidPointer { .. } if error => return Err("error with a return type other than `BOOL`".to_owned()),
+        Type::Void => (String::new(), perform("perform_primitive")),
+        Type::Value(c_type) => {
+            if let Some(primitive) = primitive(c_type) {
+                (primitive.to_owned(), perform("perform_primitive"))
+            }
+            else if known.enums.contains_key(c_type.as_str()) {
+                (c_type.clone(), format!("{}({})", c_type, perform("perform_primitive")))
+            }
+            else {
+                return Err(format!("unsupported type `{}`", c_type));
+            }
+        }
+        Type::VoidPointer { mutable } => (format!("*{} std::ffi::c_void", if *mutable { "mut" } else { "const" }), perform("perform_primitive")),
+        Type::Object { kind, nullable } => {
+            let object = known.object_type(kind, scope);
+            let retained = returns_retained(&method.selector);
+            if error {
+                lifetime = "<'a>";
+                let function = if retained { "perform_result" } else { "perform_result_autorelease_to_retain" };
+                (format!("Result<StrongCell<{}>, AutoreleasedCell<'a, NSError>>", object),
+                 format!("{}.map(|raw| {}::assume_nonnil(raw).assume_retained())", perform(function), object))
+            }
+            else {
+                let function = if retained { "perform" } else { "perform_autorelease_to_retain" };
+                if *nullable {
+                    (format!("Option<StrongCell<{}>>", object), format!("let raw = {};\n            {}::nullable(raw).assume_retained()", perform(function), object))
+                }
+                else {
+                    (format!("StrongCell<{}>", object), format!("let raw = {};\n            {}::assume_nonnil(raw).assume_retained()", perform(function), object))
+                }
+            }
+        }
+        Type::ErrorOut => return Err("returns `NSError **`".to_owned()),
+        Type::Unsupported(written) => return Err(format!("unsupported type `{}`", written)),
+    };
+
+    let pool = if lifetime.is_empty() { "&ActiveAutoreleasePool" } else { "&'a ActiveAutoreleasePool" };
+    let mut signature = Vec::new();
+    if !init && !method.class_method {
+        signature.push("&self".to_owned());
+    }
+    signature.push(format!("pool: {}", pool));
+    signature.extend(parameters);
+    let name = if KEYWORDS.contains(&name) { format!("r#{}", name) } else { name.to_owned() };
+    let return_type = if return_type.is_empty() { String::new() } else { format!(" -> {}", return_type) };
+    let mut source = String::new();
+    writeln!(source, "    pub fn {}{}({}){} {{", name, lifetime, signature.join(", "), return_type).unwrap();
+    writeln!(source, "        unsafe {{\n            {}\n        }}\n    }}", expression).unwrap();
+    Ok(source)
+}
+
+#[test] fn selector_names() {
+    assert_eq!(sel_to_rust_name("height"), "height");
+    assert_eq!(sel_to_rust_name("height:"), "height_");
+    assert_eq!(sel_to_rust_name("height:width:"), "height_width");
+    assert!(returns_retained("initWithFoo:"));
+    assert!(returns_retained("copy"));
+    assert!(returns_retained("newBufferWithLength:"));
+    assert!(!returns_retained("copyright"));
+    assert!(!returns_retained("newsletter"));
+}
+
+#[test] fn emit_methods() {
+    let header = crate::parser::parse(r#"
+NS_ASSUME_NONNULL_BEGIN
+typedef NS_ENUM(NSUInteger, MTLStorageMode) {
+    MTLStorageModeShared = 0,
+    MTLStorageModePrivate = 2,
+};
+@protocol MTLBuffer
+@property (readonly) NSUInteger length;
+@end
+@interface MTLThing : NSObject
+- (nullable instancetype)initWithName:(NSString *)name error:(NSError **)error;
+- (nullable id<MTLBuffer>)newBufferWithLength:(NSUInteger)length options:(MTLStorageMode)options;
+- (BOOL)writeToURL:(nullable NSURL *)url error:(NSError **)error;
+@property (readonly) NSString *name;
+@property MTLStorageMode storageMode;
+@property float opacity;
++ (MTLThing *)sharedThing;
+- (void)takeBlock:(void (^)(void))block;
+@end
+NS_ASSUME_NONNULL_END
+"#);
+    let source = emit(&header, "MTLThingSelectors");
+    let expected = [
+        "pub struct MTLStorageMode<u64>;",
+        "MTLStorageModeShared = 0,\n        MTLStorageModePrivate = 2\n",
+        "objc_class! {\n    ///`@interface MTLThing : NSObject`\n    pub struct MTLThing {\n        @class(MTLThing)\n    }\n}",
+        "objc_instance! {\n    ///`@protocol MTLBuffer`\n    pub struct MTLBuffer;\n}",
+        "@selector(\"initWithName:error:\")",
+        "impl MTLThingSelectors for Sel {}",
+        "pub fn initWithName<'a>(pool: &'a ActiveAutoreleasePool, name: &NSString) -> Result<StrongCell<Self>, AutoreleasedCell<'a, NSError>> {\n        unsafe {\n            Self::perform_result(Self::class().alloc(pool), Sel::initWithName_error(), pool, (name.assume_nonmut_perform(),)).map(|raw| Self::assume_nonnil(raw).assume_retained())",
+        "pub fn newBufferWithLength(&self, pool: &ActiveAutoreleasePool, length: u64, options: MTLStorageMode) -> Option<StrongCell<MTLBuffer>> {\n        unsafe {\n            let raw = Self::perform(self.assume_nonmut_perform(), Sel::newBufferWithLength_options(), pool, (length, options.field(),));\n            MTLBuffer::nullable(raw).assume_retained()",
+        "pub fn writeToURL<'a>(&self, pool: &'a ActiveAutoreleasePool, url: Option<&NSURL>) -> Result<(), AutoreleasedCell<'a, NSError>> {\n        unsafe {\n            Self::perform_bool_result(self.assume_nonmut_perform(), Sel::writeToURL_error(), pool, (url.assume_nonmut_perform(),))",
+        "let raw = Self::perform_autorelease_to_retain(self.assume_nonmut_perform(), Sel::name(), pool, ());\n            NSString::assume_nonnil(raw).assume_retained()",
+        "pub fn storageMode(&self, pool: &ActiveAutoreleasePool) -> MTLStorageMode {\n        unsafe {\n            MTLStorageMode(Self::perform_primitive(self.assume_nonmut_perform(), Sel::storageMode(), pool, ()))",
+        "pub fn setStorageMode(&self, pool: &ActiveAutoreleasePool, storageMode: MTLStorageMode) {\n        unsafe {\n            Self::perform_primitive(self.assume_nonmut_perform(), Sel::setStorageMode_(), pool, (storageMode.field(),))\n",
+        "pub fn sharedThing(pool: &ActiveAutoreleasePool) -> StrongCell<Self> {\n        unsafe {\n            let raw = Class::<Self>::perform_autorelease_to_retain(Self::class().assume_nonmut_perform(), Sel::sharedThing(), pool, ());",
+        "pub fn setOpacity(&self, pool: &ActiveAutoreleasePool, opacity: f32) {",
+        "//skipped `-[MTLThing takeBlock:]`: unsupported type",
+        "impl MTLBuffer {\n    ///Calls `-[MTLBuffer length]`\n    pub fn length(&self, pool: &ActiveAutoreleasePool) -> u64 {",
+    ];
+    for e in expected {
+        assert!(source.contains(e), "expected\n{}\nin\n{}", e, source);
+    }
+}
@@ -0,0 +1,129 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+//! Splits header text into tokens.
+//!
+//! Comments, preprocessor lines and string literals are dropped, since nothing we generate depends on them.
+
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum Token {
+    ///Identifiers and keywords, including ObjC `@` keywords like `@interface`
+    Ident(String),
+    ///Numeric literal, as written (including any suffix like `UL`)
+    Number(String),
+    Punct(char),
+}
+
+impl Token {
+    pub fn is_ident(&self, ident: &str) -> bool {
+        matches!(self, Token::Ident(i) if i == ident)
+    }
+    pub fn is_punct(&self, punct: char) -> bool {
+        matches!(self, Token::Punct(p) if *p == punct)
+    }
+}
+
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    //whether only whitespace appears between the last newline and `i`
+    let mut line_start = true;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line_start = true;
+            i += 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '#' && line_start {
+            //preprocessor directive, which may continue onto the next line with `\`
+            while i < chars.len() && chars[i] != '\n' {
+                if chars[i] == '\\' && chars.get(i + 1) == Some(&'\n') {
+                    i += 1;
+                }
+                else if chars[i] == '/' && chars.get(i + 1) == Some(&'*') {
+                    i = skip_block_comment(&chars, i);
+                    continue;
+                }
+                i += 1;
+            }
+            continue;
+        }
+        line_start = false;
+        if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i = skip_block_comment(&chars, i);
+            continue;
+        }
+        if c == '"' || c == '\'' {
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i += 1;
+            continue;
+        }
+        if c == '@' && chars.get(i + 1) == Some(&'"') {
+            //@"string", skip the @ and let the string case handle the rest
+            i += 1;
+            continue;
+        }
+        if c == '@' || c == '_' || c.is_alphabetic() {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i] == '_' || chars[i].is_alphanumeric()) {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Number(chars[start..i].iter().collect()));
+            continue;
+        }
+        tokens.push(Token::Punct(c));
+        i += 1;
+    }
+    tokens
+}
+
+///Returns the index after the comment that starts at `i`
+fn skip_block_comment(chars: &[char], mut i: usize) -> usize {
+    i += 2;
+    while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+        i += 1;
+    }
+    i + 2
+}
+
+#[test] fn tokenize_basic() {
+    let tokens = tokenize(r#"
+#import <Foundation/Foundation.h>
+#define FOO \
+    bar
+/* comment */ @interface Foo : NSObject // trailing
+- (void)bar:(NSString *)s; @"literal" 0x10UL
+"#);
+    let expected = vec![
+        Token::Ident("@interface".to_owned()), Token::Ident("Foo".to_owned()), Token::Punct(':'), Token::Ident("NSObject".to_owned()),
+        Token::Punct('-'), Token::Punct('('), Token::Ident("void".to_owned()), Token::Punct(')'), Token::Ident("bar".to_owned()), Token::Punct(':'),
+        Token::Punct('('), Token::Ident("NSString".to_owned()), Token::Punct('*'), Token::Punct(')'), Token::Ident("s".to_owned()), Token::Punct(';'),
+        Token::Number("0x10UL".to_owned()),
+    ];
+    assert_eq!(tokens, expected);
+}
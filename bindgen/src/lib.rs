@@ -0,0 +1,52 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+/*!
+Generates objr bindings from ObjC headers.
+
+This reads `@interface`, `@protocol`, `@property`, `NS_ENUM`/`NS_OPTIONS` and method declarations out of `.h` files
+and writes the `objc_class!`, `objc_instance!`, `objc_enum!` and `objc_selector_group!` invocations and `impl` blocks
+you would otherwise write by hand.  It's pure text parsing, no clang required.
+
+```
+let source = objr_bindgen::generate(r#"
+NS_ASSUME_NONNULL_BEGIN
+@interface NSDate : NSObject
+- (instancetype)dateByAddingTimeInterval:(NSTimeInterval)ti;
+@end
+NS_ASSUME_NONNULL_END
+"#, "NSDateSelectors");
+assert!(source.contains("pub fn dateByAddingTimeInterval(&self, pool: &ActiveAutoreleasePool, ti: f64) -> StrongCell<Self>"));
+```
+
+# Conventions
+The `perform` variant for each method is chosen from ObjC conventions:
+* Methods in the `init`, `new`, `copy` and `mutableCopy` families return +1, so we use `perform`.  `init` methods become
+  associated functions that `alloc` the receiver.
+* Other methods return +0, so we use `perform_autorelease_to_retain`.
+* A trailing `error:(NSError **)` uses `perform_result` (or `perform_result_autorelease_to_retain`), or `perform_bool_result`
+  if the method returns `BOOL`.
+* Nullable return values are `Option`, others use `assume_nonnil`.  Declarations inside `NS_ASSUME_NONNULL_BEGIN` are nonnull unless annotated.
+
+All instance methods take `&self`.  If a method mutates the receiver, change it to `&mut self` by hand; see `objc_instance!`
+in objr for the details.
+
+# Limitations
+* Types declared elsewhere (like `NSURL`) are referenced by name, so they must be in scope where you include the output.
+* `id` is bound as `NSObject`, and `id<Protocol>` as the protocol if it's declared in the same headers.
+* Blocks, C arrays, function pointers, structs, `long double`, and variadic methods are skipped with a comment.
+* Macros are not expanded.  Attribute-like macros (`API_AVAILABLE(...)`, `NS_SWIFT_NAME(...)`, etc.) are dropped,
+  and declarations marked `NS_UNAVAILABLE` or `API_UNAVAILABLE(macos)` are skipped.
+*/
+
+mod lexer;
+mod model;
+mod parser;
+mod emit;
+
+pub use model::*;
+pub use parser::parse;
+pub use emit::{emit, sel_to_rust_name};
+
+///Parses the header source and emits bindings, declaring selectors in a trait named `selector_trait`.
+pub fn generate(source: &str, selector_trait: &str) -> String {
+    emit(&parse(source), selector_trait)
+}
@@ -0,0 +1,47 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+//! `objr-bindgen <header.h>... [--trait SelectorTraitName]`
+//!
+//! Writes bindings for the headers to stdout.
+
+use std::path::Path;
+
+fn main() {
+    let mut headers = Vec::new();
+    let mut selector_trait = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trait" => selector_trait = Some(args.next().unwrap_or_else(|| usage())),
+            "-h" | "--help" => usage(),
+            _ => headers.push(arg),
+        }
+    }
+    if headers.is_empty() {
+        usage();
+    }
+    //by default, name the trait after the first header, e.g. `MTLDevice.h` => `MTLDeviceSelectors`
+    let selector_trait = selector_trait.unwrap_or_else(|| {
+        let stem = Path::new(&headers[0]).file_stem().and_then(|s| s.to_str()).unwrap_or("Header");
+        let mut stem: String = stem.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '_').collect();
+        if let Some(first) = stem.get_mut(..1) {
+            first.make_ascii_uppercase();
+        }
+        format!("{}Selectors", stem)
+    });
+    let mut source = String::new();
+    for header in &headers {
+        match std::fs::read_to_string(header) {
+            Ok(text) => { source.push_str(&text); source.push('\n'); }
+            Err(e) => {
+                eprintln!("objr-bindgen: can't read {}: {}", header, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    print!("{}", objr_bindgen::generate(&source, &selector_trait));
+}
+
+fn usage() -> ! {
+    eprintln!("usage: objr-bindgen <header.h>... [--trait SelectorTraitName]");
+    std::process::exit(2);
+}
@@ -0,0 +1,86 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+//! The declarations we understand, independent of how they were spelled in the header.
+
+///Everything parsed out of one or more headers.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Header {
+    ///`@interface` declarations, including categories
+    pub classes: Vec<Interface>,
+    ///`@protocol` declarations
+    pub protocols: Vec<Interface>,
+    ///`NS_ENUM` and `NS_OPTIONS` declarations
+    pub enums: Vec<Enum>,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Interface {
+    pub name: String,
+    ///Generic parameters, like `ObjectType` in `NSArray<ObjectType>`
+    pub type_parameters: Vec<String>,
+    pub superclass: Option<String>,
+    ///Set for `@interface Name (Category)`.  Class extensions have an empty category name.
+    pub category: Option<String>,
+    pub protocols: Vec<String>,
+    ///Methods, including accessors for `@property` declarations
+    pub methods: Vec<Method>,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Method {
+    ///`+` rather than `-`
+    pub class_method: bool,
+    pub selector: String,
+    pub return_type: Type,
+    ///Name and type of each argument
+    pub arguments: Vec<(String, Type)>,
+    ///Ends in `, ...`
+    pub variadic: bool,
+}
+
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum Type {
+    Void,
+    ///Some non-pointer type, like `BOOL`, `NSUInteger` or an enum.  Resolved when we emit, since the enum might be declared later.
+    Value(String),
+    ///`void *`.  `mutable` is false for `const void *`.
+    VoidPointer { mutable: bool },
+    Object { kind: ObjectKind, nullable: bool },
+    ///`NSError **`
+    ErrorOut,
+    ///Something we can't bind, as written in the header
+    Unsupported(String),
+}
+
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum ObjectKind {
+    Instancetype,
+    ///`id`, perhaps with protocols as in `id<NSCopying>`
+    Id(Vec<String>),
+    ///A pointer to some class, like `NSString *`
+    Class(String),
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Enum {
+    pub name: String,
+    ///Underlying type, like `NSUInteger`
+    pub raw_type: String,
+    ///`NS_OPTIONS` rather than `NS_ENUM`
+    pub options: bool,
+    pub members: Vec<EnumMember>,
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct EnumMember {
+    pub name: String,
+    ///`None` if we could not work out the value
+    pub value: Option<EnumValue>,
+}
+
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum EnumValue {
+    ///Integer value
+    Evaluated(i128),
+    ///An expression we couldn't evaluate, as written in the header
+    Unevaluated(String),
+}
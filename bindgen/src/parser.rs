@@ -0,0 +1,664 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+//! Turns tokens into a [Header].
+//!
+//! This is not a C parser.  It understands the shapes of declarations that appear in framework headers, and skips
+//! anything else a statement at a time.
+
+use std::collections::HashMap;
+use crate::lexer::Token;
+use crate::model::*;
+
+///Stands in for availability attributes that make a declaration unavailable on macOS
+const UNAVAILABLE: &str = "@objr_unavailable";
+
+///Macros that look like attributes, but that we need to see
+const MEANINGFUL_MACROS: &[&str] = &["NS_ENUM", "NS_OPTIONS", "NS_CLOSED_ENUM", "NS_ERROR_ENUM", "NS_ASSUME_NONNULL_BEGIN", "NS_ASSUME_NONNULL_END"];
+
+///Attribute macros that don't take arguments, and so can't be recognized by a following `(`
+const BARE_ATTRIBUTE_SUFFIXES: &[&str] = &["_EXPORT", "_EXTERN", "_ATTRIBUTE", "_UNAVAILABLE", "_DESIGNATED_INITIALIZER", "_REQUIRES_SUPER",
+    "_RETAINED", "_INNER_POINTER", "_REFINED_FOR_SWIFT", "_NOESCAPE", "_ROOT_CLASS", "_NIL_TERMINATION", "_ENUM", "_SENDABLE", "_ACTOR", "_NONISOLATED",
+    "_NOTHROW", "_CONSUMED", "_ARGUMENT"];
+
+///Qualifiers that start with `__` but are part of the type
+const QUALIFIERS: &[&str] = &["__kindof", "__nullable", "__nonnull", "__null_unspecified", "__strong", "__weak", "__autoreleasing", "__unsafe_unretained",
+    "__covariant", "__contravariant", "__block"];
+
+///Parses the source of a header.
+pub fn parse(source: &str) -> Header {
+    let tokens = strip_attributes(crate::lexer::tokenize(source));
+    let mut parser = Parser { tokens, pos: 0, assume_nonnull: false, enum_values: HashMap::new() };
+    let mut header = Header::default();
+    parser.parse_header(&mut header);
+    header
+}
+
+fn is_macro_name(name: &str) -> bool {
+    name.len() >= 3 && name.contains('_') && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+///Removes availability and similar attributes, replacing those that make a declaration unavailable with [UNAVAILABLE].
+fn strip_attributes(tokens: Vec<Token>) -> Vec<Token> {
+    let mut output = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        let name = match &tokens[i] {
+            Token::Ident(name) => name.as_str(),
+            _ => { output.push(tokens[i].clone()); i += 1; continue; }
+        };
+        let has_arguments = tokens.get(i + 1).map(|t| t.is_punct('(')).unwrap_or(false);
+        let is_attribute = !MEANINGFUL_MACROS.contains(&name) && (
+            (name.starts_with("__") && !QUALIFIERS.contains(&name)) ||
+                (is_macro_name(name) && (has_arguments || BARE_ATTRIBUTE_SUFFIXES.iter().any(|s| name.ends_with(s)) || name.starts_with("NS_SWIFT_") || name.starts_with("NS_TYPED_")))
+        );
+        if name == "NS_HEADER_AUDIT_BEGIN" || name == "NS_HEADER_AUDIT_END" {
+            //these imply NS_ASSUME_NONNULL
+            output.push(Token::Ident(name.replace("HEADER_AUDIT", "ASSUME_NONNULL")));
+            i += 1;
+            if has_arguments {
+                i = matching_close(&tokens, i) + 1;
+            }
+            continue;
+        }
+        if !is_attribute {
+            output.push(tokens[i].clone());
+            i += 1;
+            continue;
+        }
+        let mut end = i + 1;
+        if has_arguments {
+            end = matching_close(&tokens, i + 1) + 1;
+        }
+        let arguments = &tokens[i + 1..end.min(tokens.len())];
+        let unavailable = match name {
+            "NS_UNAVAILABLE" | "UNAVAILABLE_ATTRIBUTE" | "NS_AUTOMATED_REFCOUNT_UNAVAILABLE" | "__unavailable" => true,
+            "API_UNAVAILABLE" | "NS_UNAVAILABLE_MAC" => arguments.iter().any(|t| t.is_ident("macos") || t.is_ident("macosx")) || name == "NS_UNAVAILABLE_MAC",
+            "__attribute__" => arguments.iter().any(|t| t.is_ident("unavailable")),
+            _ => false,
+        };
+        if unavailable {
+            output.push(Token::Ident(UNAVAILABLE.to_owned()));
+        }
+        i = end;
+    }
+    output
+}
+
+///Index of the bracket that closes the one at `open`, or the last index if there is none
+fn matching_close(tokens: &[Token], open: usize) -> usize {
+    let (open_char, close_char) = match tokens[open] {
+        Token::Punct('(') => ('(', ')'),
+        Token::Punct('{') => ('{', '}'),
+        Token::Punct('<') => ('<', '>'),
+        Token::Punct('[') => ('[', ']'),
+        ref other => panic!("{:?} is not a bracket", other),
+    };
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        if token.is_punct(open_char) {
+            depth += 1;
+        }
+        else if token.is_punct(close_char) {
+            depth -= 1;
+            if depth == 0 {
+                return i;
+            }
+        }
+    }
+    tokens.len() - 1
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    ///Inside `NS_ASSUME_NONNULL_BEGIN`
+    assume_nonnull: bool,
+    ///Enum members seen so far, for evaluating later members
+    enum_values: HashMap<String, i128>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset)
+    }
+    fn next_is_punct(&self, punct: char) -> bool {
+        self.peek().map(|t| t.is_punct(punct)).unwrap_or(false)
+    }
+    fn next_is_ident(&self, ident: &str) -> bool {
+        self.peek().map(|t| t.is_ident(ident)).unwrap_or(false)
+    }
+    fn eat_punct(&mut self, punct: char) -> bool {
+        let matches = self.next_is_punct(punct);
+        if matches { self.pos += 1; }
+        matches
+    }
+    fn ident(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(Token::Ident(i)) => { let i = i.clone(); self.pos += 1; Some(i) }
+            _ => None
+        }
+    }
+    ///Consumes the bracketed group starting at the current token, returning its contents
+    fn group(&mut self) -> Vec<Token> {
+        let close = matching_close(&self.tokens, self.pos);
+        let contents = self.tokens[self.pos + 1..close].to_vec();
+        self.pos = close + 1;
+        contents
+    }
+    ///Skips to the end of the current statement, which is a `;` or a balanced `{}` block.
+    fn skip_statement(&mut self) {
+        while let Some(token) = self.peek() {
+            if token.is_punct(';') {
+                self.pos += 1;
+                return;
+            }
+            if token.is_punct('{') {
+                self.group();
+                self.eat_punct(';');
+                return;
+            }
+            if token.is_punct('(') || token.is_punct('[') {
+                self.group();
+                continue;
+            }
+            if token.is_ident("@end") {
+                return;
+            }
+            self.pos += 1;
+        }
+    }
+    fn skip_unavailable(&mut self) -> bool {
+        let mut unavailable = false;
+        while self.next_is_ident(UNAVAILABLE) {
+            unavailable = true;
+            self.pos += 1;
+        }
+        unavailable
+    }
+
+    fn parse_header(&mut self, header: &mut Header) {
+        while let Some(token) = self.peek().cloned() {
+            match token {
+                Token::Ident(ref i) if i == "NS_ASSUME_NONNULL_BEGIN" => { self.assume_nonnull = true; self.pos += 1; }
+                Token::Ident(ref i) if i == "NS_ASSUME_NONNULL_END" => { self.assume_nonnull = false; self.pos += 1; }
+                Token::Ident(ref i) if i == "@interface" => {
+                    self.pos += 1;
+                    if let Some(interface) = self.parse_interface(false) {
+                        header.classes.push(interface);
+                    }
+                }
+                Token::Ident(ref i) if i == "@protocol" => {
+                    self.pos += 1;
+                    //forward declarations look like `@protocol A, B;`
+                    if matches!(self.peek_at(1), Some(t) if t.is_punct(';') || t.is_punct(',')) {
+                        self.skip_statement();
+                    }
+                    else if let Some(protocol) = self.parse_interface(true) {
+                        header.protocols.push(protocol);
+                    }
+                }
+                Token::Ident(ref i) if i == "typedef" && matches!(self.peek_at(1), Some(Token::Ident(e)) if is_enum_macro(e)) => {
+                    self.pos += 1;
+                    self.parse_enum(header);
+                }
+                Token::Ident(ref i) if is_enum_macro(i) => {
+                    self.parse_enum(header);
+                }
+                Token::Ident(ref i) if i == "@end" || i == UNAVAILABLE => { self.pos += 1; }
+                _ => self.skip_statement(),
+            }
+        }
+    }
+
+    ///Parses an `@interface` or `@protocol` after the keyword, through `@end`.
+    fn parse_interface(&mut self, protocol: bool) -> Option<Interface> {
+        let name = self.ident()?;
+        let mut type_parameters = Vec::new();
+        if !protocol && self.next_is_punct('<') {
+            type_parameters = self.group().into_iter().filter_map(|t| match t { Token::Ident(i) if !QUALIFIERS.contains(&i.as_str()) => Some(i), _ => None }).collect();
+        }
+        let mut category = None;
+        if self.next_is_punct('(') {
+            let contents = self.group();
+            category = Some(contents.into_iter().find_map(|t| match t { Token::Ident(i) => Some(i), _ => None }).unwrap_or_default());
+        }
+        let mut superclass = None;
+        if self.eat_punct(':') {
+            superclass = self.ident();
+            //superclass generics look like protocols, but mention our type parameters
+            if self.next_is_punct('<') {
+                let close = matching_close(&self.tokens, self.pos);
+                let mentions_parameters = self.tokens[self.pos..close].iter().any(|t| matches!(t, Token::Ident(i) if type_parameters.contains(i)));
+                if mentions_parameters {
+                    self.group();
+                }
+            }
+        }
+        let mut protocols = Vec::new();
+        if self.next_is_punct('<') {
+            protocols = self.group().into_iter().filter_map(|t| match t { Token::Ident(i) => Some(i), _ => None }).collect();
+        }
+        self.skip_unavailable();
+        if self.next_is_punct('{') {
+            //ivars
+            self.group();
+        }
+        let mut methods = Vec::new();
+        while let Some(token) = self.peek().cloned() {
+            match token {
+                Token::Ident(ref i) if i == "@end" => { self.pos += 1; break; }
+                Token::Punct(c) if c == '-' || c == '+' => {
+                    self.pos += 1;
+                    if let Some(method) = self.parse_method(c == '+') {
+                        methods.push(method);
+                    }
+                }
+                Token::Ident(ref i) if i == "@property" => {
+                    self.pos += 1;
+                    methods.extend(self.parse_property());
+                }
+                Token::Ident(ref i) if i.starts_with('@') => { self.pos += 1; }
+                Token::Ident(ref i) if i == "NS_ASSUME_NONNULL_BEGIN" => { self.assume_nonnull = true; self.pos += 1; }
+                Token::Ident(ref i) if i == "NS_ASSUME_NONNULL_END" => { self.assume_nonnull = false; self.pos += 1; }
+                _ => self.skip_statement(),
+            }
+        }
+        Some(Interface { name, type_parameters, superclass, category, protocols, methods })
+    }
+
+    ///Parses a method after the `-` or `+`.  Returns `None` for unavailable methods.
+    fn parse_method(&mut self, class_method: bool) -> Option<Method> {
+        let return_type = if self.next_is_punct('(') {
+            let tokens = self.group();
+            parse_type(&tokens, self.assume_nonnull)
+        }
+        else {
+            //C's implicit int, ObjC's implicit id
+            Type::Object { kind: ObjectKind::Id(Vec::new()), nullable: !self.assume_nonnull }
+        };
+        let mut selector = String::new();
+        let mut arguments = Vec::new();
+        let mut variadic = false;
+        loop {
+            let part = if matches!(self.peek(), Some(Token::Ident(i)) if i != UNAVAILABLE) { self.ident().unwrap() } else { String::new() };
+            if !self.eat_punct(':') {
+                selector.push_str(&part);
+                break;
+            }
+            selector.push_str(&part);
+            selector.push(':');
+            let argument_type = if self.next_is_punct('(') {
+                let tokens = self.group();
+                parse_type(&tokens, self.assume_nonnull)
+            }
+            else {
+                Type::Object { kind: ObjectKind::Id(Vec::new()), nullable: !self.assume_nonnull }
+            };
+            let name = self.ident().unwrap_or_else(|| format!("arg{}", arguments.len()));
+            arguments.push((name, argument_type));
+            if self.next_is_punct(',') {
+                //`, ...`
+                variadic = true;
+                while self.eat_punct(',') || self.eat_punct('.') {}
+            }
+            if !matches!(self.peek(), Some(Token::Ident(i)) if i != UNAVAILABLE) && !self.next_is_punct(':') {
+                break;
+            }
+        }
+        let unavailable = self.skip_unavailable();
+        self.skip_statement();
+        if unavailable || selector.is_empty() {
+            return None;
+        }
+        Some(Method { class_method, selector, return_type, arguments, variadic })
+    }
+
+    ///Parses a property after `@property`, returning its accessors
+    fn parse_property(&mut self) -> Vec<Method> {
+        let mut readonly = false;
+        let mut class_property = false;
+        let mut getter = None;
+        let mut setter = None;
+        let mut getter_nullable = None;
+        let mut setter_nullable = None;
+        if self.next_is_punct('(') {
+            let attributes = self.group();
+            let mut i = 0;
+            while i < attributes.len() {
+                if let Token::Ident(attribute) = &attributes[i] {
+                    match attribute.as_str() {
+                        "readonly" => readonly = true,
+                        "class" => class_property = true,
+                        "nullable" | "null_unspecified" => { getter_nullable = Some(true); setter_nullable = Some(true); }
+                        "nonnull" => { getter_nullable = Some(false); setter_nullable = Some(false); }
+                        "null_resettable" => { getter_nullable = Some(false); setter_nullable = Some(true); }
+                        "getter" | "setter" => {
+                            //`getter=name` or `setter=name:`
+                            let mut name = String::new();
+                            let mut j = i + 2;
+                            while j < attributes.len() && !attributes[j].is_punct(',') {
+                                match &attributes[j] {
+                                    Token::Ident(part) => name.push_str(part),
+                                    Token::Punct(':') => name.push(':'),
+                                    _ => {}
+                                }
+                                j += 1;
+                            }
+                            if attribute == "getter" { getter = Some(name) } else { setter = Some(name) }
+                            i = j;
+                        }
+                        _ => {}
+                    }
+                }
+                i += 1;
+            }
+        }
+        let start = self.pos;
+        self.skip_statement();
+        let mut declaration: Vec<Token> = self.tokens[start..self.pos].iter().filter(|t| !t.is_punct(';')).cloned().collect();
+        if declaration.iter().any(|t| t.is_ident(UNAVAILABLE)) {
+            return Vec::new();
+        }
+        //the last identifier is the name; a block property like `void (^handler)(void)` doesn't end in one
+        let name = match declaration.pop() {
+            Some(Token::Ident(name)) => name,
+            _ => return Vec::new()
+        };
+        let getter_type = with_nullability(parse_type(&declaration, self.assume_nonnull), getter_nullable);
+        let setter_type = with_nullability(parse_type(&declaration, self.assume_nonnull), setter_nullable);
+        let mut methods = vec![Method {
+            class_method: class_property,
+            selector: getter.unwrap_or_else(|| name.clone()),
+            return_type: getter_type,
+            arguments: Vec::new(),
+            variadic: false,
+        }];
+        if !readonly {
+            let mut capitalized = name.clone();
+            capitalized[..1].make_ascii_uppercase();
+            methods.push(Method {
+                class_method: class_property,
+                selector: setter.unwrap_or_else(|| format!("set{}:", capitalized)),
+                return_type: Type::Void,
+                arguments: vec![(name, setter_type)],
+                variadic: false,
+            });
+        }
+        methods
+    }
+
+    ///Parses `NS_ENUM(Type, Name) { ... };` starting at the macro name
+    fn parse_enum(&mut self, header: &mut Header) {
+        let options = self.next_is_ident("NS_OPTIONS");
+        self.pos += 1;
+        if !self.next_is_punct('(') {
+            self.skip_statement();
+            return;
+        }
+        let arguments = self.group();
+        let mut idents = arguments.split(|t| t.is_punct(',')).map(|part| part.iter().filter_map(|t| match t { Token::Ident(i) => Some(i.as_str()), _ => None }).collect::<Vec<_>>().join(" "));
+        let raw_type = idents.next().unwrap_or_default();
+        let name = idents.next().unwrap_or_default();
+        //NS_ERROR_ENUM(domain, name) has no type
+        let (raw_type, name) = if name.is_empty() || self.tokens[self.pos - 2].is_ident("NS_ERROR_ENUM") { ("NSInteger".to_owned(), name) } else { (raw_type, name) };
+        self.skip_unavailable();
+        if !self.next_is_punct('{') {
+            self.skip_statement();
+            return;
+        }
+        let body = self.group();
+        self.skip_statement();
+        let mut members = Vec::new();
+        let mut previous: Option<i128> = Some(-1);
+        for member in body.split(|t| t.is_punct(',')) {
+            let member: Vec<Token> = member.iter().filter(|t| !t.is_ident(UNAVAILABLE)).cloned().collect();
+            let member_name = match member.first() {
+                Some(Token::Ident(i)) => i.clone(),
+                _ => continue,
+            };
+            let value = match member.iter().position(|t| t.is_punct('=')) {
+                Some(equals) => {
+                    let expression = &member[equals + 1..];
+                    match evaluate(expression, &self.enum_values) {
+                        Some(value) => EnumValue::Evaluated(value),
+                        None => EnumValue::Unevaluated(expression_text(expression)),
+                    }
+                }
+                None => match previous {
+                    Some(p) => EnumValue::Evaluated(p + 1),
+                    None => EnumValue::Unevaluated(format!("{} + 1", members.last().map(|m: &EnumMember| m.name.clone()).unwrap_or_default())),
+                }
+            };
+            previous = match value {
+                EnumValue::Evaluated(v) => { self.enum_values.insert(member_name.clone(), v); Some(v) }
+                EnumValue::Unevaluated(_) => None,
+            };
+            members.push(EnumMember { name: member_name, value: Some(value) });
+        }
+        header.enums.push(Enum { name, raw_type, options, members });
+    }
+}
+
+fn is_enum_macro(name: &str) -> bool {
+    matches!(name, "NS_ENUM" | "NS_OPTIONS" | "NS_CLOSED_ENUM" | "NS_ERROR_ENUM")
+}
+
+fn with_nullability(t: Type, nullable: Option<bool>) -> Type {
+    match (t, nullable) {
+        (Type::Object { kind, .. }, Some(nullable)) => Type::Object { kind, nullable },
+        (t, _) => t
+    }
+}
+
+fn expression_text(tokens: &[Token]) -> String {
+    tokens.iter().map(|t| match t {
+        Token::Ident(i) | Token::Number(i) => i.clone(),
+        Token::Punct(p) => p.to_string(),
+    }).collect::<Vec<_>>().join(" ")
+}
+
+///Parses the tokens of a type, like the contents of `(NSString * _Nullable)`.
+pub(crate) fn parse_type(tokens: &[Token], assume_nonnull: bool) -> Type {
+    let written = expression_text(tokens);
+    if tokens.iter().any(|t| t.is_punct('^') || t.is_punct('(') || t.is_punct('[')) {
+        return Type::Unsupported(written);
+    }
+    let mut words = Vec::new();
+    let mut protocols = Vec::new();
+    let mut stars = 0;
+    //nullability of the outermost pointer, which is written last
+    let mut nullable = None;
+    let mut prefix_nullable = None;
+    let mut mutable = true;
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Punct('<') => {
+                let close = matching_close(tokens, i);
+                if words.last().map(|w: &String| w == "id").unwrap_or(false) {
+                    protocols = tokens[i + 1..close].iter().filter_map(|t| match t { Token::Ident(i) => Some(i.clone()), _ => None }).collect();
+                }
+                i = close;
+            }
+            Token::Punct('*') => { stars += 1; nullable = None; }
+            Token::Ident(word) => match word.as_str() {
+                //written before the type, like `nullable NSString *`
+                "nullable" | "null_unspecified" => prefix_nullable = Some(true),
+                "nonnull" => prefix_nullable = Some(false),
+                "_Nullable" | "__nullable" | "_Null_unspecified" | "__null_unspecified" => nullable = Some(true),
+                "_Nonnull" | "__nonnull" => nullable = Some(false),
+                "const" => if stars == 0 { mutable = false },
+                "in" | "out" | "inout" | "bycopy" | "byref" | "oneway" | "__kindof" | "__strong" | "__weak" | "__autoreleasing" | "__unsafe_unretained" | "struct" | "enum" => {}
+                _ => words.push(word.clone()),
+            },
+            _ => return Type::Unsupported(written),
+        }
+        i += 1;
+    }
+    let nullable = nullable.or(prefix_nullable).unwrap_or(!assume_nonnull);
+    let base = words.join(" ");
+    match (base.as_str(), stars) {
+        ("void", 0) => Type::Void,
+        ("instancetype", 0) => Type::Object { kind: ObjectKind::Instancetype, nullable },
+        ("id", 0) => Type::Object { kind: ObjectKind::Id(protocols), nullable },
+        ("void", 1) => Type::VoidPointer { mutable },
+        ("NSError", 2) => Type::ErrorOut,
+        ("", _) => Type::Unsupported(written),
+        (base, 0) => Type::Value(base.to_owned()),
+        (base, 1) if base.starts_with(|c: char| c.is_ascii_uppercase()) && words.len() == 1 => Type::Object { kind: ObjectKind::Class(base.to_owned()), nullable },
+        _ => Type::Unsupported(written),
+    }
+}
+
+///Evaluates an integer constant expression, like `1 << 3` or `FooA | FooB`.
+fn evaluate(tokens: &[Token], names: &HashMap<String, i128>) -> Option<i128> {
+    let mut evaluator = Evaluator { tokens, pos: 0, names };
+    let value = evaluator.binary(0)?;
+    if evaluator.pos == tokens.len() { Some(value) } else { None }
+}
+
+struct Evaluator<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    names: &'a HashMap<String, i128>,
+}
+impl<'a> Evaluator<'a> {
+    ///Operators and their precedence, loosest first
+    const OPERATORS: &'static [&'static [&'static str]] = &[&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+
+    fn operator(&self) -> Option<(&'static str, usize)> {
+        let first = match self.tokens.get(self.pos) { Some(Token::Punct(p)) => *p, _ => return None };
+        let second = match self.tokens.get(self.pos + 1) { Some(Token::Punct(p)) => Some(*p), _ => None };
+        for (level, operators) in Self::OPERATORS.iter().enumerate() {
+            for operator in operators.iter() {
+                let mut chars = operator.chars();
+                let matches = chars.next() == Some(first) && chars.next().map(|c| second == Some(c)).unwrap_or(true);
+                if matches {
+                    return Some((operator, level));
+                }
+            }
+        }
+        None
+    }
+
+    fn binary(&mut self, level: usize) -> Option<i128> {
+        if level == Self::OPERATORS.len() {
+            return self.unary();
+        }
+        let mut value = self.binary(level + 1)?;
+        while let Some((operator, operator_level)) = self.operator() {
+            if operator_level != level {
+                break;
+            }
+            self.pos += operator.len();
+            let rhs = self.binary(level + 1)?;
+            value = match operator {
+                "|" => value | rhs,
+                "^" => value ^ rhs,
+                "&" => value & rhs,
+                "<<" => value.checked_shl(u32::try_from(rhs).ok()?)?,
+                ">>" => value.checked_shr(u32::try_from(rhs).ok()?)?,
+                "+" => value.checked_add(rhs)?,
+                "-" => value.checked_sub(rhs)?,
+                "*" => value.checked_mul(rhs)?,
+                "/" => value.checked_div(rhs)?,
+                "%" => value.checked_rem(rhs)?,
+                _ => unreachable!(),
+            };
+        }
+        Some(value)
+    }
+
+    fn unary(&mut self) -> Option<i128> {
+        let token = self.tokens.get(self.pos)?.clone();
+        self.pos += 1;
+        match token {
+            Token::Punct('-') => Some(-self.unary()?),
+            Token::Punct('+') => self.unary(),
+            Token::Punct('~') => Some(!self.unary()?),
+            Token::Punct('(') => {
+                //casts like `(NSUInteger)1` are not supported, but grouping is
+                let value = self.binary(0)?;
+                if matches!(self.tokens.get(self.pos), Some(Token::Punct(')'))) { self.pos += 1; Some(value) } else { None }
+            }
+            Token::Number(number) => parse_number(&number),
+            Token::Ident(name) => self.names.get(&name).copied(),
+            _ => None,
+        }
+    }
+}
+
+///Parses a C integer literal like `0x10UL`
+fn parse_number(number: &str) -> Option<i128> {
+    let digits = number.trim_end_matches(['u', 'U', 'l', 'L']);
+    if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i128::from_str_radix(hex, 16).ok()
+    }
+    else if digits.len() > 1 && digits.starts_with('0') {
+        i128::from_str_radix(&digits[1..], 8).ok()
+    }
+    else {
+        digits.parse().ok()
+    }
+}
+
+#[test] fn parse_interface() {
+    let header = parse(r#"
+NS_ASSUME_NONNULL_BEGIN
+API_AVAILABLE(macos(10.11), ios(8.0))
+@interface MTLFoo<ObjectType> : NSArray<ObjectType> <NSCopying, NSSecureCoding>
+{
+    int _ivar;
+}
+@property (nonatomic, readonly, copy) NSString *name;
+@property (nullable, nonatomic, getter=isEnabled) BOOL enabled;
+@property (class, readonly) MTLFoo *sharedFoo;
+- (instancetype)init NS_UNAVAILABLE;
+- (nullable instancetype)initWithName:(NSString *)name error:(NSError **)error NS_DESIGNATED_INITIALIZER;
++ (void)doSomething:(NSUInteger)count withObject:(nullable id<NSCopying>)object API_DEPRECATED("", macos(10.0, 10.1));
+- (void)run:(void (^)(void))block;
+@end
+NS_ASSUME_NONNULL_END
+"#);
+    assert_eq!(header.classes.len(), 1);
+    let class = &header.classes[0];
+    assert_eq!(class.name, "MTLFoo");
+    assert_eq!(class.superclass.as_deref(), Some("NSArray"));
+    assert_eq!(class.protocols, vec!["NSCopying", "NSSecureCoding"]);
+    let selectors: Vec<&str> = class.methods.iter().map(|m| m.selector.as_str()).collect();
+    assert_eq!(selectors, vec!["name", "isEnabled", "setEnabled:", "sharedFoo", "initWithName:error:", "doSomething:withObject:", "run:"]);
+    assert_eq!(class.methods[0].return_type, Type::Object { kind: ObjectKind::Class("NSString".to_owned()), nullable: false });
+    assert_eq!(class.methods[2].arguments, vec![("enabled".to_owned(), Type::Value("BOOL".to_owned()))]);
+    assert!(class.methods[3].class_method);
+    assert_eq!(class.methods[4].return_type, Type::Object { kind: ObjectKind::Instancetype, nullable: true });
+    assert_eq!(class.methods[4].arguments[1].1, Type::ErrorOut);
+    assert_eq!(class.methods[5].arguments[1].1, Type::Object { kind: ObjectKind::Id(vec!["NSCopying".to_owned()]), nullable: true });
+    assert!(matches!(class.methods[6].arguments[0].1, Type::Unsupported(_)));
+}
+
+#[test] fn parse_enums() {
+    let header = parse(r#"
+typedef NS_ENUM(NSUInteger, MTLPixelFormat) {
+    MTLPixelFormatInvalid = 0,
+    MTLPixelFormatA8Unorm,
+    MTLPixelFormatR8Unorm = 10,
+    MTLPixelFormatR8Unorm_sRGB API_AVAILABLE(macos(11.0)),
+} API_AVAILABLE(macos(10.11));
+typedef NS_OPTIONS(NSUInteger, MTLResourceOptions) {
+    MTLResourceCPUCacheModeDefaultCache = 0UL << 0,
+    MTLResourceStorageModePrivate = 2UL << 4,
+    MTLResourceOptionCPUCacheModeDefault = MTLResourceCPUCacheModeDefaultCache | MTLResourceStorageModePrivate,
+    MTLResourceMax = NSUIntegerMax,
+};
+"#);
+    assert_eq!(header.enums.len(), 2);
+    let values: Vec<Option<EnumValue>> = header.enums[0].members.iter().map(|m| m.value.clone()).collect();
+    assert_eq!(values, vec![Some(EnumValue::Evaluated(0)), Some(EnumValue::Evaluated(1)), Some(EnumValue::Evaluated(10)), Some(EnumValue::Evaluated(11))]);
+    assert_eq!(header.enums[0].raw_type, "NSUInteger");
+    assert!(header.enums[1].options);
+    assert_eq!(header.enums[1].members[1].value, Some(EnumValue::Evaluated(32)));
+    assert_eq!(header.enums[1].members[2].value, Some(EnumValue::Evaluated(32)));
+    assert_eq!(header.enums[1].members[3].value, Some(EnumValue::Unevaluated("NSUIntegerMax".to_owned())));
+}
@@ -1,7 +1,21 @@
 extern crate cc;
 
 fn main() {
-    cc::Build::new()
-        .file("src/hard-exception.m")
-        .compile("libexception.a");
-}
\ No newline at end of file
+    //The mock runtime has no ObjC exceptions to catch, and the machine may not have an ObjC compiler
+    if std::env::var_os("CARGO_FEATURE_MOCK_RUNTIME").is_some() {
+        return;
+    }
+    let mut build = cc::Build::new();
+    build.file("src/hard-exception.m");
+    if std::env::var_os("CARGO_FEATURE_GNUSTEP").is_some() {
+        //GNUstep headers are not on the default search path; gnustep-config knows where they are
+        if let Ok(output) = std::process::Command::new("gnustep-config").arg("--objc-flags").output() {
+            for flag in String::from_utf8_lossy(&output.stdout).split_whitespace() {
+                build.flag(flag);
+            }
+        }
+        build.flag("-fobjc-runtime=gnustep-2.0");
+        build.flag("-fobjc-exceptions");
+    }
+    build.compile("libexception.a");
+}
@@ -6,6 +6,7 @@
 
 
 
+#[cfg(not(any(feature="gnustep", feature="mock-runtime")))]
 pub fn implement_class(rust_name: &str,class_name: &str) -> String {
     format!(r#"
         impl ::objr::bindings::ObjcClass for {RUST_NAME} {{
@@ -27,4 +28,18 @@ pub fn implement_class(rust_name: &str,class_name: &str) -> String {
             }}
         }}
     "#, RUST_NAME=rust_name,CLASS_NAME=class_name)
-}
\ No newline at end of file
+}
+
+///Without dyld (libobjc2, mock-runtime) nothing fixes up our class reference, so we look it up once and cache it.
+#[cfg(any(feature="gnustep", feature="mock-runtime"))]
+pub fn implement_class(rust_name: &str,class_name: &str) -> String {
+    format!(r#"
+        impl ::objr::bindings::ObjcClass for {RUST_NAME} {{
+            fn class() -> &'static ::objr::bindings::Class<{RUST_NAME}> {{
+                static CLASS: ::core::sync::atomic::AtomicPtr<core::ffi::c_void> = ::core::sync::atomic::AtomicPtr::new(::core::ptr::null_mut());
+                let any_class = ::objr::bindings::AnyClass::_cached(&CLASS, b"{CLASS_NAME}\0");
+                unsafe{{ &*(any_class as *const ::objr::bindings::AnyClass as *const ::objr::bindings::Class<{RUST_NAME}>) }}
+            }}
+        }}
+    "#, RUST_NAME=rust_name,CLASS_NAME=class_name)
+}
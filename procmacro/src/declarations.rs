@@ -12,9 +12,7 @@ struct SelectorPart(String);
 struct ArgumentName(String);
 
 ///Taken from https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/ObjCRuntimeGuide/Articles/ocrtTypeEncodings.html#//apple_ref/doc/uid/TP40008048-CH100
-#[derive(Debug)]
-//ignore the fact that some of these cases are unused
-#[allow(dead_code)]
+#[derive(Debug,Clone)]
 enum ParsedType {
     Char,
     Int,
@@ -33,17 +31,110 @@ enum ParsedType {
     CharStar,
     Object,
     Sel,
-    //These types are included but may not be correctly parsed
-    Array,
-    Structure,
-    Union,
-    Bitfield,
+    ///`[count type]`
+    Array(usize, Box<ParsedType>),
+    ///`{name=fields}`
+    Structure(String, Vec<ParsedType>),
+    ///`(name=fields)`
+    Union(String, Vec<ParsedType>),
+    ///A bitfield of this many bits
+    Bitfield(u8),
     Pointer(Box<ParsedType>),
     Class,
+    ///A type declared in Rust, such as a `#[derive(ObjcEncode)]` struct.
+    ///
+    /// The procmacro doesn't know its encoding or size, so these are worked out at compile time.
+    Rust(String),
     Unknown,
-    //"Special" types, not part of the standard, but implemented for convenience
-    CGRect,
-    CGSize,
+}
+
+///Structs from the system frameworks, by the names they're usually spelled.
+///
+/// These are the 64-bit definitions, where `CGFloat` is `double` and `NSUInteger` is `unsigned long`.
+fn known_structure(name: &str) -> Option<ParsedType> {
+    use ParsedType::*;
+    let structure = |name: &str, fields: Vec<ParsedType>| Structure(name.to_owned(), fields);
+    let doubles = |name: &str, count: usize| structure(name, vec![Double; count]);
+    let ulongs = |name: &str, count: usize| structure(name, vec![ULong; count]);
+    Some(match name {
+        "NSRange" | "_NSRange" => ulongs("_NSRange", 2),
+        "CGPoint" | "NSPoint" => doubles("CGPoint", 2),
+        "CGSize" | "NSSize" => doubles("CGSize", 2),
+        "CGVector" => doubles("CGVector", 2),
+        "CGRect" | "NSRect" => structure("CGRect", vec![doubles("CGPoint", 2), doubles("CGSize", 2)]),
+        "CGAffineTransform" => doubles("CGAffineTransform", 6),
+        "NSEdgeInsets" => doubles("NSEdgeInsets", 4),
+        "NSDirectionalEdgeInsets" => doubles("NSDirectionalEdgeInsets", 4),
+        "UIEdgeInsets" => doubles("UIEdgeInsets", 4),
+        "MTLClearColor" => doubles("MTLClearColor", 4),
+        "MTLViewport" => doubles("MTLViewport", 6),
+        "MTLOrigin" => ulongs("MTLOrigin", 3),
+        "MTLSize" => ulongs("MTLSize", 3),
+        "MTLRegion" => structure("MTLRegion", vec![ulongs("MTLOrigin", 3), ulongs("MTLSize", 3)]),
+        "MTLScissorRect" => ulongs("MTLScissorRect", 4),
+        "MTLSamplePosition" => structure("MTLSamplePosition", vec![Float; 2]),
+        _ => return None
+    })
+}
+
+///Splits C field declarations like `int a; struct { char b; } c; unsigned d : 3;` at the top-level `;`.
+fn split_fields(body: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, char) in body.char_indices() {
+        match char {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ';' if depth == 0 => {
+                fields.push(body[start..index].trim());
+                start = index + 1;
+            }
+            _ => ()
+        }
+    }
+    fields.push(body[start..].trim());
+    fields.retain(|f| !f.is_empty());
+    fields
+}
+
+///Parses a C field declaration, like `int a`, `char name[16]` or `unsigned int flag : 1`.
+fn parse_field(field: &str) -> ParsedType {
+    //colons inside a nested struct, or in a path like `foo::Bar`, don't make a bitfield
+    let tail_start = field.rfind('}').map(|index| index + 1).unwrap_or(0);
+    if let Some((_, width)) = field[tail_start..].rsplit_once(':').filter(|(declaration, _)| !declaration.ends_with(':')) {
+        return match width.trim().parse() {
+            Ok(width) => ParsedType::Bitfield(width),
+            Err(_) => ParsedType::Unknown,
+        }
+    }
+    //array suffixes, innermost last
+    let mut declaration = field;
+    let mut counts = Vec::new();
+    while let Some(prefix) = declaration.strip_suffix(']') {
+        match prefix.rsplit_once('[').map(|(prefix, count)| (prefix, count.trim().parse::<usize>())) {
+            Some((prefix, Ok(count))) => {
+                counts.push(count);
+                declaration = prefix.trim_end();
+            }
+            _ => return ParsedType::Unknown,
+        }
+    }
+    //the field name is the trailing identifier
+    let name_start = declaration.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_').len();
+    let mut parsed = ParsedType::parse(&declaration[..name_start]);
+    for count in counts {
+        parsed = ParsedType::Array(count, Box::new(parsed));
+    }
+    parsed
+}
+
+///Rounds `value` up to a multiple of `alignment`
+fn round_up(value: usize, alignment: usize) -> usize {
+    match value % alignment {
+        0 => value,
+        remainder => value + alignment - remainder,
+    }
 }
 
 impl ParsedType {
@@ -53,12 +144,13 @@ impl ParsedType {
             ParsedType::Char => "c".to_owned(),
             ParsedType::Int => "i".to_owned(),
             ParsedType::Short => "s".to_owned(),
-            ParsedType::Long => "l".to_owned(),
+            //On 64-bit Apple platforms, `long` is encoded like `long long`
+            ParsedType::Long => "q".to_owned(),
             ParsedType::LongLong => "q".to_owned(),
             ParsedType::UChar => "C".to_owned(),
             ParsedType::UInt => "I".to_owned(),
             ParsedType::UShort => "S".to_owned(),
-            ParsedType::ULong => "L".to_owned(),
+            ParsedType::ULong => "Q".to_owned(),
             ParsedType::ULongLong => "Q".to_owned(),
             ParsedType::Float => "f".to_owned(),
             ParsedType::Double => "d".to_owned(),
@@ -67,99 +159,153 @@ impl ParsedType {
             ParsedType::CharStar => "*".to_owned(),
             ParsedType::Object => "@".to_owned(),
             ParsedType::Sel => ":".to_owned(),
-            ParsedType::Array => "[v]".to_owned(), //treated as array to void
-            ParsedType::Structure => "{n=v}".to_owned(), //treated as struct of void
-            ParsedType::Union => "(n=v)".to_owned(), //union of void
-            ParsedType::Bitfield => "b0".to_owned(), //0 bits
+            ParsedType::Array(count, element) => format!("[{}{}]",count,element.type_encoding()),
+            ParsedType::Structure(name, fields) => {
+                format!("{{{}={}}}",name,fields.iter().map(|f| f.type_encoding()).collect::<String>())
+            }
+            ParsedType::Union(name, fields) => {
+                format!("({}={})",name,fields.iter().map(|f| f.type_encoding()).collect::<String>())
+            }
+            ParsedType::Bitfield(width) => format!("b{}",width),
             ParsedType::Pointer(t) => {
                 let mut s = "^".to_owned();
                 s.push_str(&t.type_encoding());
                 s
             }
-            ParsedType::Class => "@".to_owned(),
+            ParsedType::Class => "#".to_owned(),
+            //The fields are only known to the Rust compiler.  This is how clang encodes a struct without its fields.
+            ParsedType::Rust(name) => format!("{{{}}}",name),
             ParsedType::Unknown => "?".to_owned(),
-            ParsedType::CGRect => "{CGRect={CGPoint=dd}{CGSize=dd}}".to_owned(),
-            ParsedType::CGSize => "{CGSize=dd}".to_owned(),
         }
     }
 
-    //This is declared as `Result` so we can make it a const fn.  However,
-    //the err is basically a panic.
-    const fn magic_size(&self) -> Result<u8,()> {
-
-        /*On x64 anyway this appears to be the size of the type in bytes, rounded up to the nearest word.
-
-        e.g. char is 1 byte, but we round up to 4
-        int is 4 bytes and also rounded up to 4 etc.
+    ///The C `sizeof` and `alignof` the type, if we know it
+    fn layout(&self) -> Option<(usize, usize)> {
+        match self {
+            ParsedType::Char | ParsedType::UChar | ParsedType::Bool => Some((1, 1)),
+            ParsedType::Short | ParsedType::UShort => Some((2, 2)),
+            ParsedType::Int | ParsedType::UInt | ParsedType::Float => Some((4, 4)),
+            ParsedType::Long | ParsedType::LongLong | ParsedType::ULong | ParsedType::ULongLong | ParsedType::Double => Some((8, 8)),
+            ParsedType::Void => Some((0, 1)),
+            ParsedType::CharStar | ParsedType::Object | ParsedType::Sel | ParsedType::Pointer(_) | ParsedType::Class => Some((8, 8)),
+            ParsedType::Array(count, element) => element.layout().map(|(size, alignment)| (size * count, alignment)),
+            ParsedType::Structure(_, fields) => {
+                /*Bitfields are packed into `unsigned int` storage units.  A bitfield that doesn't fit in what's left
+                of the current unit starts a new one.
+                 */
+                let mut bits = 0;
+                let mut alignment = 1;
+                for field in fields {
+                    match field {
+                        ParsedType::Bitfield(width) => {
+                            let width = *width as usize;
+                            if width == 0 || bits % 32 + width > 32 {
+                                bits = round_up(bits, 32);
+                            }
+                            bits += width;
+                            alignment = alignment.max(4);
+                        }
+                        other => {
+                            let (size, field_alignment) = other.layout()?;
+                            bits = round_up(round_up(bits, 8) / 8, field_alignment) * 8 + size * 8;
+                            alignment = alignment.max(field_alignment);
+                        }
+                    }
+                }
+                Some((round_up(round_up(bits, 8) / 8, alignment), alignment))
+            }
+            ParsedType::Union(_, fields) => {
+                let mut size = 0;
+                let mut alignment = 1;
+                for field in fields {
+                    let (field_size, field_alignment) = match field {
+                        ParsedType::Bitfield(width) => (round_up(*width as usize, 8) / 8, 4),
+                        other => other.layout()?,
+                    };
+                    size = size.max(field_size);
+                    alignment = alignment.max(field_alignment);
+                }
+                Some((round_up(size, alignment), alignment))
+            }
+            ParsedType::Bitfield(_) => None,
+            ParsedType::Rust(_) => None,
+            ParsedType::Unknown => None,
+        }
+    }
 
-        I assume this is some alignment or memory thing either part of C or objc, not sure which.
+    ///The C `sizeof` the type, if we know it
+    fn size(&self) -> Option<usize> {
+        self.layout().map(|(size, _)| size)
+    }
 
-        Not handling the incomplete types since it seems like more work than it's worth.
-         */
+    ///Space the type takes up in the argument frame of a method type encoding.
+    ///
+    /// This is the size of the type, except that types smaller than `int` are rounded up to `int`.
+    /// `Err` if it isn't a valid argument type, or we don't know its size.
+    fn frame_size(&self) -> Result<usize,String> {
         match self {
-            ParsedType::Char => Ok(4),
-            ParsedType::Int => Ok(4),
-            ParsedType::Short => Ok(4),
-            ParsedType::Long => Ok(8),
-            ParsedType::LongLong => Ok(8),
-            ParsedType::UChar => Ok(4),
-            ParsedType::UInt => Ok(4),
-            ParsedType::UShort => Ok(4),
-            ParsedType::ULong => Ok(8),
-            ParsedType::ULongLong => Ok(8),
-            ParsedType::Float => Ok(4),
-            ParsedType::Double => Ok(8),
-            ParsedType::Bool => Ok(4),
-            ParsedType::Void => Err(()),
-            ParsedType::CharStar => Ok(8),
-            ParsedType::Object => Ok(8),
-            ParsedType::Sel => Ok(8),
-            ParsedType::Array => Err(()),
-            ParsedType::Structure => Err(()),
-            ParsedType::Union => Err(()),
-            ParsedType::Bitfield => Err(()),
-            ParsedType::Pointer(_) => Ok(8),
-            ParsedType::Class => Ok(8),
-            ParsedType::Unknown => Err(()),
-            ParsedType::CGRect => Ok(32),
-            ParsedType::CGSize => Ok(16),
+            ParsedType::Void => Err("`void` is not a valid argument type".to_owned()),
+            other => other.size().map(|size| size.max(4)).ok_or_else(|| format!("Unknown size for {}",other.type_encoding())),
         }
     }
+
     fn parse(str: &str) -> Self {
+        let str = str.trim();
+        let str = str.strip_prefix("const ").map(|s| s.trim_start()).unwrap_or(str);
+        //struct or union declared inline, like `struct Foo { int a; float b; }`
+        if let (Some(open), Some(body)) = (str.find('{'), str.strip_suffix('}')) {
+            let (keyword, name) = str[..open].trim().split_once(' ').map(|(k, n)| (k, n.trim())).unwrap_or((str[..open].trim(), "?"));
+            let fields = split_fields(&body[open + 1..]).into_iter().map(parse_field).collect();
+            let name = if name.is_empty() { "?" } else { name }.to_owned();
+            return match keyword {
+                "struct" => ParsedType::Structure(name, fields),
+                "union" => ParsedType::Union(name, fields),
+                _ => ParsedType::Unknown,
+            }
+        }
+        if let Some(name) = str.strip_prefix("struct ").or_else(|| str.strip_prefix("union ")) {
+            let name = name.trim();
+            return known_structure(name).unwrap_or_else(|| ParsedType::Rust(name.to_owned()));
+        }
+        //arrays, like `int[4]`
+        if str.ends_with(']') {
+            return parse_field(str);
+        }
+        if let Some(structure) = known_structure(str) {
+            return structure;
+        }
         match str {
-            "CGSize" => ParsedType::CGSize,
-            "NSSize" => ParsedType::CGSize,
-            "CGRect" => ParsedType::CGRect,
-            "NSRect" => ParsedType::CGRect,
-            "id" => ParsedType::Object,
-            "char" => ParsedType::Char,
-            "int" => ParsedType::Int,
-            "short" => ParsedType::Short,
-            "long" => ParsedType::Long,
-            "long long" => ParsedType::LongLong,
-            "unsigned char" => ParsedType::UChar,
-            "unsigned int" => ParsedType::UInt,
-            "unsigned short" => ParsedType::UShort,
-            "unsigned long"=>ParsedType::ULong,
-            "unsigned long long"=>ParsedType::ULongLong,
-            "float"=>ParsedType::Float,
-            "double"=>ParsedType::Double,
-            "bool"=>ParsedType::Bool,
-            "BOOL"=>ParsedType::Bool,
-            "void"=>ParsedType::Void,
-            "char*"=>ParsedType::CharStar,
-            "char *"=>ParsedType::CharStar,
-            str if str.ends_with("*") => {
+            "id" | "instancetype" => ParsedType::Object,
+            str if str.starts_with("id<") || str.starts_with("id <") => ParsedType::Object,
+            "Class" => ParsedType::Class,
+            "char" | "signed char" | "int8_t" => ParsedType::Char,
+            "int" | "signed" | "signed int" | "int32_t" => ParsedType::Int,
+            "short" | "short int" | "int16_t" => ParsedType::Short,
+            "long" | "long int" => ParsedType::Long,
+            "long long" | "int64_t" | "NSInteger" | "ssize_t" => ParsedType::LongLong,
+            "unsigned char" | "uint8_t" => ParsedType::UChar,
+            "unsigned int" | "unsigned" | "uint32_t" => ParsedType::UInt,
+            "unsigned short" | "uint16_t" | "unichar" => ParsedType::UShort,
+            "unsigned long" | "size_t" => ParsedType::ULong,
+            "unsigned long long" | "uint64_t" | "NSUInteger" => ParsedType::ULongLong,
+            "float" => ParsedType::Float,
+            "double" | "CGFloat" | "NSTimeInterval" | "CFTimeInterval" => ParsedType::Double,
+            "bool" | "BOOL" | "_Bool" => ParsedType::Bool,
+            "void" => ParsedType::Void,
+            "char*" | "char *" => ParsedType::CharStar,
+            str if str.ends_with('*') => {
                 //parse the part before the pointer
-                let prior_to_ptr = str.split_at(str.len() - 2);
-                let final_result = match ParsedType::parse(prior_to_ptr.0) {
-                    ParsedType::Unknown => ParsedType::Object,
+                let prior_to_ptr = str[..str.len() - 1].trim_end();
+                match ParsedType::parse(prior_to_ptr) {
+                    //pointers to types we don't know are assumed to be objects, like `NSString *`
+                    ParsedType::Unknown | ParsedType::Rust(_) => ParsedType::Object,
                     //valid types are pointers to the type
                     other => ParsedType::Pointer(Box::new(other))
-                };
-                final_result
+                }
             },
-            "SEL"=>ParsedType::Sel,
+            "SEL" => ParsedType::Sel,
+            //something like `MyStruct`, which must be a Rust type implementing `Encode`
+            str if !str.is_empty() && !str.starts_with(|c: char| c.is_ascii_digit()) && str.chars().all(|c| c.is_alphanumeric() || c == '_' || c == ':') => ParsedType::Rust(str.to_owned()),
             _ => ParsedType::Unknown
         }
     }
@@ -188,7 +334,8 @@ enum PartType {
 
 
 struct ParsedDeclaration {
-    //todo: methodkind
+    ///`+` rather than `-`
+    class_method: bool,
     return_type: Type,
     //All methods are required to have at least 1 part.
     //To model this in the typesystem, we store the first part inline
@@ -217,42 +364,51 @@ impl ParsedDeclaration {
         s
     }
 
-    fn type_str(&self) -> String {
-        let mut user_args = Vec::new();
+    ///Types of the arguments after `self` and `_cmd`, as written
+    fn argument_types(&self) -> Vec<&str> {
+        let mut types = Vec::new();
         match &self.first_part {
             PartType::LoneSelector(_) => {}
             PartType::Argument(arg) => {
-                user_args.push(ParsedType::parse(&arg.argument_type.0));
+                types.push(arg.argument_type.0.as_str());
             }
         }
         for arg in &self.next_parts {
-            user_args.push(ParsedType::parse(&arg.argument_type.0));
+            types.push(arg.argument_type.0.as_str());
         }
+        types
+    }
+
+    fn type_str(&self) -> Result<String,String> {
+        let user_args: Vec<ParsedType> = self.argument_types().into_iter().map(ParsedType::parse).collect();
         let return_type = ParsedType::parse(&self.return_type.0);
+        if let Some(ParsedType::Rust(name)) = user_args.iter().chain(std::iter::once(&return_type)).find(|t| matches!(t, ParsedType::Rust(_))) {
+            return Err(format!("`{}` is not a known ObjC type, so its encoding is only known to the Rust compiler",name));
+        }
         //output starts with return type
         let mut output = return_type.type_encoding();
-        //Next phrase is the entire size
-        //calculate the arg size
-        let user_arg_size = user_args.iter().fold(0, |a, b| a + b.magic_size().expect("magic_size"));
-        let entire_size = user_arg_size
-            + ParsedType::Object.magic_size().expect("magic_size") //implicit self arg
-        + ParsedType::Sel.magic_size().expect("magic_size"); //implicit SEL arg
-        //return type seems not to be included in this value.
+        //Next phrase is the entire size of the arguments, including the implicit self and SEL.
+        //The return type is not included in this value.
+        let self_size = ParsedType::Object.frame_size()?;
+        let mut entire_size = self_size + ParsedType::Sel.frame_size()?;
+        for arg in &user_args {
+            entire_size += arg.frame_size()?;
+        }
 
         //this consists of
         //0.  entire_size
-        //1. @0 => seems to indicate the self arg goes into some 0 slot
-        //2. :{} => sel goes into slot 8
-        output.push_str(&format!("{}@0:{}",entire_size,ParsedType::Object.magic_size().expect("magic_size")));
+        //1. @0 => the self arg is at offset 0
+        //2. :{} => sel is at offset 8
+        output.push_str(&format!("{}@0:{}",entire_size,self_size));
 
-        let mut slot = ParsedType::Object.magic_size().expect("magic_size") + ParsedType::Sel.magic_size().expect("magic_size");
+        //each argument is followed by its offset in the frame
+        let mut slot = self_size + ParsedType::Sel.frame_size()?;
         for arg in user_args {
             output.push_str(&arg.type_encoding());
             output.push_str(&format!("{}",slot));
-            //advance slot for next time?
-            slot += arg.magic_size().expect("magic_size");
+            slot += arg.frame_size()?;
         }
-        output
+        Ok(output)
     }
 }
 
@@ -264,6 +420,7 @@ impl ParsedDeclaration {
         let mut state = DeclarationParserState::Initial;
         let mut string_iter = str.chars();
         let mut return_type = None;
+        let mut class_method = false;
 
         let mut current_partial_argument_type = None;
         let mut current_partial_selector_part = None;
@@ -279,11 +436,12 @@ impl ParsedDeclaration {
                     if char == ' ' {
                         state = DeclarationParserState::Initial; //continue
                     }
-                    else if char == '-' {
+                    else if char == '-' || char == '+' {
+                        class_method = char == '+';
                         state = DeclarationParserState::ReturnType(Type(String::with_capacity(10)));
                     }
                     else {
-                        return Err(format!("expected `-``near {:?}",char));
+                        return Err(format!("expected `-` or `+` near {:?}",char));
                     }
                 }
                 DeclarationParserState::ReturnType(partial_type) => {
@@ -292,7 +450,10 @@ impl ParsedDeclaration {
                         state = DeclarationParserState::ReturnType(partial_type);
                     }
                     else if char == ' ' {
-                        return Err("Expected return type near ' '".to_owned());
+                        //multi-word types like `unsigned int` or `NSString *`
+                        let mut extended_type = partial_type.0;
+                        extended_type.push(char);
+                        state = DeclarationParserState::ReturnType(Type(extended_type));
                     }
                     else if char == '(' {
                         //ignore
@@ -303,7 +464,7 @@ impl ParsedDeclaration {
                         if DEBUG_PARSER {
                             println!("Parsed return type {:?}",partial_type);
                         }
-                        return_type = Some(partial_type);
+                        return_type = Some(Type(partial_type.0.trim_end().to_owned()));
                         state = DeclarationParserState::SelectorPart(SelectorPart(String::with_capacity(20)));
                     }
                     else if char == '(' || char == ' ' {
@@ -347,7 +508,10 @@ impl ParsedDeclaration {
                         state = DeclarationParserState::ArgumentType(partial_type)
                     }
                     else if char == ' ' {
-                        return Err(format!("Expected argument type near whitespace after {:?}",partial_type));
+                        //multi-word types like `unsigned int` or `NSString *`
+                        let mut new = partial_type.0;
+                        new.push(char);
+                        state = DeclarationParserState::ArgumentType(Type(new));
                     }
                     else if char == '(' { //ignore this token
                         state = DeclarationParserState::ArgumentType(partial_type)
@@ -357,7 +521,7 @@ impl ParsedDeclaration {
                         if DEBUG_PARSER {
                             println!("Parsed argument type {:?}",partial_type);
                         }
-                        current_partial_argument_type = Some(partial_type);
+                        current_partial_argument_type = Some(Type(partial_type.0.trim_end().to_owned()));
                         state = DeclarationParserState::ArgumentName(ArgumentName(String::with_capacity(10)));
                     }
                     else { //extend type
@@ -393,7 +557,7 @@ impl ParsedDeclaration {
 
         //at this point, the question is, did we stop at an OK location?
         let expected: Option<&'static str> = match state {
-            DeclarationParserState::Initial => Some("-"),
+            DeclarationParserState::Initial => Some("-` or `+"),
             DeclarationParserState::ReturnType(_) => Some(")"),
             DeclarationParserState::SelectorPart(_) => None, //ok to stop here
             DeclarationParserState::ArgumentType(_) => Some(")"),
@@ -428,6 +592,7 @@ impl ParsedDeclaration {
 
 
         Ok(ParsedDeclaration {
+            class_method,
             return_type: return_type.expect("return_type"),
             first_part,
             next_parts: parsed_partials
@@ -443,8 +608,53 @@ pub fn parse_to_selector(declaration: &str) -> Result<String,String> {
 
 
 pub fn parse_to_type_encoding(declaration: &str) -> Result<String,String> {
+    ParsedDeclaration::from_str(declaration)?.type_str()
+}
+
+///A type in a method signature, as written, and what we know about it.
+pub struct SignatureType {
+    pub objc_type: String,
+    ///The type encoding.  `None` for a Rust type, named by `objc_type`, whose encoding comes from its `Encode` implementation.
+    pub encoding: Option<String>,
+    pub size: Option<usize>,
+}
+
+///The return type, and the argument types (including `self` and `_cmd`) of a declaration, for checking against the Rust function.
+pub fn parse_to_signature(declaration: &str) -> Result<(SignatureType, Vec<SignatureType>),String> {
+    let decl = ParsedDeclaration::from_str(declaration)?;
+    let signature_type = |objc_type: &str| {
+        let parsed = ParsedType::parse(objc_type);
+        let encoding = match parsed {
+            ParsedType::Rust(_) => None,
+            ref other => Some(other.type_encoding()),
+        };
+        SignatureType { objc_type: objc_type.trim().to_owned(), encoding, size: parsed.size() }
+    };
+    let mut arguments = vec![signature_type(if decl.class_method { "Class" } else { "id" }), signature_type("SEL")];
+    arguments.extend(decl.argument_types().into_iter().map(signature_type));
+    Ok((signature_type(&decl.return_type.0), arguments))
+}
+
+///Type encoding for a property of the given ObjC type, like `@"NSString"` for `NSString *`.
+///
+/// This is more specific than the encoding used in method signatures, which doesn't name the class.
+pub fn parse_to_property_type_encoding(objc_type: &str) -> String {
+    let compact: String = objc_type.chars().filter(|c| !c.is_whitespace()).collect();
+    if let Some(protocols) = compact.strip_prefix("id<").and_then(|p| p.strip_suffix('>')) {
+        return format!("@\"<{}>\"",protocols.replace(',',"><"));
+    }
+    if let Some(base) = compact.strip_suffix('*') {
+        if !base.is_empty() && base.chars().all(|c| c.is_alphanumeric() || c == '_') && matches!(ParsedType::parse(base),ParsedType::Unknown | ParsedType::Rust(_)) {
+            return format!("@\"{}\"",base);
+        }
+    }
+    ParsedType::parse(objc_type.trim()).type_encoding()
+}
+
+///Whether the declaration is a class (`+`) method
+pub fn parse_to_class_method(declaration: &str) -> Result<bool,String> {
     let decl = ParsedDeclaration::from_str(declaration);
-    decl.map(|f| f.type_str())
+    decl.map(|f| f.class_method)
 }
 
 
@@ -456,7 +666,7 @@ fn parse_declaration_1() {
     let t = parse_1.unwrap();
     assert_eq!(t.selector(), "bar");
 
-    assert_eq!(t.type_str(), "v16@0:8");
+    assert_eq!(t.type_str().unwrap(), "v16@0:8");
 }
 
 #[test]
@@ -465,7 +675,7 @@ fn parse_declaration_2() {
     assert!(parse_2.is_ok(),"{:?}",parse_2.err().unwrap());
     let p = parse_2.unwrap();
     assert_eq!(p.selector(), "a:b:");
-    assert_eq!(p.type_str(), "v24@0:8i16f20");
+    assert_eq!(p.type_str().unwrap(), "v24@0:8i16f20");
 }
 
 #[test] fn parse_declaration_3() {
@@ -473,5 +683,66 @@ fn parse_declaration_2() {
     assert!(parse.is_ok());
     let p = parse.unwrap();
     assert_eq!(p.selector(), "initWithFrame:");
-    assert_eq!(p.type_str(), "@48@0:8{CGRect={CGPoint=dd}{CGSize=dd}}16");
-}
\ No newline at end of file
+    assert_eq!(p.type_str().unwrap(), "@48@0:8{CGRect={CGPoint=dd}{CGSize=dd}}16");
+}
+
+#[test] fn parse_class_method() {
+    let parse = ParsedDeclaration::from_str("+(id) sharedInstance");
+    assert!(parse.is_ok());
+    let p = parse.unwrap();
+    assert!(p.class_method);
+    assert_eq!(p.selector(), "sharedInstance");
+    assert_eq!(p.type_str().unwrap(), "@16@0:8");
+    assert!(!ParsedDeclaration::from_str("-(id) init").unwrap().class_method);
+}
+
+#[test] fn parse_multiword_types() {
+    let p = ParsedDeclaration::from_str("-(unsigned int) count").unwrap();
+    assert_eq!(p.type_str().unwrap(), "I16@0:8");
+    let p = ParsedDeclaration::from_str("-(void) setTitle:(NSString *) title count:(unsigned long long ) count").unwrap();
+    assert_eq!(p.selector(), "setTitle:count:");
+    assert_eq!(p.type_str().unwrap(), "v32@0:8@16Q24");
+}
+
+#[test] fn signature() {
+    let (return_type, arguments) = parse_to_signature("-(void) a:(unsigned short) arg b:(NSWidget *) arg2 c:(NSInteger) arg3").unwrap();
+    assert_eq!(return_type.size, Some(0));
+    let sizes: Vec<Option<usize>> = arguments.iter().map(|a| a.size).collect();
+    assert_eq!(sizes, vec![Some(8), Some(8), Some(2), Some(8), Some(8)]);
+    assert_eq!(arguments[4].objc_type, "NSInteger");
+    assert_eq!(arguments[4].encoding.as_deref(), Some("q"));
+    let (return_type, _) = parse_to_signature("-(MyStruct) value").unwrap();
+    assert_eq!(return_type.objc_type, "MyStruct");
+    assert!(return_type.encoding.is_none() && return_type.size.is_none());
+}
+
+#[test] fn structures() {
+    let type_str = |declaration: &str| ParsedDeclaration::from_str(declaration).unwrap().type_str().unwrap();
+    assert_eq!(type_str("-(NSRange) range"), "{_NSRange=QQ}16@0:8");
+    assert_eq!(type_str("-(void) moveTo:(CGPoint) point by:(char) amount"), "v36@0:8{CGPoint=dd}16c32");
+    assert_eq!(type_str("-(void) setTransform:(CGAffineTransform) transform animated:(BOOL) animated"), "v68@0:8{CGAffineTransform=dddddd}16B64");
+    assert_eq!(type_str("-(MTLClearColor) clearColor"), "{MTLClearColor=dddd}16@0:8");
+    assert_eq!(type_str("-(void) setInsets:(NSEdgeInsets) insets"), "v48@0:8{NSEdgeInsets=dddd}16");
+    assert_eq!(type_str("-(void) replaceRegion:(MTLRegion) region"), "v64@0:8{MTLRegion={MTLOrigin=QQQ}{MTLSize=QQQ}}16");
+    assert_eq!(type_str("-(void) getRect:(NSRect *) rect"), "v24@0:8^{CGRect={CGPoint=dd}{CGSize=dd}}16");
+    assert!(ParsedDeclaration::from_str("-(void) setValue:(MyStruct) value").unwrap().type_str().is_err());
+}
+
+#[test] fn inline_structures() {
+    let parsed = ParsedType::parse("struct Packed { unsigned int a : 3; unsigned int b : 30; char name[6]; union { float f; long long l; } value; }");
+    assert_eq!(parsed.type_encoding(), "{Packed=b3b30[6c](?=fq)}");
+    //two bitfield units, the name, padding, and the union
+    assert_eq!(parsed.layout(), Some((24, 8)));
+    let parsed = ParsedType::parse("struct { char c; short s; int i[2][3]; }");
+    assert_eq!(parsed.type_encoding(), "{?=cs[2[3i]]}");
+    assert_eq!(parsed.size(), Some(28));
+    assert_eq!(ParsedType::parse("union Value { char c; int i; }").size(), Some(4));
+}
+
+#[test] fn property_type_encodings() {
+    assert_eq!(parse_to_property_type_encoding("NSString *"), "@\"NSString\"");
+    assert_eq!(parse_to_property_type_encoding("id"), "@");
+    assert_eq!(parse_to_property_type_encoding("id < NSCopying >"), "@\"<NSCopying>\"");
+    assert_eq!(parse_to_property_type_encoding("unsigned int"), "I");
+    assert_eq!(parse_to_property_type_encoding("char *"), "*");
+}
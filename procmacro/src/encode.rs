@@ -0,0 +1,171 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Implements `#[derive(ObjcEncode)]` and `#[derive(ObjcPrimitive)]`
+
+use proc_macro::{TokenStream, TokenTree, Delimiter, Spacing};
+
+///Splits the fields of a struct body at top-level commas.  Commas inside generic arguments don't count.
+fn split_fields(stream: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut fields = vec![Vec::new()];
+    let mut angle_depth = 0;
+    let mut previous_joint_dash = false;
+    for token in stream {
+        let mut joint_dash = false;
+        match &token {
+            TokenTree::Punct(p) if p.as_char() == ',' && angle_depth == 0 => {
+                fields.push(Vec::new());
+                continue;
+            }
+            TokenTree::Punct(p) if p.as_char() == '<' => angle_depth += 1,
+            //`->` in a function pointer type is not a closing bracket
+            TokenTree::Punct(p) if p.as_char() == '>' && !previous_joint_dash => angle_depth -= 1,
+            TokenTree::Punct(p) if p.as_char() == '-' => joint_dash = p.spacing() == Spacing::Joint,
+            _ => ()
+        }
+        previous_joint_dash = joint_dash;
+        fields.last_mut().unwrap().push(token);
+    }
+    fields.retain(|f| !f.is_empty());
+    fields
+}
+
+///The type of a field, like `pub(crate) #[attribute] name: Type` or `pub Type`
+fn field_type(field: Vec<TokenTree>, named: bool) -> Result<String,String> {
+    let mut iter = field.into_iter().peekable();
+    loop {
+        match iter.peek() {
+            Some(TokenTree::Punct(p)) if p.as_char() == '#' => {
+                iter.next();
+                iter.next(); //the attribute
+            }
+            Some(TokenTree::Ident(i)) if i.to_string() == "pub" => {
+                iter.next();
+                if matches!(iter.peek(), Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Parenthesis) {
+                    iter.next();
+                }
+            }
+            _ => break
+        }
+    }
+    if named {
+        match iter.next() {
+            Some(TokenTree::Ident(_)) => (),
+            o => return Err(format!("Expected field name, got {:?}",o)),
+        }
+        match iter.next() {
+            Some(TokenTree::Punct(p)) if p.as_char() == ':' => (),
+            o => return Err(format!("Expected `:`, got {:?}",o)),
+        }
+    }
+    let field_type: TokenStream = iter.collect();
+    if field_type.is_empty() {
+        return Err("Expected field type".to_owned());
+    }
+    Ok(field_type.to_string())
+}
+
+///A `#[repr(C)]` struct or union, parsed for a derive
+pub struct ReprC {
+    ///`Struct` or `Union`
+    pub kind: &'static str,
+    pub name: String,
+    ///The types of the fields
+    pub fields: Vec<String>,
+    ///The `N` in `#[repr(C, packed(N))]`, or 1 for `packed`
+    pub packed: Option<usize>,
+}
+
+///Parses a `#[repr(C)]` struct or union for `#[derive(derive)]`
+pub fn parse_repr_c(stream: TokenStream, derive: &str) -> Result<ReprC,String> {
+    let mut repr_c = false;
+    let mut packed = None;
+    let mut kind = None;
+    let mut name = None;
+    let mut fields = Vec::new();
+    let mut iter = stream.into_iter().peekable();
+    while let Some(token) = iter.next() {
+        match token {
+            TokenTree::Group(g) if g.delimiter() == Delimiter::Bracket => {
+                //an attribute, we're looking for `repr(C)` or `repr(C, packed(2))`
+                let mut attribute = g.stream().into_iter();
+                if matches!(attribute.next(), Some(TokenTree::Ident(i)) if i.to_string() == "repr") {
+                    if let Some(TokenTree::Group(arguments)) = attribute.next() {
+                        let mut arguments = arguments.stream().into_iter().peekable();
+                        while let Some(argument) = arguments.next() {
+                            match argument {
+                                TokenTree::Ident(i) if i.to_string() == "C" => repr_c = true,
+                                TokenTree::Ident(i) if i.to_string() == "packed" => {
+                                    packed = Some(1);
+                                    if let Some(TokenTree::Group(n)) = arguments.peek() {
+                                        packed = Some(n.stream().to_string().parse().map_err(|_| format!("Can't parse `packed{}`",n))?);
+                                    }
+                                }
+                                _ => ()
+                            }
+                        }
+                    }
+                }
+            }
+            TokenTree::Ident(i) if kind.is_none() && (i.to_string() == "struct" || i.to_string() == "union") => {
+                kind = Some(if i.to_string() == "struct" { "Struct" } else { "Union" });
+                match iter.next() {
+                    Some(TokenTree::Ident(i)) => name = Some(i.to_string()),
+                    o => return Err(format!("Expected type name, got {:?}",o)),
+                }
+                if matches!(iter.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '<') {
+                    return Err(format!("derive({}) is not supported for generic types",derive));
+                }
+            }
+            TokenTree::Group(g) if name.is_some() && (g.delimiter() == Delimiter::Brace || g.delimiter() == Delimiter::Parenthesis) => {
+                let named = g.delimiter() == Delimiter::Brace;
+                for field in split_fields(g.stream()) {
+                    fields.push(field_type(field, named)?);
+                }
+                break;
+            }
+            _ => ()
+        }
+    }
+    let (kind, name) = match (kind, name) {
+        (Some(kind), Some(name)) => (kind, name),
+        _ => return Err(format!("derive({}) requires a struct or union",derive)),
+    };
+    if !repr_c {
+        return Err(format!("derive({}) requires `{}` to be #[repr(C)]",derive,name));
+    }
+    Ok(ReprC{kind, name, fields, packed})
+}
+
+///Implements `Encode` and `EncodeRef` for a `#[repr(C)]` struct or union
+pub fn derive_encode(stream: TokenStream) -> Result<String,String> {
+    let ReprC{kind, name, fields, ..} = parse_repr_c(stream, "ObjcEncode")?;
+    let fields: Vec<String> = fields.iter().map(|f| format!("<{} as objr::bindings::Encode>::ENCODING",f)).collect();
+    Ok(format!(r#"
+        impl objr::bindings::Encode for {NAME} {{
+            const ENCODING: objr::bindings::Encoding = objr::bindings::Encoding::{KIND}("{NAME}", &[{FIELDS}]);
+        }}
+        impl objr::bindings::EncodeRef for {NAME} {{
+            const ENCODING_REF: objr::bindings::Encoding = objr::bindings::Encoding::Pointer(&<Self as objr::bindings::Encode>::ENCODING);
+        }}
+    "#,NAME=name,KIND=kind,FIELDS=fields.join(",")))
+}
+
+///Implements `Primitive` and `Arguable` for a `#[repr(C)]` struct or union
+pub fn derive_primitive(stream: TokenStream) -> Result<String,String> {
+    let ReprC{kind, name, fields, packed} = parse_repr_c(stream, "ObjcPrimitive")?;
+    //x86_64 returns a struct in memory if it's larger than 16 bytes, if it has a misaligned field, or if any field would be returned in memory
+    let fields_in_memory: Vec<String> = fields.iter().map(|f| format!(" || <{} as objr::bindings::_PrimitiveField>::IN_MEMORY",f)).collect();
+    let misaligned = match packed {
+        Some(packed) if kind == "Struct" => {
+            let layout: Vec<String> = fields.iter().map(|f| format!("(std::mem::size_of::<{F}>(),std::mem::align_of::<{F}>())",F=f)).collect();
+            format!(" || objr::bindings::_packed_misaligned(&[{}], {})",layout.join(","),packed)
+        }
+        _ => String::new()
+    };
+    Ok(format!(r#"
+        unsafe impl objr::bindings::Primitive for {NAME} {{
+            const RETURNS_IN_MEMORY: bool = std::mem::size_of::<Self>() > 16{MISALIGNED}{FIELDS};
+        }}
+        unsafe impl objr::bindings::Arguable for {NAME} {{}}
+    "#,NAME=name,MISALIGNED=misaligned,FIELDS=fields_in_memory.join("")))
+}
@@ -6,6 +6,9 @@ pub fn instance_impl(_type: &str) -> String{
     impl ::objr::bindings::ObjcInstance for {TYPE} {{
         }}
         unsafe impl ::objr::bindings::Arguable for {TYPE} {{ }}
+        impl ::objr::bindings::EncodeRef for {TYPE} {{
+            const ENCODING_REF: ::objr::bindings::Encoding = ::objr::bindings::Encoding::Primitive("@");
+        }}
         impl std::fmt::Display for {TYPE} {{
             fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {{
                 use ::objr::foundation::NSObjectTrait;
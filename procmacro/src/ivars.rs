@@ -0,0 +1,189 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Implements the ivar list for `objc_subclass!`.
+
+use proc_macro::{TokenStream, TokenTree, Delimiter};
+
+pub struct Ivar {
+    pub name: String,
+    ///Rust type, as written
+    pub rust_type: String,
+}
+
+///Parses `name: Type, name2: Type2` out of the ivar block.
+pub fn parse_ivars(stream: TokenStream) -> Result<Vec<Ivar>,String> {
+    let mut ivars = Vec::new();
+    let mut iter = stream.into_iter().peekable();
+    loop {
+        let name = match iter.next() {
+            None => break,
+            Some(TokenTree::Ident(i)) => i.to_string(),
+            Some(o) => return Err(format!("Expected ivar name, got {:?}",o)),
+        };
+        match iter.next() {
+            Some(TokenTree::Punct(p)) if p.as_char() == ':' => (),
+            o => return Err(format!("Expected `:` after ivar {}, got {:?}",name,o)),
+        }
+        //types passed from macro_rules as `$t:ty` arrive as a single invisible group,
+        //but handle the spelled-out form as well by tracking `<>` depth.
+        let mut rust_type = String::new();
+        let mut depth = 0;
+        while let Some(token) = iter.peek() {
+            match token {
+                TokenTree::Punct(p) if p.as_char() == ',' && depth == 0 => break,
+                TokenTree::Punct(p) if p.as_char() == '<' => depth += 1,
+                TokenTree::Punct(p) if p.as_char() == '>' => depth -= 1,
+                _ => {}
+            }
+            let token = iter.next().unwrap();
+            match token {
+                TokenTree::Group(g) if g.delimiter() == Delimiter::None => rust_type.push_str(&g.stream().to_string()),
+                other => rust_type.push_str(&other.to_string()),
+            }
+            rust_type.push(' ');
+        }
+        if rust_type.is_empty() {
+            return Err(format!("Expected type for ivar {}",name));
+        }
+        ivars.push(Ivar{name, rust_type: rust_type.trim().to_owned()});
+        //trailing comma
+        iter.next();
+    }
+    Ok(ivars)
+}
+
+///The ObjC type encoding for an ivar holding an object, like `StrongCell<NSString>` or `Option<StrongCell<NSString>>`, from its spelling.
+///
+/// Like clang, this names the class (`@"NSString"`), which `Encode` can't.  Other types are `None`,
+/// and get their encoding from `Encode` instead.
+pub fn object_encoding(rust_type: &str) -> Option<String> {
+    let rust_type: String = rust_type.chars().filter(|c| !c.is_whitespace()).collect();
+    //Option of a cell is a nullable pointer, so it's encoded the same
+    let rust_type = rust_type.strip_prefix("Option<").and_then(|t| t.strip_suffix('>')).unwrap_or(&rust_type);
+    let open = rust_type.find('<')?;
+    let (base, argument) = (&rust_type[..open], rust_type[open + 1..].strip_suffix('>'));
+    match base.rsplit("::").next().unwrap() {
+        "StrongCell" | "StrongMutCell" | "StrongLifetimeCell" | "AutoreleasedCell" | "AutoreleasedMutCell" => {
+            let class = argument.map(|a| a.rsplit(',').next().unwrap().rsplit("::").next().unwrap());
+            match class {
+                Some(class) if !class.is_empty() && class.chars().all(|c| c.is_alphanumeric() || c == '_') => Some(format!("@\"{}\"",class)),
+                _ => Some("@".to_owned())
+            }
+        }
+        _ => None
+    }
+}
+
+///The expression for an ivar's `Encoding`.
+///
+/// Types that don't implement `Encode` are `?`.  ObjC doesn't need this to be right, but the debugger and KVC use it.
+fn encoding_expression(rust_type: &str, ty: &str) -> String {
+    match object_encoding(rust_type) {
+        Some(encoding) => format!("objr::bindings::Encoding::Primitive(\"{}\")",encoding.replace('"',"\\\"")),
+        None => format!("objr::bindings::_IvarEncoding::<{}>::ENCODING",ty),
+    }
+}
+
+///Emits the ivar list, offset symbols, and accessors for a subclass.
+pub fn ivar_list(visibility: &str, identifier: &str, objc_name: &str, ivars: &[Ivar]) -> String {
+    let mut types = String::new();
+    let mut statics = String::new();
+    let mut entries = String::new();
+    let mut accessors = String::new();
+    //the first ivar goes after `isa`
+    let mut previous_end = "8".to_owned();
+    for ivar in ivars {
+        let name = &ivar.name;
+        let ty = format!("super::ivar_type_{OBJC_NAME}_{NAME}",OBJC_NAME=objc_name,NAME=name);
+        let encoding = encoding_expression(&ivar.rust_type, &ty);
+        types.push_str(&format!("#[allow(non_camel_case_types)] type ivar_type_{OBJC_NAME}_{NAME} = {TYPE};\n",OBJC_NAME=objc_name,NAME=name,TYPE=ivar.rust_type));
+        statics.push_str(&format!(r#"
+            pub const OFFSET_{NAME}: usize = align({PREVIOUS_END}, std::mem::align_of::<{TY}>());
+            #[link_section="__TEXT,__objc_methname,cstring_literals"]
+            static NAME_{NAME}: [u8; {NAME_LEN}] = *b"{NAME}\0";
+            const ENCODING_{NAME}: objr::bindings::Encoding = {ENCODING};
+            #[link_section="__TEXT,__objc_methtype,cstring_literals"]
+            static TYPE_{NAME}: [u8; ENCODING_{NAME}.encoded_len() + 1] = objr::bindings::_type_encoding(&ENCODING_{NAME});
+            //The runtime rewrites this if the superclass turns out to be larger than we thought
+            #[link_section="__DATA,__objc_ivar"]
+            #[export_name="OBJC_IVAR_$_{OBJC_NAME}.{NAME}"]
+            pub static OBJC_IVAR_{NAME}: objr::bindings::_IvarOffset = objr::bindings::_IvarOffset::new(OFFSET_{NAME} as u32);
+        "#,NAME=name,NAME_LEN=name.len() + 1,TY=ty,PREVIOUS_END=previous_end,OBJC_NAME=objc_name,
+            ENCODING=encoding));
+        entries.push_str(&format!(r#"
+                IvarT {{
+                    offset: OBJC_IVAR_{NAME}.as_ptr(),
+                    name: &NAME_{NAME} as *const u8,
+                    r#type: &TYPE_{NAME} as *const u8,
+                    //log2
+                    alignment: std::mem::align_of::<{TY}>().trailing_zeros(),
+                    size: std::mem::size_of::<{TY}>() as u32,
+                }},"#,NAME=name,TY=ty));
+        accessors.push_str(&format!(r#"
+            ///Gets a mutable reference to the `{NAME}` ivar.
+            ///
+            /// # Safety
+            /// You must guarantee you are called from an exclusive, mutable context, and that the ivar has been initialized
+            /// (or that you are initializing it without reading or dropping the old value, e.g. with [std::ptr::write]).
+            #[allow(dead_code)]
+            {VIS} unsafe fn {NAME}_mut(&self) -> &mut ivar_type_{OBJC_NAME}_{NAME} {{
+                //Note that we need to read_volatile here to get the real runtime offset,
+                //not the offset known at compile time.  `get` does this for us.
+                let offset = ivar_list_{OBJC_NAME}::OBJC_IVAR_{NAME}.get() as usize;
+                &mut *((self as *const Self as *mut u8).add(offset) as *mut ivar_type_{OBJC_NAME}_{NAME})
+            }}
+            ///Gets a reference to the `{NAME}` ivar.
+            #[allow(dead_code)]
+            {VIS} fn {NAME}(&self) -> &ivar_type_{OBJC_NAME}_{NAME} {{
+                unsafe {{ self.{NAME}_mut() }} //coerce to non-mut
+            }}
+        "#,NAME=name,VIS=visibility,OBJC_NAME=objc_name));
+        previous_end = format!("OFFSET_{NAME} + std::mem::size_of::<{TY}>()",NAME=name,TY=ty);
+    }
+    //Like clang, only export a list that has something in it
+    let attributes = if ivars.is_empty() {
+        String::new()
+    }
+    else {
+        format!(r#"#[link_section="__DATA,__objc_const"]
+            #[export_name="_OBJC_INSTANCE_VARIABLES_{OBJC_NAME}"]"#,OBJC_NAME=objc_name)
+    };
+    format!(r#"
+        {TYPES}
+        //when there are no ivars, some of this goes unused
+        #[allow(non_upper_case_globals,non_snake_case,dead_code)]
+        mod ivar_list_{OBJC_NAME} {{
+            type IvarT = super::subclass_impl_{OBJC_NAME}::IvarT;
+            type IvarListT<const SIZE: usize> = super::subclass_impl_{OBJC_NAME}::IvarListT<SIZE>;
+            //the `?` encoding, for ivar types that don't implement Encode
+            use objr::bindings::_IvarEncodingFallback;
+            const fn align(offset: usize, alignment: usize) -> usize {{
+                (offset + alignment - 1) / alignment * alignment
+            }}
+            {STATICS}
+            pub const INSTANCE_SIZE: usize = {PREVIOUS_END};
+            pub const IVAR_COUNT: usize = {COUNT};
+            {ATTRIBUTES}
+            pub static IVAR_LIST: objr::bindings::_SyncWrapper<IvarListT<{COUNT}>> = objr::bindings::_SyncWrapper(IvarListT {{
+                //some dispute about whether this is the size of ivar_t, a magic number, or both.  In practice it's 32
+                magic: 32,
+                count: {COUNT},
+                ivars: [{ENTRIES}
+                ]
+            }});
+        }}
+        impl {IDENTIFIER} {{
+            {ACCESSORS}
+        }}
+    "#,TYPES=types,OBJC_NAME=objc_name,STATICS=statics,PREVIOUS_END=previous_end,COUNT=ivars.len(),ENTRIES=entries,IDENTIFIER=identifier,ACCESSORS=accessors,ATTRIBUTES=attributes)
+}
+
+#[test] fn encodings() {
+    assert_eq!(object_encoding("StrongCell < NSObject >").unwrap(), "@\"NSObject\"");
+    assert_eq!(object_encoding("Option<objr::bindings::StrongCell<NSString>>").unwrap(), "@\"NSString\"");
+    assert_eq!(object_encoding("StrongLifetimeCell<'a, NSString>").unwrap(), "@\"NSString\"");
+    assert_eq!(object_encoding("u32"), None);
+    assert_eq!(object_encoding("Option<u32>"), None);
+    assert_eq!(object_encoding("Option<f64>"), None);
+    assert_eq!(object_encoding("Vec<u8>"), None);
+}
@@ -11,6 +11,11 @@ mod flatten;
 mod strings;
 mod export_name;
 mod declarations;
+mod ivars;
+mod methods;
+mod protocols;
+mod properties;
+mod encode;
 
 use proc_macro::{TokenStream, TokenTree};
 use misc::{error, parse_literal_string,parse_ident,parse_type};
@@ -137,6 +142,28 @@ pub fn derive_objc_instance(stream: TokenStream) -> TokenStream {
     instances::instance_impl(&parsed_name.unwrap()).parse().unwrap()
 }
 
+///Derive macro for `Encode`.
+///
+/// Requires a `#[repr(C)]` struct or union, whose fields all implement `Encode`.
+#[proc_macro_derive(ObjcEncode)]
+pub fn derive_objc_encode(stream: TokenStream) -> TokenStream {
+    match encode::derive_encode(stream) {
+        Ok(implementation) => implementation.parse().unwrap(),
+        Err(e) => error(&e),
+    }
+}
+
+///Derive macro for `Primitive` and `Arguable`, so a struct can be passed to and returned from `perform_primitive`.
+///
+/// Requires a `#[repr(C)]` struct or union, whose fields all implement `Primitive` (or are arrays of them).
+#[proc_macro_derive(ObjcPrimitive)]
+pub fn derive_objc_primitive(stream: TokenStream) -> TokenStream {
+    match encode::derive_primitive(stream) {
+        Ok(implementation) => implementation.parse().unwrap(),
+        Err(e) => error(&e),
+    }
+}
+
 ///Provides an implementation of ObjcClass, based on an `objc_any_class!()` trait being in scope.
 /// ```
 /// # fn main() {} //https://stackoverflow.com/questions/67443775/combining-doctests-and-extern-crate/67452255#67452255
@@ -851,4 +878,153 @@ pub fn __type_declr(stream: TokenStream) -> TokenStream {
         other => { return error(&format!("Expected end of macro invocation, got {:?}",other));}
     }
     format!("type {PREFIX}{IDENT} = {TYPE};", PREFIX=prefix, IDENT=ident, TYPE=ty).parse().unwrap()
-}
\ No newline at end of file
+}
+
+/**
+Declares the ivars of a subclass: the ivar list, the `OBJC_IVAR_$_Class.name` offset symbols, and accessors on the wrapper type.
+
+```ignore
+__objc_subclass_ivars!(pub, RustIdentifier, ObjcName, { count: u32, delegate: StrongCell<NSObject> });
+```
+expands to a module `ivar_list_ObjcName` with an `IVAR_LIST` static, an `INSTANCE_SIZE` const,
+and `count()`/`count_mut()` etc. on `RustIdentifier`.  It expects `subclass_impl_ObjcName` to be in scope.
+*/
+#[doc(hidden)]
+#[proc_macro]
+pub fn __objc_subclass_ivars(stream: TokenStream) -> TokenStream {
+    let mut iter = stream.into_iter();
+    //the visibility is everything before the first comma.  It may be empty, or an invisible group.
+    let mut visibility = String::new();
+    loop {
+        match iter.next() {
+            Some(TokenTree::Punct(p)) if p == ',' => break,
+            Some(other) => visibility.push_str(&other.to_string()),
+            None => { return error("Expected visibility")}
+        }
+    }
+    let identifier = match parse_ident(&mut iter) {
+        Ok(i) => i,
+        Err(e) => { return error(&format!("Expected rust identifier, got {}",e))}
+    };
+    match iter.next() {
+        Some(TokenTree::Punct(p)) if p == ',' => (),
+        o => { return error(&format!("Expected comma, got {:?}",o))}
+    };
+    let objc_name = match parse_ident(&mut iter) {
+        Ok(i) => i,
+        Err(e) => { return error(&format!("Expected objc identifier, got {}",e))}
+    };
+    match iter.next() {
+        Some(TokenTree::Punct(p)) if p == ',' => (),
+        o => { return error(&format!("Expected comma, got {:?}",o))}
+    };
+    let ivars = match iter.next() {
+        Some(TokenTree::Group(g)) => match ivars::parse_ivars(g.stream()) {
+            Ok(ivars) => ivars,
+            Err(e) => { return error(&e)}
+        },
+        o => { return error(&format!("Expected ivar block, got {:?}",o))}
+    };
+    match iter.next() {
+        None => (),
+        Some(e) => { return error(&format!("Expected end of macro invocation, got {:?}",e))}
+    };
+    ivars::ivar_list(&visibility, &identifier, &objc_name, &ivars).parse().unwrap()
+}
+
+/**
+Declares the method and property lists of a subclass.
+
+```ignore
+__objc_subclass_methods!(RustIdentifier, ObjcName, ["-(id) init", init, "+(id) shared", shared], [@property(nonatomic) int count; @synthesize count = _count;]);
+```
+expands to a module `implpart_method_list_ObjcName` with `INSTANCE_METHOD_LIST` and `CLASS_METHOD_LIST` statics
+and `INSTANCE_METHOD_COUNT`/`CLASS_METHOD_COUNT` consts, and a module `property_list_ObjcName` with a `PROPERTY_LIST` static
+and a `PROPERTY_COUNT` const.  The functions are resolved in the caller's scope.  Synthesized accessors use the ivars
+declared by [__objc_subclass_ivars!].
+*/
+#[doc(hidden)]
+#[proc_macro]
+pub fn __objc_subclass_methods(stream: TokenStream) -> TokenStream {
+    let mut iter = stream.into_iter();
+    let identifier = match parse_ident(&mut iter) {
+        Ok(i) => i,
+        Err(e) => { return error(&format!("Expected rust identifier, got {}",e))}
+    };
+    match iter.next() {
+        Some(TokenTree::Punct(p)) if p == ',' => (),
+        o => { return error(&format!("Expected comma, got {:?}",o))}
+    };
+    let objc_name = match parse_ident(&mut iter) {
+        Ok(i) => i,
+        Err(e) => { return error(&format!("Expected objc identifier, got {}",e))}
+    };
+    match iter.next() {
+        Some(TokenTree::Punct(p)) if p == ',' => (),
+        o => { return error(&format!("Expected comma, got {:?}",o))}
+    };
+    let mut methods = match iter.next() {
+        Some(TokenTree::Group(g)) => match methods::parse_methods(g.stream()) {
+            Ok(methods) => methods,
+            Err(e) => { return error(&e)}
+        },
+        o => { return error(&format!("Expected method block, got {:?}",o))}
+    };
+    match iter.next() {
+        Some(TokenTree::Punct(p)) if p == ',' => (),
+        o => { return error(&format!("Expected comma, got {:?}",o))}
+    };
+    let properties = match iter.next() {
+        Some(TokenTree::Group(g)) => match properties::parse_properties(g.stream()) {
+            Ok(properties) => properties,
+            Err(e) => { return error(&e)}
+        },
+        o => { return error(&format!("Expected property block, got {:?}",o))}
+    };
+    match iter.next() {
+        None => (),
+        Some(e) => { return error(&format!("Expected end of macro invocation, got {:?}",e))}
+    };
+    let synthesized = match properties::synthesize(&identifier, &objc_name, &properties, &mut methods) {
+        Ok(s) => s,
+        Err(e) => { return error(&e)}
+    };
+    let mut output = methods::method_lists(&objc_name, &methods, &synthesized);
+    output.push_str(&properties::property_list(&objc_name, &properties));
+    output.parse().unwrap()
+}
+
+/**
+Declares the protocol list of a subclass.
+
+```ignore
+__objc_subclass_protocols!(ObjcName, [NSCopying, MTKViewDelegate]);
+```
+expands to a module `protocol_list_ObjcName` with a `PROTOCOL_LIST` static and a `PROTOCOL_COUNT` const.
+It expects `subclass_impl_ObjcName` to be in scope.
+*/
+#[doc(hidden)]
+#[proc_macro]
+pub fn __objc_subclass_protocols(stream: TokenStream) -> TokenStream {
+    let mut iter = stream.into_iter();
+    let objc_name = match parse_ident(&mut iter) {
+        Ok(i) => i,
+        Err(e) => { return error(&format!("Expected objc identifier, got {}",e))}
+    };
+    match iter.next() {
+        Some(TokenTree::Punct(p)) if p == ',' => (),
+        o => { return error(&format!("Expected comma, got {:?}",o))}
+    };
+    let protocols = match iter.next() {
+        Some(TokenTree::Group(g)) => match protocols::parse_protocols(g.stream()) {
+            Ok(protocols) => protocols,
+            Err(e) => { return error(&e)}
+        },
+        o => { return error(&format!("Expected protocol block, got {:?}",o))}
+    };
+    match iter.next() {
+        None => (),
+        Some(e) => { return error(&format!("Expected end of macro invocation, got {:?}",e))}
+    };
+    protocols::protocol_list(&objc_name, &protocols).parse().unwrap()
+}
@@ -0,0 +1,244 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Implements the method lists for `objc_subclass!`.
+
+use proc_macro::{TokenStream, TokenTree, Delimiter};
+use crate::declarations::{parse_to_selector, parse_to_type_encoding, parse_to_class_method, parse_to_signature, SignatureType};
+
+pub struct Method {
+    ///ObjC declaration, like `-(id) initWithFoo:(int) foo`
+    pub declaration: String,
+    ///ObjC selector, like `initWithFoo:`
+    pub selector: String,
+    ///`None` when the declaration involves Rust types, whose encodings are worked out at compile time
+    pub type_encoding: Option<String>,
+    ///`+` rather than `-`
+    pub class_method: bool,
+    ///Path to the Rust function, from inside the method list module
+    pub function: String,
+    return_type: SignatureType,
+    ///Including `self` and `_cmd`
+    arguments: Vec<SignatureType>,
+}
+
+impl Method {
+    pub fn new(declaration: &str, function: String) -> Result<Method,String> {
+        let (return_type, arguments) = parse_to_signature(declaration)?;
+        let type_encoding = if arguments.iter().chain(std::iter::once(&return_type)).all(|t| t.encoding.is_some()) {
+            Some(parse_to_type_encoding(declaration)?)
+        }
+        else {
+            None
+        };
+        Ok(Method {
+            declaration: declaration.to_owned(),
+            selector: parse_to_selector(declaration)?,
+            type_encoding,
+            class_method: parse_to_class_method(declaration)?,
+            function,
+            return_type,
+            arguments,
+        })
+    }
+
+    ///Checks at compile time that the Rust function has the declared arity, and that its argument and return types
+    /// have the sizes of the declared types (where we know them).
+    fn signature_check(&self) -> String {
+        let placeholders = vec!["_"; self.arguments.len()].join(",");
+        let mut asserts = String::new();
+        let mismatch = |what: &str, declared: &SignatureType, size: usize| format!("{:?}",
+            format!("objc_subclass!: {} of `{}` is declared as `{}` ({} bytes), but the Rust function's is a different size",what,self.declaration,declared.objc_type,size));
+        for (index, argument) in self.arguments.iter().enumerate() {
+            if let Some(size) = argument.size {
+                asserts.push_str(&format!("\n                assert!(arguments[{INDEX}] == {SIZE}, {MESSAGE});",
+                    INDEX=index,SIZE=size,MESSAGE=mismatch(&format!("argument {}",index),argument,size)));
+            }
+        }
+        if let Some(size) = self.return_type.size {
+            asserts.push_str(&format!("\n                assert!(return_size == {SIZE}, {MESSAGE});",SIZE=size,MESSAGE=mismatch("the return type",&self.return_type,size)));
+        }
+        format!(r#"
+            const _: () = {{
+                let function: unsafe extern "C" fn({PLACEHOLDERS}) -> _ = {FUNCTION};
+                let (arguments, return_size) = objr::bindings::_method_signature_sizes(&function);{ASSERTS}
+            }};"#,PLACEHOLDERS=placeholders,FUNCTION=self.function,ASSERTS=asserts)
+    }
+}
+
+///Path to a Rust type named in a declaration, from inside the method list module
+fn rust_type_path(name: &str) -> String {
+    if name.starts_with("crate::") || name.starts_with("::") {
+        name.to_owned()
+    }
+    else {
+        format!("super::{}",name)
+    }
+}
+
+impl Method {
+    ///Emits `METHTYPE_{index}`.
+    ///
+    /// If the declaration involves Rust types, we don't know their encodings and sizes, so the string is built
+    /// by a const fn from their `Encode` implementations.
+    fn method_type(&self, index: usize) -> String {
+        if let Some(type_encoding) = &self.type_encoding {
+            return format!(r#"
+            #[link_section="__TEXT,__objc_methtype,cstring_literals"]
+            static METHTYPE_{INDEX}: [u8; {ENCODING_LEN}] = *b"{ENCODING}\0";
+            "#,INDEX=index,ENCODING=type_encoding.replace('"',"\\\""),ENCODING_LEN=type_encoding.len() + 1);
+        }
+        let encoding = |t: &SignatureType| match &t.encoding {
+            Some(encoding) => format!("objr::bindings::Encoding::Primitive({:?})",encoding),
+            None => format!("<{} as objr::bindings::Encode>::ENCODING",rust_type_path(&t.objc_type)),
+        };
+        let arguments: Vec<String> = self.arguments.iter().map(|argument| {
+            let size = match argument.size {
+                Some(size) => size.to_string(),
+                None => format!("core::mem::size_of::<{}>()",rust_type_path(&argument.objc_type)),
+            };
+            format!("({},{})",encoding(argument),size)
+        }).collect();
+        format!(r#"
+            const METHTYPE_RETURN_{INDEX}: objr::bindings::Encoding = {RETURN};
+            const METHTYPE_ARGUMENTS_{INDEX}: [(objr::bindings::Encoding, usize); {COUNT}] = [{ARGUMENTS}];
+            #[link_section="__TEXT,__objc_methtype,cstring_literals"]
+            static METHTYPE_{INDEX}: [u8; objr::bindings::_method_type_encoding_len(&METHTYPE_RETURN_{INDEX}, &METHTYPE_ARGUMENTS_{INDEX})] =
+                objr::bindings::_method_type_encoding(&METHTYPE_RETURN_{INDEX}, &METHTYPE_ARGUMENTS_{INDEX});
+        "#,INDEX=index,RETURN=encoding(&self.return_type),COUNT=arguments.len(),ARGUMENTS=arguments.join(","))
+    }
+}
+
+///Parses `"-(void) declaration", function, "+(id) declaration2", function2` out of the method block.
+pub fn parse_methods(stream: TokenStream) -> Result<Vec<Method>,String> {
+    let mut methods = Vec::new();
+    let mut iter = stream.into_iter().peekable();
+    loop {
+        //literals passed from macro_rules may also arrive in an invisible group
+        let declaration = match iter.next() {
+            None => break,
+            Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::None => g.stream().into_iter().next(),
+            other => other,
+        };
+        let declaration = match declaration {
+            Some(TokenTree::Literal(l)) => {
+                let l = l.to_string();
+                match l.strip_prefix('"').and_then(|l| l.strip_suffix('"')) {
+                    Some(l) => l.to_owned(),
+                    None => return Err(format!("Expected method declaration, got {}",l))
+                }
+            }
+            o => return Err(format!("Expected method declaration, got {:?}",o)),
+        };
+        match iter.next() {
+            Some(TokenTree::Punct(p)) if p.as_char() == ',' => (),
+            o => return Err(format!("Expected `,` after {}, got {:?}",declaration,o)),
+        }
+        //functions passed from macro_rules as `$f:expr` arrive as a single invisible group
+        let mut function = String::new();
+        while let Some(token) = iter.peek() {
+            if matches!(token, TokenTree::Punct(p) if p.as_char() == ',') { break }
+            match iter.next().unwrap() {
+                TokenTree::Group(g) if g.delimiter() == Delimiter::None => function.push_str(&g.stream().to_string()),
+                other => function.push_str(&other.to_string()),
+            }
+        }
+        if function.is_empty() {
+            return Err(format!("Expected function for {}",declaration));
+        }
+        methods.push(Method::new(&declaration, format!("super::{}",function))?);
+        //trailing comma
+        iter.next();
+    }
+    Ok(methods)
+}
+
+///Emits a `__objc_nlclslist` entry, so that the runtime realizes the class at launch and calls `+load`.
+#[cfg(not(any(feature="gnustep", feature="mock-runtime")))]
+fn nonlazy_class(objc_name: &str) -> String {
+    format!(r#"
+        #[link_section="__DATA,__objc_nlclslist,regular,no_dead_strip"]
+        #[used]
+        static NONLAZY_CLASS: objr::bindings::_SyncWrapper<*const core::ffi::c_void> =
+            objr::bindings::_SyncWrapper(&super::subclass_finalize_{OBJC_NAME}::CLASS.0 as *const _ as *const core::ffi::c_void);
+    "#,OBJC_NAME=objc_name)
+}
+
+///The dynamic runtimes register classes on first use, so there is no list to put the class in.
+#[cfg(any(feature="gnustep", feature="mock-runtime"))]
+fn nonlazy_class(_objc_name: &str) -> String {
+    String::new()
+}
+
+///Emits one method list.  `kind` is `INSTANCE` or `CLASS`.
+fn method_list(objc_name: &str, kind: &str, methods: &[(usize, &Method)]) -> String {
+    let entries: String = methods.iter().map(|(index, method)| format!(r#"
+                MethodT {{
+                    name: &METHNAME_{INDEX} as *const u8,
+                    types: &METHTYPE_{INDEX} as *const u8,
+                    imp: {FUNCTION} as *const core::ffi::c_void
+                }},"#,INDEX=index,FUNCTION=method.function)).collect();
+    //Like clang, only export lists that have something in them
+    let attributes = if methods.is_empty() {
+        String::new()
+    }
+    else {
+        format!(r#"#[link_section="__DATA,__objc_const"]
+        #[export_name="_OBJC_$_{KIND}_METHODS_{OBJC_NAME}"]"#,KIND=kind,OBJC_NAME=objc_name)
+    };
+    format!(r#"
+        pub const {KIND}_METHOD_COUNT: usize = {COUNT};
+        {ATTRIBUTES}
+        pub static {KIND}_METHOD_LIST: objr::bindings::_SyncWrapper<MethodListT<{COUNT}>> = objr::bindings::_SyncWrapper(MethodListT {{
+            magic: 24,
+            count: {COUNT},
+            methods: [{ENTRIES}
+            ]
+        }});
+    "#,KIND=kind,COUNT=methods.len(),ATTRIBUTES=attributes,ENTRIES=entries)
+}
+
+///Emits the instance and class method lists for a subclass.
+///
+/// `synthesized` is code for any synthesized accessors, which are emitted into the same module.
+pub fn method_lists(objc_name: &str, methods: &[Method], synthesized: &str) -> String {
+    let mut strings = String::new();
+    for (index, method) in methods.iter().enumerate() {
+        /*todo: The real objc compiler deduplicates these values across different functions.
+        From studying compiled binaries it appears that the *linker* also deduplicates local (`L`) symbols of this type,
+        so I'm uncertain if deduplicating this at the compile phase has any effect really.
+        */
+        strings.push_str(&format!(r#"
+            #[link_section="__TEXT,__objc_methname,cstring_literals"]
+            static METHNAME_{INDEX}: [u8; {SELECTOR_LEN}] = *b"{SELECTOR}\0";
+        "#,INDEX=index,SELECTOR=method.selector,SELECTOR_LEN=method.selector.len() + 1));
+        strings.push_str(&method.method_type(index));
+        strings.push_str(&method.signature_check());
+    }
+    let (class_methods, instance_methods): (Vec<_>, Vec<_>) = methods.iter().enumerate().partition(|(_, m)| m.class_method);
+    //Like clang, tell the runtime to call `.cxx_construct` when allocating, and `.cxx_destruct` when deallocating
+    let has_instance_method = |selector: &str| instance_methods.iter().any(|(_, m)| m.selector == selector);
+    let structor_flags = match (has_instance_method(".cxx_construct"), has_instance_method(".cxx_destruct")) {
+        (true, _) => format!("super::subclass_impl_{OBJC_NAME}::RO_HAS_CXX_STRUCTORS",OBJC_NAME=objc_name),
+        (false, true) => format!("super::subclass_impl_{OBJC_NAME}::RO_HAS_CXX_STRUCTORS | super::subclass_impl_{OBJC_NAME}::RO_HAS_CXX_DTOR_ONLY",OBJC_NAME=objc_name),
+        (false, false) => "0".to_owned(),
+    };
+    let nonlazy = if class_methods.iter().any(|(_, m)| m.selector == "load") {
+        nonlazy_class(objc_name)
+    }
+    else {
+        String::new()
+    };
+    format!(r#"
+        #[allow(non_upper_case_globals,non_snake_case)]
+        mod implpart_method_list_{OBJC_NAME} {{
+            objr::__objc_sublcass_implpart_method_prelude!(MethodT,MethodListT);
+            {STRINGS}
+            {SYNTHESIZED}
+            {INSTANCE}
+            {CLASS}
+            pub const STRUCTOR_FLAGS: u32 = {STRUCTOR_FLAGS};
+            {NONLAZY}
+        }}
+    "#,OBJC_NAME=objc_name,STRINGS=strings,SYNTHESIZED=synthesized,INSTANCE=method_list(objc_name,"INSTANCE",&instance_methods),
+        CLASS=method_list(objc_name,"CLASS",&class_methods),STRUCTOR_FLAGS=structor_flags,NONLAZY=nonlazy)
+}
@@ -0,0 +1,327 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Implements declared properties for `objc_subclass!`.
+
+use proc_macro::{TokenStream, TokenTree, Delimiter};
+use crate::declarations::parse_to_property_type_encoding;
+use crate::methods::Method;
+
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+enum Ownership {
+    Strong,
+    Copy,
+    Weak,
+    ///`assign` or `unsafe_unretained`
+    Assign,
+}
+
+pub struct Property {
+    pub name: String,
+    ///ObjC type, as written
+    objc_type: String,
+    readonly: bool,
+    nonatomic: bool,
+    ownership: Option<Ownership>,
+    getter: Option<String>,
+    setter: Option<String>,
+    ///Set by `@synthesize`
+    ivar: Option<String>,
+}
+
+impl Property {
+    fn type_encoding(&self) -> String {
+        parse_to_property_type_encoding(&self.objc_type)
+    }
+    fn is_object(&self) -> bool {
+        self.type_encoding().starts_with('@')
+    }
+    ///Objects default to strong, like ARC
+    fn ownership(&self) -> Ownership {
+        self.ownership.unwrap_or(if self.is_object() { Ownership::Strong } else { Ownership::Assign })
+    }
+    fn getter(&self) -> String {
+        self.getter.clone().unwrap_or_else(|| self.name.clone())
+    }
+    fn setter(&self) -> String {
+        self.setter.clone().unwrap_or_else(|| {
+            let mut chars = self.name.chars();
+            let first = chars.next().unwrap().to_uppercase();
+            format!("set{}{}:",first,chars.as_str())
+        })
+    }
+    ///The attribute string, like `T@"NSString",&,N,V_title`.  Matches the order clang uses.
+    pub fn attributes(&self) -> String {
+        let mut attributes = vec![format!("T{}",self.type_encoding())];
+        if self.readonly {
+            attributes.push("R".to_owned());
+        }
+        match self.ownership {
+            Some(Ownership::Copy) => attributes.push("C".to_owned()),
+            Some(Ownership::Weak) => attributes.push("W".to_owned()),
+            //clang doesn't write retain for readonly properties, which have no setter to retain with
+            Some(Ownership::Strong) if !self.readonly => attributes.push("&".to_owned()),
+            None if self.is_object() && !self.readonly => attributes.push("&".to_owned()),
+            //clang only writes assign for objects when it's explicit
+            _ => (),
+        }
+        if self.nonatomic {
+            attributes.push("N".to_owned());
+        }
+        if let Some(getter) = &self.getter {
+            attributes.push(format!("G{}",getter));
+        }
+        if let Some(setter) = &self.setter {
+            attributes.push(format!("S{}",setter));
+        }
+        if let Some(ivar) = &self.ivar {
+            attributes.push(format!("V{}",ivar));
+        }
+        attributes.join(",")
+    }
+}
+
+///Splits the stream into statements separated by `;`
+fn statements(stream: TokenStream) -> Vec<Vec<TokenTree>> {
+    //tokens passed through macro_rules may arrive in invisible groups
+    fn flatten(stream: TokenStream, into: &mut Vec<TokenTree>) {
+        for token in stream {
+            match token {
+                TokenTree::Group(g) if g.delimiter() == Delimiter::None => flatten(g.stream(), into),
+                other => into.push(other),
+            }
+        }
+    }
+    let mut tokens = Vec::new();
+    flatten(stream, &mut tokens);
+    tokens.split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ';'))
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_vec())
+        .collect()
+}
+
+fn parse_property(tokens: &[TokenTree]) -> Result<Property,String> {
+    let mut property = Property { name: String::new(), objc_type: String::new(), readonly: false, nonatomic: false, ownership: None, getter: None, setter: None, ivar: None };
+    let mut rest = &tokens[2..];
+    if let Some(TokenTree::Group(g)) = rest.first() {
+        if g.delimiter() == Delimiter::Parenthesis {
+            let attributes = g.stream().into_iter().collect::<Vec<_>>();
+            for attribute in attributes.split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ',')) {
+                let attribute: String = attribute.iter().map(|t| t.to_string()).collect();
+                match attribute.as_str() {
+                    "nonatomic" => property.nonatomic = true,
+                    "atomic" => property.nonatomic = false,
+                    "readonly" => property.readonly = true,
+                    "readwrite" => property.readonly = false,
+                    "strong" | "retain" => property.ownership = Some(Ownership::Strong),
+                    "copy" => property.ownership = Some(Ownership::Copy),
+                    "weak" => property.ownership = Some(Ownership::Weak),
+                    "assign" | "unsafe_unretained" => property.ownership = Some(Ownership::Assign),
+                    "nullable" | "nonnull" | "null_resettable" | "null_unspecified" => (),
+                    "class" => return Err("Class properties are not supported".to_owned()),
+                    other => {
+                        if let Some(getter) = other.strip_prefix("getter=") {
+                            property.getter = Some(getter.to_owned());
+                        }
+                        else if let Some(setter) = other.strip_prefix("setter=") {
+                            property.setter = Some(setter.to_owned());
+                        }
+                        else {
+                            return Err(format!("Unknown property attribute {}",other));
+                        }
+                    }
+                }
+            }
+            rest = &rest[1..];
+        }
+    }
+    match rest.split_last() {
+        Some((TokenTree::Ident(name), objc_type)) if !objc_type.is_empty() => {
+            property.name = name.to_string();
+            property.objc_type = objc_type.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(" ");
+        }
+        _ => return Err(format!("Expected a type and name after @property, got {}",TokenStream::from_iter(rest.iter().cloned()))),
+    }
+    Ok(property)
+}
+
+///Parses `@property(attributes) Type name;` and `@synthesize name = ivar;` statements.
+pub fn parse_properties(stream: TokenStream) -> Result<Vec<Property>,String> {
+    let mut properties: Vec<Property> = Vec::new();
+    for statement in statements(stream) {
+        let keyword = match (statement.first(), statement.get(1)) {
+            (Some(TokenTree::Punct(p)), Some(TokenTree::Ident(keyword))) if p.as_char() == '@' => keyword.to_string(),
+            _ => return Err(format!("Expected @property or @synthesize, got {}",TokenStream::from_iter(statement.iter().cloned()))),
+        };
+        match keyword.as_str() {
+            "property" => properties.push(parse_property(&statement)?),
+            "synthesize" => {
+                //`@synthesize name` uses an ivar of the same name, `@synthesize name = ivar` names the ivar
+                let (name, ivar) = match &statement[2..] {
+                    [TokenTree::Ident(name)] => (name.to_string(), name.to_string()),
+                    [TokenTree::Ident(name), TokenTree::Punct(p), TokenTree::Ident(ivar)] if p.as_char() == '=' => (name.to_string(), ivar.to_string()),
+                    _ => return Err(format!("Expected `@synthesize name = ivar`, got {}",TokenStream::from_iter(statement.iter().cloned()))),
+                };
+                let property = properties.iter_mut().find(|p| p.name == name).ok_or_else(|| format!("@synthesize {} without a matching @property",name))?;
+                if property.ownership() == Ownership::Weak {
+                    return Err(format!("Can't synthesize weak property {}",name));
+                }
+                property.ivar = Some(ivar);
+            }
+            other => return Err(format!("Expected @property or @synthesize, got @{}",other)),
+        }
+    }
+    Ok(properties)
+}
+
+/**
+Emits the synthesized accessors, and appends them to `methods`.
+
+Like clang, strong and copy ivars are released by `.cxx_destruct` when the object is deallocated.  If the class already
+has a `.cxx_destruct`, it's replaced by one that releases the ivars and then calls it, so a payload that is itself
+the ivar sees nil rather than a released object.
+*/
+pub fn synthesize(identifier: &str, objc_name: &str, properties: &[Property], methods: &mut Vec<Method>) -> Result<String,String> {
+    let mut code = String::new();
+    let mut releases = String::new();
+    let mut released_ivars = Vec::new();
+    for property in properties {
+        let ivar = match &property.ivar {
+            Some(ivar) => ivar,
+            None => continue,
+        };
+        let name = &property.name;
+        let ivar_type = format!("super::ivar_type_{OBJC_NAME}_{IVAR}",OBJC_NAME=objc_name,IVAR=ivar);
+        //methods are declared with `id`, only the property names the class
+        let method_type = if property.is_object() { "id".to_owned() } else { property.objc_type.clone() };
+        let getter_declaration = format!("-({}) {}",method_type,property.getter());
+        let setter_declaration = format!("-(void) {}({}) value",property.setter(),method_type);
+        let offset = format!("super::ivar_list_{OBJC_NAME}::OBJC_IVAR_{IVAR}.get() as isize",OBJC_NAME=objc_name,IVAR=ivar);
+        let (getter, setter) = if property.is_object() {
+            code.push_str(&format!("const _: () = assert!(std::mem::size_of::<{IVAR_TYPE}>() == std::mem::size_of::<*const core::ffi::c_void>(), \"ivar for object property `{NAME}` must be pointer-sized, like Option<StrongCell<T>>\");\n",
+                IVAR_TYPE=ivar_type,NAME=name));
+            //the runtime functions handle retain/release, copy and atomicity for us
+            (format!(r#"
+                extern "C" fn synthesized_get_{NAME}(objc_self: &super::{IDENTIFIER}, sel: objr::bindings::Sel) -> *const core::ffi::c_void {{
+                    unsafe {{ objr::bindings::_objc_getProperty(objc_self as *const _ as *mut core::ffi::c_void, sel, {OFFSET}, {ATOMIC}) }}
+                }}"#,NAME=name,IDENTIFIER=identifier,OFFSET=offset,ATOMIC=!property.nonatomic),
+            format!(r#"
+                extern "C" fn synthesized_set_{NAME}(objc_self: &super::{IDENTIFIER}, sel: objr::bindings::Sel, value: *const core::ffi::c_void) {{
+                    unsafe {{ objr::bindings::_objc_setProperty(objc_self as *const _ as *mut core::ffi::c_void, sel, {OFFSET}, value, {ATOMIC}, {COPY}) }}
+                }}"#,NAME=name,IDENTIFIER=identifier,OFFSET=offset,ATOMIC=!property.nonatomic,COPY=property.ownership() == Ownership::Copy))
+        }
+        else {
+            (format!(r#"
+                extern "C" fn synthesized_get_{NAME}(objc_self: &super::{IDENTIFIER}, _sel: objr::bindings::Sel) -> {IVAR_TYPE} {{
+                    *objc_self.{IVAR}()
+                }}"#,IVAR_TYPE=ivar_type,NAME=name,IDENTIFIER=identifier,IVAR=ivar),
+            format!(r#"
+                extern "C" fn synthesized_set_{NAME}(objc_self: &super::{IDENTIFIER}, _sel: objr::bindings::Sel, value: {IVAR_TYPE}) {{
+                    unsafe {{ *objc_self.{IVAR}_mut() = value; }}
+                }}"#,IVAR_TYPE=ivar_type,NAME=name,IDENTIFIER=identifier,IVAR=ivar))
+        };
+        code.push_str(&getter);
+        methods.push(Method::new(&getter_declaration, format!("synthesized_get_{}",name))?);
+        if !property.readonly {
+            code.push_str(&setter);
+            methods.push(Method::new(&setter_declaration, format!("synthesized_set_{}",name))?);
+        }
+        let owned = matches!(property.ownership(), Ownership::Strong | Ownership::Copy);
+        if property.is_object() && owned && !released_ivars.contains(ivar) {
+            //storing nil releases the old value
+            releases.push_str(&format!(r#"
+                    objr::bindings::_objc_setProperty(objc_self as *const _ as *mut core::ffi::c_void, sel, {OFFSET}, core::ptr::null(), false, false);"#,OFFSET=offset));
+            released_ivars.push(ivar.clone());
+        }
+    }
+    if !releases.is_empty() {
+        let existing = methods.iter_mut().find(|m| !m.class_method && m.selector == ".cxx_destruct");
+        let call_existing = match &existing {
+            Some(existing) => format!(r#"
+                    let existing: extern "C" fn(&super::{IDENTIFIER}, objr::bindings::Sel) = {FUNCTION};
+                    existing(objc_self, sel);"#,IDENTIFIER=identifier,FUNCTION=existing.function),
+            None => String::new(),
+        };
+        code.push_str(&format!(r#"
+                extern "C" fn synthesized_cxx_destruct(objc_self: &super::{IDENTIFIER}, sel: objr::bindings::Sel) {{
+                    unsafe {{{RELEASES}
+                    }}{CALL_EXISTING}
+                }}"#,IDENTIFIER=identifier,CALL_EXISTING=call_existing,RELEASES=releases));
+        match existing {
+            Some(existing) => existing.function = "synthesized_cxx_destruct".to_owned(),
+            None => methods.push(Method::new("-(void) .cxx_destruct", "synthesized_cxx_destruct".to_owned())?),
+        }
+    }
+    Ok(code)
+}
+
+///Emits the property list for a subclass.
+pub fn property_list(objc_name: &str, properties: &[Property]) -> String {
+    let mut statics = String::new();
+    let mut entries = String::new();
+    for (index, property) in properties.iter().enumerate() {
+        let attributes = property.attributes();
+        statics.push_str(&format!(r#"
+            #[link_section="__TEXT,__objc_methname,cstring_literals"]
+            static NAME_{INDEX}: [u8; {NAME_LEN}] = *b"{NAME}\0";
+            #[link_section="__TEXT,__objc_methname,cstring_literals"]
+            static ATTRIBUTES_{INDEX}: [u8; {ATTRIBUTES_LEN}] = *b"{ATTRIBUTES}\0";
+        "#,INDEX=index,NAME=property.name,NAME_LEN=property.name.len() + 1,
+            ATTRIBUTES=attributes.replace('"',"\\\""),ATTRIBUTES_LEN=attributes.len() + 1));
+        entries.push_str(&format!(r#"
+                PropertyT {{ name: &NAME_{INDEX} as *const u8, attributes: &ATTRIBUTES_{INDEX} as *const u8 }},"#,INDEX=index));
+    }
+    //Like clang, only export a list that has something in it
+    let attributes = if properties.is_empty() {
+        String::new()
+    }
+    else {
+        format!(r#"#[link_section="__DATA,__objc_const"]
+        #[export_name="_OBJC_$_PROP_LIST_{OBJC_NAME}"]"#,OBJC_NAME=objc_name)
+    };
+    format!(r#"
+        #[allow(non_upper_case_globals,non_snake_case)]
+        mod property_list_{OBJC_NAME} {{
+            type PropertyT = super::subclass_impl_{OBJC_NAME}::PropertyT;
+            type PropertyListT<const SIZE: usize> = super::subclass_impl_{OBJC_NAME}::PropertyListT<SIZE>;
+            {STATICS}
+            pub const PROPERTY_COUNT: usize = {COUNT};
+            {ATTRIBUTES}
+            pub static PROPERTY_LIST: objr::bindings::_SyncWrapper<PropertyListT<{COUNT}>> = objr::bindings::_SyncWrapper(PropertyListT {{
+                //size of property_t
+                entsize: 16,
+                count: {COUNT},
+                properties: [{ENTRIES}
+                ]
+            }});
+        }}
+    "#,OBJC_NAME=objc_name,STATICS=statics,COUNT=properties.len(),ATTRIBUTES=attributes,ENTRIES=entries)
+}
+
+#[cfg(test)]
+fn parse_str(s: &str) -> Property {
+    //proc_macro isn't available in unit tests, so build the property by hand
+    let mut words = s.split_whitespace();
+    let name = words.next_back().unwrap().to_owned();
+    let objc_type = words.collect::<Vec<_>>().join(" ");
+    Property { name, objc_type, readonly: false, nonatomic: true, ownership: None, getter: None, setter: None, ivar: None }
+}
+
+#[test] fn attributes() {
+    let mut title = parse_str("NSString * title");
+    title.ivar = Some("_title".to_owned());
+    assert_eq!(title.attributes(), "T@\"NSString\",&,N,V_title");
+    title.readonly = true;
+    assert_eq!(title.attributes(), "T@\"NSString\",R,N,V_title");
+    title.ownership = Some(Ownership::Strong);
+    assert_eq!(title.attributes(), "T@\"NSString\",R,N,V_title");
+    title.ownership = Some(Ownership::Copy);
+    title.readonly = true;
+    assert_eq!(title.attributes(), "T@\"NSString\",R,C,N,V_title");
+    let mut count = parse_str("unsigned int count");
+    count.nonatomic = false;
+    assert_eq!(count.attributes(), "TI");
+    assert_eq!(count.setter(), "setCount:");
+    let mut enabled = parse_str("BOOL enabled");
+    enabled.getter = Some("isEnabled".to_owned());
+    assert_eq!(enabled.attributes(), "TB,N,GisEnabled");
+}
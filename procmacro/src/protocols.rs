@@ -0,0 +1,98 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Implements the protocol list for `objc_subclass!`.
+
+use proc_macro::{TokenStream, TokenTree, Delimiter};
+
+///Parses `NSCopying, MTKViewDelegate` out of the protocol block.
+pub fn parse_protocols(stream: TokenStream) -> Result<Vec<String>,String> {
+    let mut protocols = Vec::new();
+    let mut iter = stream.into_iter();
+    loop {
+        let protocol = match iter.next() {
+            None => break,
+            Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::None => g.stream().to_string(),
+            Some(TokenTree::Ident(i)) => i.to_string(),
+            Some(o) => return Err(format!("Expected protocol name, got {:?}",o)),
+        };
+        protocols.push(protocol);
+        match iter.next() {
+            None => break,
+            Some(TokenTree::Punct(p)) if p.as_char() == ',' => (),
+            Some(o) => return Err(format!("Expected `,` after {}, got {:?}",protocols.last().unwrap(),o)),
+        }
+    }
+    Ok(protocols)
+}
+
+///Emits the `__objc_protolist` entry, so the runtime reads our protocol definition (or finds the existing one).
+#[cfg(not(any(feature="gnustep", feature="mock-runtime")))]
+fn protolist_entry(protocol: &str) -> String {
+    format!(r#"
+        #[link_section="__DATA,__objc_protolist,coalesced,no_dead_strip"]
+        #[used]
+        static LABEL_{PROTOCOL}: objr::bindings::_SyncWrapper<*const ProtocolT> = objr::bindings::_SyncWrapper(&PROTOCOL_{PROTOCOL}.0);
+    "#,PROTOCOL=protocol)
+}
+
+///The dynamic runtimes look protocols up by name when we register the class.
+#[cfg(any(feature="gnustep", feature="mock-runtime"))]
+fn protolist_entry(_protocol: &str) -> String {
+    String::new()
+}
+
+///Emits the protocol list for a subclass.
+pub fn protocol_list(objc_name: &str, protocols: &[String]) -> String {
+    let mut statics = String::new();
+    let mut entries = String::new();
+    for protocol in protocols {
+        statics.push_str(&format!(r#"
+            #[link_section="__TEXT,__objc_classname,cstring_literals"]
+            static NAME_{PROTOCOL}: [u8; {NAME_LEN}] = *b"{PROTOCOL}\0";
+            //clang emits this as a weak hidden `__OBJC_PROTOCOL_$_{PROTOCOL}` in each object file that uses it, which frameworks don't export.
+            //We can't emit weak symbols, so each class gets its own copy.  The runtime keeps whichever definition it saw first
+            //(usually the framework's), and checks conformance by name, so this is equivalent.
+            #[link_section="__DATA,__objc_data"]
+            pub static PROTOCOL_{PROTOCOL}: objr::bindings::_SyncWrapper<ProtocolT> = objr::bindings::_SyncWrapper(ProtocolT {{
+                isa: std::ptr::null(),
+                mangled_name: &NAME_{PROTOCOL} as *const u8,
+                protocols: std::ptr::null(),
+                instance_methods: std::ptr::null(),
+                class_methods: std::ptr::null(),
+                optional_instance_methods: std::ptr::null(),
+                optional_class_methods: std::ptr::null(),
+                instance_properties: std::ptr::null(),
+                size: std::mem::size_of::<ProtocolT>() as u32,
+                flags: 0,
+                extended_method_types: std::ptr::null(),
+                demangled_name: std::ptr::null(),
+                class_properties: std::ptr::null(),
+            }});
+            {PROTOLIST}
+        "#,PROTOCOL=protocol,NAME_LEN=protocol.len() + 1,PROTOLIST=protolist_entry(protocol)));
+        entries.push_str(&format!("\n                &PROTOCOL_{PROTOCOL}.0 as *const ProtocolT,",PROTOCOL=protocol));
+    }
+    //Like clang, only export a list that has something in it
+    let attributes = if protocols.is_empty() {
+        String::new()
+    }
+    else {
+        format!(r#"#[link_section="__DATA,__objc_const"]
+        #[export_name="_OBJC_CLASS_PROTOCOLS_$_{OBJC_NAME}"]"#,OBJC_NAME=objc_name)
+    };
+    format!(r#"
+        #[allow(non_upper_case_globals,non_snake_case)]
+        mod protocol_list_{OBJC_NAME} {{
+            type ProtocolT = super::subclass_impl_{OBJC_NAME}::ProtocolT;
+            type ProtocolListT<const SIZE: usize> = super::subclass_impl_{OBJC_NAME}::ProtocolListT<SIZE>;
+            {STATICS}
+            pub const PROTOCOL_COUNT: usize = {COUNT};
+            {ATTRIBUTES}
+            pub static PROTOCOL_LIST: objr::bindings::_SyncWrapper<ProtocolListT<{COUNT}>> = objr::bindings::_SyncWrapper(ProtocolListT {{
+                count: {COUNT},
+                list: [{ENTRIES}
+                ]
+            }});
+        }}
+    "#,OBJC_NAME=objc_name,STATICS=statics,COUNT=protocols.len(),ATTRIBUTES=attributes,ENTRIES=entries)
+}
@@ -4,6 +4,7 @@
 extern crate proc_macro;
 
 ///An expression for a `Sel` with a dyld-time static
+#[cfg(not(any(feature="gnustep", feature="mock-runtime")))]
 pub fn sel_expression(selector: &str) -> String {
     format!(
         r#"
@@ -23,6 +24,18 @@ pub fn sel_expression(selector: &str) -> String {
         ,selector=selector,len=selector.len() + 1)
 }
 
+///An expression for a `Sel`, registered on first use.  Without dyld (libobjc2, mock-runtime) nothing fixes up our selector references.
+#[cfg(any(feature="gnustep", feature="mock-runtime"))]
+pub fn sel_expression(selector: &str) -> String {
+    format!(
+        r#"
+    {{
+        static SELECTOR: ::core::sync::atomic::AtomicPtr<core::ffi::c_void> = ::core::sync::atomic::AtomicPtr::new(::core::ptr::null_mut());
+        ::objr::bindings::Sel::_cached(&SELECTOR, b"{selector}\0")
+    }}"#
+        ,selector=selector)
+}
+
 ///Declares a "partial" fn like `unsafe fn my_selector() -> ::objr::bindings::Sel` with no trailing `;`
 pub fn make_fn_partial(fn_name: &str) -> String {
     format!("unsafe fn {fn_name}() -> ::objr::bindings::Sel",fn_name=fn_name)
@@ -1,6 +1,7 @@
 //SPDX-License-Identifier: MIT OR Apache-2.0
 
 ///Emits the code we need for a static string expression.
+#[cfg(not(any(feature="gnustep", feature="mock-runtime")))]
 pub fn static_string(string_literal: &str) -> String {
 
     format!(r#"
@@ -62,4 +63,17 @@ pub fn static_string(string_literal: &str) -> String {
 		codegen_workaround()
 	}}
     "#,STRING_LITERAL=string_literal,LITERAL_LENGTH=string_literal.len() + 1,LITERAL_LENGTH_MINUS_ONE=string_literal.len())
-}
\ No newline at end of file
+}
+
+///Emits the code we need for a static string expression.
+///
+/// We don't know how to lay out a constant string for libobjc2 (or the mock runtime), so the string is created on first use instead.
+#[cfg(any(feature="gnustep", feature="mock-runtime"))]
+pub fn static_string(string_literal: &str) -> String {
+    format!(r#"
+    {{
+		static STRING: ::core::sync::atomic::AtomicPtr<core::ffi::c_void> = ::core::sync::atomic::AtomicPtr::new(::core::ptr::null_mut());
+		objr::foundation::NSString::_cached(&STRING, "{STRING_LITERAL}")
+	}}
+    "#,STRING_LITERAL=string_literal)
+}
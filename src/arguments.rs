@@ -6,8 +6,11 @@
 use super::bindings::*;
 use std::ffi::c_void;
 use std::fmt::Debug;
-use std::mem::size_of;
+use crate::encode::_VerifyEncode;
+#[cfg(feature="verify-sends")]
+use crate::encode::{Encode, Encoding};
 
+#[cfg(not(any(feature="gnustep", feature="mock-runtime")))]
 #[link(name="objc", kind="dylib")]
 extern "C" {
     fn objc_msgSend();
@@ -17,6 +20,15 @@ extern "C" {
     fn objc_msgSendSuper2_stret();
 }
 
+//libobjc2 does not have trampolines like objc_msgSend.  Instead, we look up the IMP and call it ourselves.
+#[cfg(all(feature="gnustep", not(feature="mock-runtime")))]
+#[link(name="objc", kind="dylib")]
+extern "C" {
+    fn objc_msg_lookup(receiver: *mut c_void, sel: Sel) -> unsafe extern "C" fn();
+    fn objc_msg_lookup_super(sup: *const ObjcSuper, sel: Sel) -> unsafe extern "C" fn();
+    fn class_getSuperclass(class: *const AnyClass) -> *const AnyClass;
+}
+
 //defined in https://opensource.apple.com/source/objc4/objc4-371.2/runtime/message.h
 //This is the first argument to `objc_msgSendSuper2` instead of the receiver
 #[repr(C)]
@@ -31,10 +43,80 @@ struct ObjcSuper {
     class: *const AnyClass,
 }
 
+///Finds the function to call for sending `sel` to `receiver`, returning `R`.
+///
+/// Call the result as `imp(receiver, sel, args...)`.
+#[cfg(not(any(feature="gnustep", feature="mock-runtime")))]
+#[inline(always)] unsafe fn msg_send_fn<R: Primitive>(_receiver: *mut c_void, _sel: Sel) -> unsafe extern "C" fn() {
+    if cfg!(target_arch="x86_64") {
+        if !R::RETURNS_IN_MEMORY {
+            objc_msgSend
+        }
+        else {
+            objc_msgSend_stret
+        }
+        /*NOTE: `float` and `double` come back in xmm0 like any other SSE value, so they use plain objc_msgSend.
+
+        objc_msgSend_fpret is only for `long double`, which comes back on the x87 stack, and objc_msgSend_fp2ret is
+        for `_Complex long double`.  Rust has no type for either, and an extern "C" fn can't read the x87 stack,
+        so they can't be Primitive and we never need those variants.  arm64 has no variants at all.
+       */
+    }
+    else {
+        objc_msgSend
+    }
+}
+#[cfg(all(feature="gnustep", not(feature="mock-runtime")))]
+#[inline(always)] unsafe fn msg_send_fn<R: Primitive>(receiver: *mut c_void, sel: Sel) -> unsafe extern "C" fn() {
+    //The IMP is an ordinary C function, so the C ABI takes care of struct returns for us.
+    objc_msg_lookup(receiver, sel)
+}
+
+///Finds the function to call for sending `sel` to super, returning `R`.
+///
+/// Call the result as `imp(first, sel, args...)` where `first` is the first element of the return value.
+/// `objc_super` must outlive the call.
+#[cfg(not(any(feature="gnustep", feature="mock-runtime")))]
+#[inline(always)] unsafe fn msg_send_super_fn<R: Primitive>(objc_super: &ObjcSuper, _sel: Sel) -> (*mut c_void, unsafe extern "C" fn()) {
+    let imp = if cfg!(target_arch="x86_64") {
+        if !R::RETURNS_IN_MEMORY {
+            objc_msgSendSuper2
+        }
+        else {
+            objc_msgSendSuper2_stret
+        }
+        /*NOTE: I verified in clang that, for "long double" case, we still use objc_msgSendSuper2.  I have no explanation
+        for why there is no fpret verison.  However since we don't deal with fpret anyway, this is somewhat irrelevant.
+         */
+    }
+    else {
+        objc_msgSendSuper2
+    };
+    (objc_super as *const ObjcSuper as *mut c_void, imp)
+}
+#[cfg(all(feature="gnustep", not(feature="mock-runtime")))]
+#[inline(always)] unsafe fn msg_send_super_fn<R: Primitive>(objc_super: &ObjcSuper, sel: Sel) -> (*mut c_void, unsafe extern "C" fn()) {
+    //Unlike objc_msgSendSuper2, libobjc2 wants the class to start searching from, which is the superclass.
+    let lookup_super = ObjcSuper {
+        receiver: objc_super.receiver,
+        class: class_getSuperclass(objc_super.class),
+    };
+    (objc_super.receiver, objc_msg_lookup_super(&lookup_super, sel))
+}
+#[cfg(feature="mock-runtime")]
+#[inline(always)] unsafe fn msg_send_fn<R: Primitive>(receiver: *mut c_void, sel: Sel) -> unsafe extern "C" fn() {
+    crate::mock::runtime::msg_send_fn(receiver, sel)
+}
+#[cfg(feature="mock-runtime")]
+#[inline(always)] unsafe fn msg_send_super_fn<R: Primitive>(objc_super: &ObjcSuper, sel: Sel) -> (*mut c_void, unsafe extern "C" fn()) {
+    (objc_super.receiver, crate::mock::runtime::msg_send_super_fn(objc_super.receiver, objc_super.class, sel))
+}
+
 ///Trait describing a type that can be used as arugments.  Generally, this is a tuple of all the arguments to some method.
 ///
 /// This type is sealed; you may not implement it from outside the crate.
-/// All implementations are provided via macro.
+/// All implementations are provided via macro.  Tuples are `Debug` only up to 12 elements, so 13 to 16 arguments
+/// are passed wrapped in [LongArguments].
 pub trait Arguments: Sized + Debug + crate::private::Sealed {
     ///Implementation deatil of [PerformsSelector::perform_primitive]
     unsafe fn invoke_primitive<R: Primitive>(receiver: *mut c_void, sel: Sel, pool: &ActiveAutoreleasePool, args: Self) -> R;
@@ -83,6 +165,33 @@ must be opted in with [ArguableBehavior::assume_nonmut_perform()]
 //primitive types can have exclusive references passed
 unsafe impl<O: Arguable> Arguable for &mut O {}
 unsafe impl<O: Arguable> Arguable for *mut O {}
+/*Nullable arguments are common enough that they're arguable directly, rather than via `assume_nonmut_perform`.
+These are FFI-safe because `None` is packed as nil.
+ */
+unsafe impl<O: Arguable> Arguable for Option<&O> {}
+unsafe impl<O: Arguable> Arguable for Option<&mut O> {}
+
+///Types that convert to an [Arguable] when they're passed in the arguments of the `perform` family, so call sites don't have to.
+///
+/// Every [Arguable] converts to itself.  `&StrongCell<T>` and `&AutoreleasedCell<T>` are passed as the object they hold.
+/// As with [ArguableBehavior::assume_nonmut_perform()], passing a shared reference assumes the method doesn't mutate that argument.
+pub trait IntoArguable {
+    ///The type that's actually passed.
+    type Arguable: Arguable;
+    fn into_arguable(self) -> Self::Arguable;
+}
+impl<A: Arguable> IntoArguable for A {
+    type Arguable = A;
+    #[inline] fn into_arguable(self) -> A { self }
+}
+impl<T: ObjcInstance + Arguable> IntoArguable for &StrongCell<T> {
+    type Arguable = *mut T;
+    #[inline] fn into_arguable(self) -> *mut T { &**self as *const T as *mut T }
+}
+impl<'a, T: ObjcInstance + Arguable> IntoArguable for &AutoreleasedCell<'a, T> {
+    type Arguable = *mut T;
+    #[inline] fn into_arguable(self) -> *mut T { &**self as *const T as *mut T }
+}
 
 
 pub trait ArguableBehavior {
@@ -132,7 +241,71 @@ impl<A: Arguable> ArguableBehavior for Option<&A> {
 /// whereas return types can be const.
 ///
 /// This cannot inherit from Arguable because various types are primitives (for example, `*const Struct`) but we only allow arguing `*mut Struct`.
-pub unsafe trait Primitive: Sized {
+///
+/// # Structs
+/// `#[repr(C)]` structs can be passed and returned by value, like `CGRect` or `NSRange`.  Derive this trait (and [Arguable])
+/// with `#[derive(ObjcPrimitive)]`, which works out [Primitive::RETURNS_IN_MEMORY] from the fields:
+///
+/// ```
+/// use objr::bindings::*;
+/// #[derive(ObjcPrimitive,ObjcEncode,Debug)]
+/// #[repr(C)]
+/// pub struct NSRange {
+///     pub location: usize,
+///     pub length: usize,
+/// }
+/// #[derive(ObjcPrimitive,ObjcEncode,Debug)]
+/// #[repr(C)]
+/// pub struct MTLRegion {
+///     pub origin: [usize; 3],
+///     pub size: [usize; 3],
+/// }
+/// assert!(!NSRange::RETURNS_IN_MEMORY);
+/// assert!(MTLRegion::RETURNS_IN_MEMORY);
+/// ```
+///
+/// Each field must be `Primitive`, or an array of them.  Note that arguments also need `Debug`, and [Encode] with the `verify-sends` feature.
+pub unsafe trait Primitive: Sized + _VerifyEncode {
+    ///Whether the x86_64 ABI returns this type in memory, via a hidden pointer argument, rather than in registers.
+    ///
+    /// Such types are returned with `objc_msgSend_stret`, so getting this wrong is UB.  It depends on how the type is classified,
+    /// not just its size: a 16-byte struct is returned in registers, unless a field is misaligned by `#[repr(packed)]`.
+    ///
+    /// The default is right for scalars and for structs without packed fields.  It has no effect on other architectures.
+    const RETURNS_IN_MEMORY: bool = std::mem::size_of::<Self>() > 16;
+}
+
+///Used by `#[derive(ObjcPrimitive)]` to classify the fields of a struct, which may be arrays.
+#[doc(hidden)]
+pub trait _PrimitiveField {
+    const IN_MEMORY: bool;
+}
+impl<P: Primitive> _PrimitiveField for P {
+    const IN_MEMORY: bool = P::RETURNS_IN_MEMORY;
+}
+impl<F: _PrimitiveField, const N: usize> _PrimitiveField for [F; N] {
+    const IN_MEMORY: bool = F::IN_MEMORY;
+}
+
+///Used by `#[derive(ObjcPrimitive)]`.  Whether a `#[repr(C, packed(pack))]` struct with these `(size, align)` fields has any field
+/// that is not at a multiple of its natural alignment.
+#[doc(hidden)]
+pub const fn _packed_misaligned(fields: &[(usize, usize)], pack: usize) -> bool {
+    let mut offset = 0;
+    let mut f = 0;
+    while f < fields.len() {
+        let (size, align) = fields[f];
+        let packed_align = if align < pack { align } else { pack };
+        if offset % packed_align != 0 {
+            offset += packed_align - offset % packed_align;
+        }
+        if offset % align != 0 {
+            return true;
+        }
+        offset += size;
+        f += 1;
+    }
+    false
 }
 
 unsafe impl<P: Primitive> Primitive for *const P {}
@@ -153,6 +326,8 @@ unsafe impl Arguable for *const c_void {}
 
 unsafe impl Primitive for f64 {}
 unsafe impl Arguable for f64 {}
+unsafe impl Primitive for f32 {}
+unsafe impl Arguable for f32 {}
 
 unsafe impl Primitive for () {}
 unsafe impl Arguable for () {}
@@ -177,79 +352,94 @@ unsafe impl Primitive for i16 {}
 unsafe impl Arguable for i8 {}
 unsafe impl Primitive for i8 {}
 
+//NSUInteger and NSInteger
+unsafe impl Arguable for usize {}
+unsafe impl Primitive for usize {}
+unsafe impl Arguable for isize {}
+unsafe impl Primitive for isize {}
+
+///Checks a send against the method's type encoding, see the `verify` module.
+///
+/// `$error` is the type of a trailing error argument, if any.
+#[cfg(feature="verify-sends")]
+macro_rules! verify_send {
+    ($receiver:expr, $super_class:expr, $sel:expr, $return_type:expr, [$($type:ty),*] $(, $error:ty)?) => {
+        crate::verify::verify_send($receiver, $super_class, $sel, $return_type,
+            &[$((<$type as Encode>::ENCODING, std::mem::size_of::<$type>()),)* $((<$error as Encode>::ENCODING, std::mem::size_of::<$error>()))?])
+    }
+}
+#[cfg(not(feature="verify-sends"))]
+macro_rules! verify_send {
+    ($($tokens:tt)*) => {}
+}
+
+///Warns if there's no autorelease pool for the send, with the `verify-pools` feature.
+#[cfg(feature="verify-pools")]
+macro_rules! verify_pool {
+    () => { crate::autorelease::check_pool_exists() }
+}
+#[cfg(not(feature="verify-pools"))]
+macro_rules! verify_pool {
+    () => {}
+}
+
 ///Implementation macro for declaring [Argument] types.
+///
+/// `@for` implements it for `$args`, which is destructured with `$pattern`.
 macro_rules! arguments_impl {
     (
         $($identifier:ident : $type:ident),*
+    ) => (
+        arguments_impl!(@for ($($type,)*), ($($identifier,)*), $($identifier : $type),*);
+    );
+    (
+        @for $args:ty, $pattern:pat, $($identifier:ident : $type:ident),*
     ) => (
         //seal the type
-        impl<$($type:Arguable),*> crate::objr::private::Sealed for ($($type,)*) where $($type: Debug),* {}
-        impl<$($type:Arguable),*> Arguments for ($($type,)*) where $($type: Debug),* {
-           #[inline] unsafe fn invoke_primitive<R: Primitive>(obj: *mut c_void, sel: Sel, _pool: &ActiveAutoreleasePool, ($($identifier,)*): Self) -> R {
+        impl<$($type:IntoArguable),*> crate::objr::private::Sealed for $args where $($type: Debug, <$type as IntoArguable>::Arguable: _VerifyEncode),* {}
+        impl<$($type:IntoArguable),*> Arguments for $args where $($type: Debug, <$type as IntoArguable>::Arguable: _VerifyEncode),* {
+           #[inline] unsafe fn invoke_primitive<R: Primitive>(obj: *mut c_void, sel: Sel, _pool: &ActiveAutoreleasePool, $pattern: Self) -> R {
+                verify_send!(obj, None, sel, R::ENCODING, [$(<$type as IntoArguable>::Arguable),*]);
+                verify_pool!();
                //autoreleasepool is encouraged by signature but not used
-
-                let impcast = if cfg!(target_arch="x86_64") {
-                    //this condition seems to broadly agree with clang
-                    if size_of::<R>() <= 16 {
-                        objc_msgSend
-                    }
-                    else {
-                        objc_msgSend_stret
-                    }
-                    /*NOTE: For "long double" we need fpret, but there does not seem to be an equivalent rust type.
-
-                    In general there isn't a type on apple silicon either, I think this is not widely used by the runtime and so it can
-                    be ignored.
-                   */
-                }
-                else {
-                    objc_msgSend
-                };
-                let imp: unsafe extern fn(*mut c_void, Sel $(, $type)*) -> R =
+                let impcast = msg_send_fn::<R>(obj, sel);
+                let imp: unsafe extern fn(*mut c_void, Sel $(, <$type as IntoArguable>::Arguable)*) -> R =
                     std::mem::transmute(impcast);
-                imp(obj, sel $(, $identifier)*)
+                imp(obj, sel $(, $identifier.into_arguable())*)
             }
-           #[inline] unsafe fn invoke_primitive_super<R: Primitive>(obj: *mut c_void, sel: Sel, _pool: &ActiveAutoreleasePool, class: *const AnyClass, ($($identifier,)*): Self) -> R {
+           #[inline] unsafe fn invoke_primitive_super<R: Primitive>(obj: *mut c_void, sel: Sel, _pool: &ActiveAutoreleasePool, class: *const AnyClass, $pattern: Self) -> R {
+               verify_send!(obj, Some(class), sel, R::ENCODING, [$(<$type as IntoArguable>::Arguable),*]);
+               verify_pool!();
                let objc_super = ObjcSuper {
                    receiver: obj,
                    class: class
                };
-               let impcast = if cfg!(target_arch="x86_64") {
-                    //this condition seems to broadly agree with clang
-                    if size_of::<R>() <= 16 {
-                        objc_msgSendSuper2
-                    }
-                    else {
-                        objc_msgSendSuper2_stret
-                    }
-                    /*NOTE: I verified in clang that, for "long double" case, we still use objc_msgSendSuper2.  I have no explanation
-                    for why there is no fpret verison.  However since we don't deal with fpret anyway, this is somewhat irrelevant.
-                     */
-                }
-                else {
-                    objc_msgSendSuper2
-                };
-                let imp: unsafe extern fn(*const ObjcSuper, Sel $(, $type)*) -> R =
+               let (first, impcast) = msg_send_super_fn::<R>(&objc_super, sel);
+                let imp: unsafe extern fn(*mut c_void, Sel $(, <$type as IntoArguable>::Arguable)*) -> R =
                     std::mem::transmute(impcast);
-                imp(&objc_super, sel $(, $identifier)*)
+                imp(first, sel $(, $identifier.into_arguable())*)
             }
-            #[inline] unsafe fn invoke<R: ObjcInstance>(obj: *mut c_void, sel: Sel, _pool: &ActiveAutoreleasePool, ($($identifier,)*): Self) -> *const R {
+            #[inline] unsafe fn invoke<R: ObjcInstance>(obj: *mut c_void, sel: Sel, _pool: &ActiveAutoreleasePool, $pattern: Self) -> *const R {
+               verify_send!(obj, None, sel, Encoding::Primitive("@"), [$(<$type as IntoArguable>::Arguable),*]);
+               verify_pool!();
                //autoreleasepool is encouraged by signature but not used
-               let impcast = objc_msgSend as unsafe extern fn();
-                let imp: unsafe extern fn(*mut c_void, Sel $(, $type)*) -> *mut c_void =
+               let impcast = msg_send_fn::<*mut c_void>(obj, sel);
+                let imp: unsafe extern fn(*mut c_void, Sel $(, <$type as IntoArguable>::Arguable)*) -> *mut c_void =
                     std::mem::transmute(impcast);
-                let ptr = imp(obj, sel $(, $identifier)*);
+                let ptr = imp(obj, sel $(, $identifier.into_arguable())*);
                 ptr as *const R
             }
-           #[inline] unsafe fn invoke_super<R: ObjcInstance>(obj: *mut c_void, sel: Sel, _pool: &ActiveAutoreleasePool,class: *const AnyClass, ($($identifier,)*): Self) -> *const R {
+           #[inline] unsafe fn invoke_super<R: ObjcInstance>(obj: *mut c_void, sel: Sel, _pool: &ActiveAutoreleasePool,class: *const AnyClass, $pattern: Self) -> *const R {
+               verify_send!(obj, Some(class), sel, Encoding::Primitive("@"), [$(<$type as IntoArguable>::Arguable),*]);
+               verify_pool!();
                let objc_super = ObjcSuper {
                    receiver: obj,
                    class: class
                };
-               let impcast = objc_msgSendSuper2 as unsafe extern fn();
-                let imp: unsafe extern "C" fn(*const ObjcSuper, Sel $(, $type)*) -> *mut c_void =
+               let (first, impcast) = msg_send_super_fn::<*mut c_void>(&objc_super, sel);
+                let imp: unsafe extern "C" fn(*mut c_void, Sel $(, <$type as IntoArguable>::Arguable)*) -> *mut c_void =
                     std::mem::transmute(impcast);
-                let ptr = imp(&objc_super, sel $(, $identifier)*);
+                let ptr = imp(first, sel $(, $identifier.into_arguable())*);
                 ptr as *const R
             }
 
@@ -260,12 +450,14 @@ macro_rules! arguments_impl {
            /// 2.  Assumes trailing error parameter
            /// 3.  Caller wants +1 / StrongCell, but callee returns +0 / autoreleased.  Resolved via the magic trampoline `objc_retainAutoreleasedReturnValue`.
            ///
-            #[inline] unsafe fn invoke_error_trampoline_strong<'a, R: ObjcInstance>(obj: *mut c_void, sel: Sel, pool: &'a ActiveAutoreleasePool, ($($identifier,)*): Self) -> Result<*const R,AutoreleasedCell<'a, NSError>> {
+            #[inline] unsafe fn invoke_error_trampoline_strong<'a, R: ObjcInstance>(obj: *mut c_void, sel: Sel, pool: &'a ActiveAutoreleasePool, $pattern: Self) -> Result<*const R,AutoreleasedCell<'a, NSError>> {
+               verify_send!(obj, None, sel, Encoding::Primitive("@"), [$(<$type as IntoArguable>::Arguable),*], *mut *const NSError);
+               verify_pool!();
                use crate::performselector::objc_retainAutoreleasedReturnValue;
-               let impcast = objc_msgSend as unsafe extern fn();
+               let impcast = msg_send_fn::<*mut c_void>(obj, sel);
                let mut error: *const NSError = std::ptr::null();
-               let imp: unsafe extern fn(*mut c_void, Sel, $( $type, )* &mut *const NSError) -> *const R  = std::mem::transmute(impcast);
-               let ptr = imp(obj,sel, $($identifier,)* &mut error );
+               let imp: unsafe extern fn(*mut c_void, Sel, $( <$type as IntoArguable>::Arguable, )* &mut *const NSError) -> *const R  = std::mem::transmute(impcast);
+               let ptr = imp(obj,sel, $($identifier.into_arguable(),)* &mut error );
                //ok to call this with nil
                objc_retainAutoreleasedReturnValue(ptr as *const c_void);
                if ptr != std::ptr::null_mut() {
@@ -277,11 +469,13 @@ macro_rules! arguments_impl {
                    Err(NSError::assume_nonnil(error).assume_autoreleased(pool))
                }
            }
-           #[inline] unsafe fn invoke_error<'a, R: ObjcInstance>(receiver: *mut c_void, sel: Sel, pool: &'a ActiveAutoreleasePool, ($($identifier,)*): Self) -> Result<*const R, AutoreleasedCell<'a, NSError>> {
-               let impcast = objc_msgSend as unsafe extern fn();
+           #[inline] unsafe fn invoke_error<'a, R: ObjcInstance>(receiver: *mut c_void, sel: Sel, pool: &'a ActiveAutoreleasePool, $pattern: Self) -> Result<*const R, AutoreleasedCell<'a, NSError>> {
+               verify_send!(receiver, None, sel, Encoding::Primitive("@"), [$(<$type as IntoArguable>::Arguable),*], *mut *const NSError);
+               verify_pool!();
+               let impcast = msg_send_fn::<*mut c_void>(receiver, sel);
                let mut error: *const NSError = std::ptr::null();
-               let imp: unsafe extern fn(*mut c_void, Sel, $( $type, )* &mut *const NSError) -> *const R  = std::mem::transmute(impcast);
-               let ptr = imp(receiver,sel, $($identifier,)* &mut error );
+               let imp: unsafe extern fn(*mut c_void, Sel, $( <$type as IntoArguable>::Arguable, )* &mut *const NSError) -> *const R  = std::mem::transmute(impcast);
+               let ptr = imp(receiver,sel, $($identifier.into_arguable(),)* &mut error );
                if ptr != std::ptr::null_mut() {
                    Ok(ptr)
                }
@@ -291,11 +485,13 @@ macro_rules! arguments_impl {
                    Err(NSError::assume_nonnil(error).assume_autoreleased(pool))
                }
            }
-           #[inline] unsafe fn invoke_error_bool<'a>(receiver: *mut c_void, sel: Sel, pool: &'a ActiveAutoreleasePool, ($($identifier,)*): Self) -> Result<(), AutoreleasedCell<'a, NSError>> {
-               let impcast = objc_msgSend as unsafe extern fn();
+           #[inline] unsafe fn invoke_error_bool<'a>(receiver: *mut c_void, sel: Sel, pool: &'a ActiveAutoreleasePool, $pattern: Self) -> Result<(), AutoreleasedCell<'a, NSError>> {
+               verify_send!(receiver, None, sel, bool::ENCODING, [$(<$type as IntoArguable>::Arguable),*], *mut *const NSError);
+               verify_pool!();
+               let impcast = msg_send_fn::<bool>(receiver, sel);
                let mut error: *const NSError = std::ptr::null();
-               let imp: unsafe extern fn(*mut c_void, Sel, $( $type, )* &mut *const NSError) -> bool  = std::mem::transmute(impcast);
-               let r = imp(receiver,sel, $($identifier,)* &mut error );
+               let imp: unsafe extern fn(*mut c_void, Sel, $( <$type as IntoArguable>::Arguable, )* &mut *const NSError) -> bool  = std::mem::transmute(impcast);
+               let r = imp(receiver,sel, $($identifier.into_arguable(),)* &mut error );
                if r {
                    Ok(())
                }
@@ -307,16 +503,18 @@ macro_rules! arguments_impl {
            }
 
 
-           #[inline] unsafe fn invoke_error_trampoline_strong_super<'a, R: ObjcInstance>(obj: *mut c_void, sel: Sel, pool: &'a ActiveAutoreleasePool, class: *const AnyClass, ($($identifier,)*): Self) -> Result<*const R,AutoreleasedCell<'a, NSError>> {
+           #[inline] unsafe fn invoke_error_trampoline_strong_super<'a, R: ObjcInstance>(obj: *mut c_void, sel: Sel, pool: &'a ActiveAutoreleasePool, class: *const AnyClass, $pattern: Self) -> Result<*const R,AutoreleasedCell<'a, NSError>> {
+               verify_send!(obj, Some(class), sel, Encoding::Primitive("@"), [$(<$type as IntoArguable>::Arguable),*], *mut *const NSError);
+               verify_pool!();
                let objc_super = ObjcSuper {
                    receiver: obj,
                    class: class
                };
                use crate::performselector::objc_retainAutoreleasedReturnValue;
-               let impcast = objc_msgSendSuper2 as unsafe extern fn();
+               let (first, impcast) = msg_send_super_fn::<*mut c_void>(&objc_super, sel);
                let mut error: *const NSError = std::ptr::null();
-               let imp: unsafe extern fn(*const ObjcSuper, Sel, $( $type, )* &mut *const NSError) -> *const R  = std::mem::transmute(impcast);
-               let ptr = imp(&objc_super,sel, $($identifier,)* &mut error );
+               let imp: unsafe extern fn(*mut c_void, Sel, $( <$type as IntoArguable>::Arguable, )* &mut *const NSError) -> *const R  = std::mem::transmute(impcast);
+               let ptr = imp(first,sel, $($identifier.into_arguable(),)* &mut error );
                //ok to call this with nil
                objc_retainAutoreleasedReturnValue(ptr as *const c_void);
                if ptr != std::ptr::null_mut() {
@@ -329,15 +527,17 @@ macro_rules! arguments_impl {
                }
 
            }
-           #[inline] unsafe fn invoke_error_trampoline_super<'a, R: ObjcInstance>(receiver: *mut c_void, sel: Sel, pool: &'a ActiveAutoreleasePool, class: *const AnyClass, ($($identifier,)*): Self) -> Result<*const R, AutoreleasedCell<'a, NSError>> {
+           #[inline] unsafe fn invoke_error_trampoline_super<'a, R: ObjcInstance>(receiver: *mut c_void, sel: Sel, pool: &'a ActiveAutoreleasePool, class: *const AnyClass, $pattern: Self) -> Result<*const R, AutoreleasedCell<'a, NSError>> {
+               verify_send!(receiver, Some(class), sel, Encoding::Primitive("@"), [$(<$type as IntoArguable>::Arguable),*], *mut *const NSError);
+               verify_pool!();
             let objc_super = ObjcSuper {
                    receiver: receiver,
                    class: class
                };
-               let impcast = objc_msgSendSuper2 as unsafe extern fn();
+               let (first, impcast) = msg_send_super_fn::<*mut c_void>(&objc_super, sel);
                let mut error: *const NSError = std::ptr::null();
-               let imp: unsafe extern fn(*const ObjcSuper, Sel, $( $type, )* &mut *const NSError) -> *const R  = std::mem::transmute(impcast);
-               let ptr = imp(&objc_super,sel, $($identifier,)* &mut error );
+               let imp: unsafe extern fn(*mut c_void, Sel, $( <$type as IntoArguable>::Arguable, )* &mut *const NSError) -> *const R  = std::mem::transmute(impcast);
+               let ptr = imp(first,sel, $($identifier.into_arguable(),)* &mut error );
                if ptr != std::ptr::null_mut() {
                    Ok(ptr)
                }
@@ -368,8 +568,142 @@ arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J); //1
 arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K); //11
 arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L); //12
 
+/**
+The arguments to a method with 13 to 16 of them.
+
+The `perform` family requires its arguments to be `Debug`, but the standard library only implements that for tuples of up
+to 12 elements, so longer ones are wrapped in this.
+
+```no_run
+use objr::bindings::*;
+# let pool = unsafe{ AutoreleasePool::new() };
+# let instance = NSObject::class().alloc_init(&pool);
+let _: i32 = unsafe{ NSObject::perform_primitive(instance.assume_nonmut_perform(), Sel::from_str("sum:::::::::::::"), &pool,
+    LongArguments((1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13))) };
+```
+*/
+pub struct LongArguments<T>(pub T);
+
+///Implementation macro for [LongArguments]
+macro_rules! long_arguments_impl {
+    (
+        $($identifier:ident : $type:ident),*
+    ) => (
+        impl<$($type: Debug),*> Debug for LongArguments<($($type,)*)> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let LongArguments(($($identifier,)*)) = self;
+                f.debug_tuple("LongArguments")$(.field($identifier))*.finish()
+            }
+        }
+        arguments_impl!(@for LongArguments<($($type,)*)>, LongArguments(($($identifier,)*)), $($identifier : $type),*);
+    );
+}
+long_arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L, m: M); //13
+long_arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L, m: M, n: N); //14
+long_arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L, m: M, n: N, o: O); //15
+long_arguments_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L, m: M, n: N, o: O, p: P); //16
+
+///Types that can be passed to the `...` of a variadic method, like the arguments to `+[NSString stringWithFormat:]`.
+///
+/// C promotes arguments to `...`: integers smaller than `int` are passed as `int`, and `float` as `double`.
+/// The callee reads them back that way, so we promote them before the call.
+///
+/// # Safety
+/// `Promoted` must be FFI-safe, and be how C passes `Self` to a variadic function.
+pub unsafe trait VariadicArgument: Debug {
+    ///The type `Self` is passed as.
+    type Promoted;
+    fn promote(self) -> Self::Promoted;
+}
+
+///Implementation macro for variadic arguments passed as themselves
+macro_rules! variadic_argument_impl {
+    ($($type:ty),*) => {
+        $(
+        unsafe impl VariadicArgument for $type {
+            type Promoted = Self;
+            #[inline] fn promote(self) -> Self { self }
+        }
+        )*
+    }
+}
+///Implementation macro for variadic arguments that C promotes
+macro_rules! variadic_argument_promote {
+    ($($type:ty => $promoted:ty),*) => {
+        $(
+        unsafe impl VariadicArgument for $type {
+            type Promoted = $promoted;
+            #[inline] fn promote(self) -> $promoted { self as $promoted }
+        }
+        )*
+    }
+}
+variadic_argument_impl!(i32, u32, i64, u64, isize, usize, f64, Sel);
+//`unsigned char` and `unsigned short` also promote to `int`, since it can hold all their values
+variadic_argument_promote!(i8 => i32, u8 => i32, i16 => i32, u16 => i32, bool => i32, f32 => f64);
+//objects are passed as pointers, e.g. from [ArguableBehavior::assume_nonmut_perform()].
+//Only thin ones: a pointer to a slice or trait object is two words, not one C pointer.
+unsafe impl<T> VariadicArgument for *mut T {
+    type Promoted = Self;
+    #[inline] fn promote(self) -> Self { self }
+}
+unsafe impl<T> VariadicArgument for *const T {
+    type Promoted = Self;
+    #[inline] fn promote(self) -> Self { self }
+}
+
+///Trait describing the arguments to the `...` of a variadic method, following the named arguments `A`.
+/// Generally this is a tuple of [VariadicArgument].
+///
+/// This type is sealed; you may not implement it from outside the crate.
+/// It's implemented for up to 3 named arguments, and up to 12 variadic ones.
+pub trait VariadicArguments<A>: Sized + Debug + crate::private::SealedWith<A> {
+    ///Implementation detail of [PerformsSelector::perform_variadic_primitive]
+    unsafe fn invoke_primitive<R: Primitive>(receiver: *mut c_void, sel: Sel, pool: &ActiveAutoreleasePool, args: A, variadic: Self) -> R;
+}
+
+///Implementation macro for declaring [VariadicArguments] types.
+macro_rules! variadic_arguments_impl {
+    (
+        [$($identifier:ident : $type:ident),*] [$($variadic_identifier:ident : $variadic_type:ident),*]
+    ) => (
+        impl<$($type: IntoArguable,)* $($variadic_type: VariadicArgument),*> crate::objr::private::SealedWith<($($type,)*)> for ($($variadic_type,)*) where $($type: Debug, <$type as IntoArguable>::Arguable: _VerifyEncode),* {}
+        impl<$($type: IntoArguable,)* $($variadic_type: VariadicArgument),*> VariadicArguments<($($type,)*)> for ($($variadic_type,)*) where $($type: Debug, <$type as IntoArguable>::Arguable: _VerifyEncode),* {
+            #[inline] unsafe fn invoke_primitive<R: Primitive>(obj: *mut c_void, sel: Sel, _pool: &ActiveAutoreleasePool, ($($identifier,)*): ($($type,)*), ($($variadic_identifier,)*): Self) -> R {
+                //the method's type encoding only has the named arguments
+                verify_send!(obj, None, sel, R::ENCODING, [$(<$type as IntoArguable>::Arguable),*]);
+                verify_pool!();
+                let impcast = msg_send_fn::<R>(obj, sel);
+                /*The callee is variadic, so we must call it as variadic.  On arm64 Apple platforms, variadic arguments go on the
+                stack rather than in registers, so transmuting to a fixed signature would put them in the wrong place.
+                */
+                let imp: unsafe extern "C" fn(*mut c_void, Sel, $(<$type as IntoArguable>::Arguable,)* ...) -> R =
+                    std::mem::transmute(impcast);
+                imp(obj, sel $(, $identifier.into_arguable())* $(, $variadic_identifier.promote())*)
+            }
+        }
+    );
+}
+///Declares [VariadicArguments] for the named arguments, for each number of variadic arguments
+macro_rules! variadic_arguments_impls {
+    ($named:tt $($variadic_identifier:ident : $variadic_type:ident),*) => (
+        variadic_arguments_impls!(@each $named [] $($variadic_identifier: $variadic_type),*);
+    );
+    (@each $named:tt [$($done_identifier:ident : $done_type:ident),*] $variadic_identifier:ident : $variadic_type:ident $(, $rest_identifier:ident : $rest_type:ident)*) => (
+        variadic_arguments_impl!($named [$($done_identifier: $done_type),*]);
+        variadic_arguments_impls!(@each $named [$($done_identifier: $done_type,)* $variadic_identifier: $variadic_type] $($rest_identifier: $rest_type),*);
+    );
+    (@each $named:tt [$($done_identifier:ident : $done_type:ident),*]) => (
+        variadic_arguments_impl!($named [$($done_identifier: $done_type),*]);
+    );
+}
+variadic_arguments_impls!([a: A] v0: V0, v1: V1, v2: V2, v3: V3, v4: V4, v5: V5, v6: V6, v7: V7, v8: V8, v9: V9, v10: V10, v11: V11);
+variadic_arguments_impls!([a: A, b: B] v0: V0, v1: V1, v2: V2, v3: V3, v4: V4, v5: V5, v6: V6, v7: V7, v8: V8, v9: V9, v10: V10, v11: V11);
+variadic_arguments_impls!([a: A, b: B, c: C] v0: V0, v1: V1, v2: V2, v3: V3, v4: V4, v5: V5, v6: V6, v7: V7, v8: V8, v9: V9, v10: V10, v11: V11);
+
 
 #[test]
+#[cfg_attr(feature="mock-runtime", ignore = "needs Foundation")]
 fn perform_super() {
     use objr::bindings::*;
 
@@ -392,6 +726,7 @@ fn perform_super() {
 
 }
 
+#[cfg_attr(feature="mock-runtime", ignore = "needs Foundation")]
 #[test] fn arguable() {
     let f = objc_nsstring!("example");
     let borrowed: &NSString = &f;
@@ -404,4 +739,197 @@ fn perform_super() {
     let as_ptr = option.as_ptr();
     unsafe { as_ptr.assume_nonmut_perform() };
 
-}
\ No newline at end of file
+}
+#[cfg(test)] mod example_structs {
+    use objr::bindings::*;
+    #[derive(ObjcPrimitive,ObjcEncode,Debug,Clone,Copy,PartialEq)]
+    #[repr(C)]
+    pub struct CGPoint { pub x: f64, pub y: f64 }
+    #[derive(ObjcPrimitive,ObjcEncode,Debug,Clone,Copy,PartialEq)]
+    #[repr(C)]
+    pub struct CGSize { pub width: f64, pub height: f64 }
+    #[derive(ObjcPrimitive,ObjcEncode,Debug,Clone,Copy,PartialEq)]
+    #[repr(C)]
+    pub struct CGRect { pub origin: CGPoint, pub size: CGSize }
+    #[derive(ObjcPrimitive,ObjcEncode,Debug,Clone,Copy,PartialEq)]
+    #[repr(C)]
+    pub struct NSRange { pub location: usize, pub length: usize }
+
+    objc_subclass! {
+        pub struct ExampleStructArguments {
+            @class(ExampleStructArguments)
+            @superclass(NSObject)
+            payload: (),
+            methods: [
+                "-(CGRect) offsetFrame:(CGRect) frame by:(CGFloat) distance" => unsafe offset_frame,
+                "-(NSRange) shiftRange:(NSRange) range" => unsafe shift_range
+            ]
+        }
+    }
+    extern "C" fn offset_frame(_objc_self: &ExampleStructArguments, _sel: Sel, frame: CGRect, distance: f64) -> CGRect {
+        CGRect { origin: CGPoint { x: frame.origin.x + distance, y: frame.origin.y + distance }, size: frame.size }
+    }
+    extern "C" fn shift_range(_objc_self: &ExampleStructArguments, _sel: Sel, range: NSRange) -> NSRange {
+        NSRange { location: range.location + 1, length: range.length }
+    }
+}
+
+#[cfg(test)] mod example_floats {
+    use objr::bindings::*;
+    #[derive(ObjcPrimitive,ObjcEncode,Debug,Clone,Copy,PartialEq)]
+    #[repr(C)]
+    pub struct Float2 { pub x: f32, pub y: f32 }
+
+    objc_subclass! {
+        pub struct ExampleFloats {
+            @class(ExampleFloats)
+            @superclass(NSObject)
+            payload: (),
+            methods: [
+                "-(float) opacity" => unsafe opacity,
+                "-(float) scale:(float) value by:(double) factor" => unsafe scale,
+                "-(double) widen:(float) value" => unsafe widen,
+                "-(Float2) swap:(Float2) value" => unsafe swap
+            ]
+        }
+    }
+    extern "C" fn opacity(_objc_self: &ExampleFloats, _sel: Sel) -> f32 {
+        0.25
+    }
+    extern "C" fn scale(_objc_self: &ExampleFloats, _sel: Sel, value: f32, factor: f64) -> f32 {
+        (value as f64 * factor) as f32
+    }
+    extern "C" fn widen(_objc_self: &ExampleFloats, _sel: Sel, value: f32) -> f64 {
+        value as f64
+    }
+    extern "C" fn swap(_objc_self: &ExampleFloats, _sel: Sel, value: Float2) -> Float2 {
+        Float2 { x: value.y, y: value.x }
+    }
+}
+
+#[test] fn float_returns() {
+    use objr::bindings::*;
+    use example_floats::*;
+    let pool = unsafe{ AutoreleasePool::new() };
+    let ex = ExampleFloats::class().alloc_init(&pool);
+    let opacity: f32 = unsafe{ ExampleFloats::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("opacity"), &pool, ()) };
+    assert_eq!(opacity, 0.25);
+    let scaled: f32 = unsafe{ ExampleFloats::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("scale:by:"), &pool, (1.5f32, 3.0f64)) };
+    assert_eq!(scaled, 4.5);
+    let widened: f64 = unsafe{ ExampleFloats::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("widen:"), &pool, (0.5f32,)) };
+    assert_eq!(widened, 0.5);
+    let swapped: Float2 = unsafe{ ExampleFloats::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("swap:"), &pool, (Float2 { x: 1.0, y: 2.0 },)) };
+    assert_eq!(swapped, Float2 { x: 2.0, y: 1.0 });
+    assert!(!f32::RETURNS_IN_MEMORY);
+    assert!(!Float2::RETURNS_IN_MEMORY);
+}
+
+#[test] fn returns_in_memory() {
+    use example_structs::*;
+    #[derive(ObjcPrimitive,ObjcEncode)]
+    #[repr(C, packed)]
+    struct Packed { tag: u8, value: u32 }
+    #[derive(ObjcPrimitive,ObjcEncode)]
+    #[repr(C, packed(4))]
+    struct Packed4 { tag: u32, value: u32 }
+    #[derive(ObjcPrimitive,ObjcEncode)]
+    #[repr(C)]
+    struct ContainsPacked { inner: Packed }
+    assert!(!CGPoint::RETURNS_IN_MEMORY);
+    assert!(!NSRange::RETURNS_IN_MEMORY);
+    assert!(CGRect::RETURNS_IN_MEMORY);
+    //small, but misaligned
+    assert!(Packed::RETURNS_IN_MEMORY);
+    assert!(!Packed4::RETURNS_IN_MEMORY);
+    assert!(ContainsPacked::RETURNS_IN_MEMORY);
+    assert!(!<*const CGRect>::RETURNS_IN_MEMORY);
+    assert!(!f64::RETURNS_IN_MEMORY);
+}
+
+#[test] fn struct_arguments() {
+    use objr::bindings::*;
+    use example_structs::*;
+    let pool = unsafe{ AutoreleasePool::new() };
+    let ex = ExampleStructArguments::class().alloc_init(&pool);
+    let frame = CGRect { origin: CGPoint { x: 1.0, y: 2.0 }, size: CGSize { width: 3.0, height: 4.0 } };
+    let offset: CGRect = unsafe{ ExampleStructArguments::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("offsetFrame:by:"), &pool, (frame, 0.5)) };
+    assert_eq!(offset, CGRect { origin: CGPoint { x: 1.5, y: 2.5 }, size: frame.size });
+    let range: NSRange = unsafe{ ExampleStructArguments::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("shiftRange:"), &pool, (NSRange { location: 2, length: 3 },)) };
+    assert_eq!(range, NSRange { location: 3, length: 3 });
+}
+
+#[test]
+#[cfg_attr(all(target_vendor="apple", target_arch="aarch64"), ignore = "the method isn't really variadic, so it reads its arguments from the wrong place")]
+fn perform_variadic() {
+    use objr::bindings::*;
+    use std::ffi::CString;
+    //We can't write a variadic function in stable Rust.  But on x86_64, and other platforms' arm64, variadic arguments are passed
+    //like named ones, so a function with a fixed signature can stand in for the method.
+    extern "C" fn sum(_objc_self: &NSObject, _sel: Sel, count: i32, a: f64, b: i32, c: *const c_void) -> f64 {
+        assert_eq!(count, 3);
+        assert!(c.is_null());
+        a + b as f64
+    }
+    let pool = unsafe{ AutoreleasePool::new() };
+    let mut builder = ClassBuilder::<NSObject>::new_unique("VariadicTest").unwrap();
+    //the type encoding of a variadic method only has the named arguments
+    unsafe{ builder.add_method(Sel::from_str("sum:"), sum as *const c_void, &CString::new("d20@0:8i16").unwrap()) };
+    let class = builder.register();
+    let instance = class.alloc_init(&pool);
+    let result: f64 = unsafe{ NSObject::perform_variadic_primitive(instance.assume_nonmut_perform(), Sel::from_str("sum:"), &pool, (3,), (1.5f32, 2u8, std::ptr::null::<c_void>())) };
+    assert_eq!(result, 3.5);
+}
+
+#[test]
+#[cfg_attr(feature="mock-runtime", ignore = "needs Foundation")]
+fn perform_variadic_foundation() {
+    use objr::bindings::*;
+    let pool = unsafe{ AutoreleasePool::new() };
+    let format = objc_nsstring!("%d %@ %.1f");
+    let argument = objc_nsstring!("example");
+    let formatted = unsafe {
+        let raw = Class::<NSString>::perform_variadic_autorelease_to_retain(NSString::class().assume_nonmut_perform(), Sel::from_str("stringWithFormat:"), &pool,
+            (format.assume_nonmut_perform(),), (-42i8, argument.assume_nonmut_perform(), 1.5f32));
+        NSString::assume_nonnil(raw).assume_retained()
+    };
+    assert_eq!(formatted.to_str(&pool), "-42 example 1.5");
+}
+
+#[test] fn object_arguments() {
+    use objr::bindings::*;
+    use std::ffi::CString;
+    extern "C" fn count(_objc_self: &NSObject, _sel: Sel, a: Option<&NSObject>, b: Option<&NSObject>, c: Option<&NSObject>, d: Option<&NSObject>) -> i32 {
+        [a, b, c, d].iter().filter(|o| o.is_some()).count() as i32
+    }
+    let pool = unsafe{ AutoreleasePool::new() };
+    let mut builder = ClassBuilder::<NSObject>::new_unique("ObjectArgumentsTest").unwrap();
+    unsafe{ builder.add_method(Sel::from_str("count:b:c:d:"), count as *const c_void, &CString::new("i48@0:8@16@24@32@40").unwrap()) };
+    let class = builder.register();
+    let instance = class.alloc_init(&pool);
+    let strong = NSObject::class().alloc_init(&pool);
+    let mut strong_mut = NSObject::class().alloc_init_mut(&pool);
+    //`strong` outlives this, so it's as good as autoreleased
+    let autoreleased = unsafe{ AutoreleasedCell::assume_autoreleased(&*strong, &pool) };
+    //no conversions at the call site
+    let result: i32 = unsafe{ NSObject::perform_primitive(instance.assume_nonmut_perform(), Sel::from_str("count:b:c:d:"), &pool,
+        (None::<&NSObject>, Some(&mut *strong_mut), &strong, &autoreleased)) };
+    assert_eq!(result, 3);
+}
+
+#[test] fn sixteen_arguments() {
+    use objr::bindings::*;
+    use std::ffi::CString;
+    #[allow(clippy::too_many_arguments)]
+    extern "C" fn sum(_objc_self: &NSObject, _sel: Sel, a: i32, b: i32, c: i32, d: i32, e: i32, f: i32, g: i32, h: i32, i: i32, j: i32, k: i32, l: i32, m: i32, n: i32, o: i32, p: i32) -> i32 {
+        a + b + c + d + e + f + g + h + i + j + k + l + m + n + o + p
+    }
+    let pool = unsafe{ AutoreleasePool::new() };
+    let mut builder = ClassBuilder::<NSObject>::new_unique("SixteenArgumentsTest").unwrap();
+    let types: String = (0..16).map(|a| format!("i{}", 16 + a * 4)).collect();
+    unsafe{ builder.add_method(Sel::from_str("sum:::::::::::::::::"), sum as *const c_void, &CString::new(format!("i80@0:8{}", types)).unwrap()) };
+    let class = builder.register();
+    let instance = class.alloc_init(&pool);
+    let result: i32 = unsafe{ NSObject::perform_primitive(instance.assume_nonmut_perform(), Sel::from_str("sum:::::::::::::::::"), &pool,
+        LongArguments((1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16))) };
+    assert_eq!(result, 136);
+}
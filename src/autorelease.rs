@@ -1,13 +1,85 @@
 //SPDX-License-Identifier: MIT OR Apache-2.0
 //! Autorelease pools and similar
+//!
+//! These are also re-exported from [crate::foundation] and [crate::bindings].
 
 use core::ffi::{c_void};
 use core::marker::PhantomData;
 use std::ops::Deref;
+#[cfg(feature="verify-pools")]
+use std::cell::{Cell, RefCell};
 
+#[cfg(not(feature="mock-runtime"))]
 extern "C" {
-    pub fn objc_autoreleasePoolPush() -> *const c_void;
-    pub fn objc_autoreleasePoolPop(ptr: *const c_void);
+    pub(crate) fn objc_autoreleasePoolPush() -> *const c_void;
+    pub(crate) fn objc_autoreleasePoolPop(ptr: *const c_void);
+}
+#[cfg(feature="mock-runtime")]
+pub(crate) use crate::mock::runtime::{objc_autoreleasePoolPush, objc_autoreleasePoolPop};
+
+/*With the `verify-pools` feature, we keep our own stack of the pools on each thread, to check how they're used.
+
+Pools are identified by a serial number rather than the runtime's token, since tokens are reused.  Pools pushed
+by objc code (like the AppKit run loop) aren't on the stack.
+ */
+#[cfg(feature="verify-pools")]
+thread_local! {
+    ///Pools on this thread, innermost last
+    static POOLS: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+    static NEXT_POOL: Cell<u64> = const { Cell::new(1) };
+    ///Whether we've warned about sending a message on this thread without a pool
+    static WARNED: Cell<bool> = const { Cell::new(false) };
+}
+
+#[cfg(feature="verify-pools")]
+fn push_pool() -> u64 {
+    let id = NEXT_POOL.with(|n| { let id = n.get(); n.set(id + 1); id });
+    POOLS.with(|pools| pools.borrow_mut().push(id));
+    id
+}
+
+///Removes `id` and any pools pushed after it from the stack.
+///
+/// Returns the number of pools pushed after it, or `None` if it isn't on the stack.
+#[cfg(feature="verify-pools")]
+fn pop_pool(id: u64) -> Option<usize> {
+    POOLS.with(|pools| {
+        let mut pools = pools.borrow_mut();
+        let index = pools.iter().rposition(|p| *p == id)?;
+        let inner = pools.len() - index - 1;
+        pools.truncate(index);
+        Some(inner)
+    })
+}
+
+///The pool that an object autoreleased now goes into, or 0 if we don't know of one.
+#[cfg(feature="verify-pools")]
+pub(crate) fn current_pool() -> u64 {
+    POOLS.with(|pools| pools.borrow().last().copied()).unwrap_or(0)
+}
+
+///Warns (once per thread) if a message is sent with no pool on the thread, which usually means the pool
+/// came from [ActiveAutoreleasePool::assume_autoreleasepool()].  On a background thread, objects autoreleased by the send leak.
+#[cfg(feature="verify-pools")]
+pub(crate) fn check_pool_exists() {
+    if current_pool() == 0 && !WARNED.with(|w| w.replace(true)) {
+        eprintln!("verify-pools: message sent on thread {:?} with no autorelease pool, perhaps from assume_autoreleasepool().  (Pools pushed by objc code aren't seen, so this may be spurious.)",
+            std::thread::current().name().unwrap_or("<unnamed>"));
+    }
+}
+
+///Panics if the pool from [current_pool()] has been drained.
+#[cfg(feature="verify-pools")]
+pub(crate) fn check_pool(id: u64, type_name: &str) {
+    if id != 0 && !POOLS.with(|pools| pools.borrow().contains(&id)) {
+        panic!("verify-pools: AutoreleasedCell<{}> used after its autorelease pool was drained", type_name);
+    }
+}
+
+///The number of autorelease pools objr has pushed on this thread, with the `verify-pools` feature.
+#[cfg(feature="verify-pools")]
+pub fn autoreleasepool_depth() -> usize {
+    POOLS.with(|pools| pools.borrow().len())
 }
 
 ///Marker type that indicates you have an active autorelease pool.
@@ -22,6 +94,9 @@ extern "C" {
 /// There are two ways to construct this type:
 /// 1.  by dereferencing an [AutoreleasePool] (preferred)
 ///2.   [ActiveAutoreleasePool::assume_autoreleasepool()].
+///
+/// With the `verify-pools` feature, objr checks at runtime that pools are popped in order, that [AutoreleasedCell](crate::bindings::AutoreleasedCell)s
+/// aren't used after the pool the object actually went into is drained, and warns when a message is sent on a thread without one.
 #[derive(Debug)]
 pub struct ActiveAutoreleasePool {
     ///don't allow anyone else to construct this
@@ -53,6 +128,8 @@ pub struct AutoreleasePool {
     // !Send, !Sync
     ptr: *const c_void,
     pool: ActiveAutoreleasePool,
+    #[cfg(feature="verify-pools")]
+    id: u64,
 }
 
 impl Deref for AutoreleasePool {
@@ -65,9 +142,25 @@ impl Deref for AutoreleasePool {
 
 ///Pops the pool
 impl Drop for AutoreleasePool {
+    #[cfg(not(feature="verify-pools"))]
     fn drop(&mut self) {
         unsafe{ objc_autoreleasePoolPop(self.ptr) }
     }
+    #[cfg(feature="verify-pools")]
+    fn drop(&mut self) {
+        match pop_pool(self.id) {
+            //already drained by popping an outer pool, so don't pop it again
+            None => if !std::thread::panicking() {
+                panic!("verify-pools: autorelease pool popped after an outer pool, or on another thread")
+            }
+            Some(inner) => {
+                unsafe{ objc_autoreleasePoolPop(self.ptr) }
+                if inner != 0 && !std::thread::panicking() {
+                    panic!("verify-pools: autorelease pool popped out of order, before {} pools pushed after it", inner);
+                }
+            }
+        }
+    }
 }
 
 pub fn autoreleasepool<F: FnOnce(&ActiveAutoreleasePool) -> R,R>(f: F) -> R {
@@ -84,7 +177,226 @@ impl AutoreleasePool {
     pub unsafe fn new() -> Self {
         AutoreleasePool {
             ptr: objc_autoreleasePoolPush(),
-            pool: ActiveAutoreleasePool::assume_autoreleasepool()
+            //not `assume_autoreleasepool()`, which would look for the pool we're pushing
+            pool: ActiveAutoreleasePool {_marker: PhantomData },
+            #[cfg(feature="verify-pools")]
+            id: push_pool(),
         }
     }
 }
+
+/**
+Calls `f` for each item, inside a new autorelease pool that is drained after each one.
+
+This is for long-running loops, which would otherwise accumulate autoreleased objects until some outer pool is drained.
+The pool is also active while the iterator produces the item.
+
+```
+use objr::bindings::*;
+use objr::autorelease::for_each_drained;
+for_each_drained(0..3, |pool, _| {
+    let _object = NSObject::class().alloc_init(pool);
+});
+```
+
+To drain every few items instead, see [DrainingIterator::drain_every()].
+ */
+pub fn for_each_drained<I: IntoIterator, F: FnMut(&ActiveAutoreleasePool, I::Item)>(iter: I, mut f: F) {
+    let mut iter = iter.into_iter();
+    loop {
+        let more = autoreleasepool(|pool| {
+            match iter.next() {
+                Some(item) => { f(pool, item); true }
+                None => false
+            }
+        });
+        if !more { break }
+    }
+}
+
+/**
+An autorelease pool that can be drained and reused, for manual scoping.
+
+Like `NSAutoreleasePool`'s `drain`, [Self::drain()] releases the objects in the pool and starts a new one.  Because it needs `&mut self`,
+no [AutoreleasedCell](crate::bindings::AutoreleasedCell) that borrows the pool can be alive at the time.
+
+```
+use objr::bindings::*;
+let mut drain = unsafe{ Drain::new() };
+for _ in 0..3 {
+    let _object = NSObject::class().alloc_init(&drain);
+    drain.drain();
+}
+```
+ */
+#[derive(Debug)]
+pub struct Drain {
+    //None only while draining
+    pool: Option<AutoreleasePool>,
+}
+impl Drain {
+    ///Creates a new pool, which is popped when this type is dropped.
+    ///
+    /// # Safety
+    /// As for [AutoreleasePool::new()], pools must be dropped in reverse order to when they are created.  In addition,
+    /// don't call [Self::drain()] while a pool created after this one is active.
+    pub unsafe fn new() -> Self {
+        Drain { pool: Some(AutoreleasePool::new()) }
+    }
+    ///Releases the objects in the pool, and starts a new one.
+    pub fn drain(&mut self) {
+        //pop before pushing, so the pools stay in order
+        self.pool = None;
+        self.pool = Some(unsafe{ AutoreleasePool::new() });
+    }
+}
+impl Deref for Drain {
+    type Target = ActiveAutoreleasePool;
+    fn deref(&self) -> &Self::Target {
+        self.pool.as_ref().unwrap()
+    }
+}
+
+///Iterator adapter returned by [DrainingIterator::drain_every()].
+#[derive(Debug)]
+pub struct DrainEvery<I> {
+    iter: I,
+    every: usize,
+    count: usize,
+    drain: Option<Drain>,
+}
+impl<I: Iterator> Iterator for DrainEvery<I> {
+    type Item = I::Item;
+    fn next(&mut self) -> Option<I::Item> {
+        match &mut self.drain {
+            None => self.drain = Some(unsafe{ Drain::new() }),
+            Some(drain) if self.count == self.every => {
+                drain.drain();
+                self.count = 0;
+            }
+            Some(_) => ()
+        }
+        self.count += 1;
+        let item = self.iter.next();
+        if item.is_none() {
+            //done, so don't wait for the adapter to be dropped
+            self.drain = None;
+        }
+        item
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+///Adds [Self::drain_every()] to iterators.
+pub trait DrainingIterator: Iterator + Sized {
+    /**
+    Runs the iteration inside an autorelease pool that is drained every `n` items.
+
+    A pool is pushed when the first item is requested.  Objects autoreleased by the loop body go into it, and are released
+    when the item after every `n`th is requested, and at the end of iteration.
+
+    ```
+    use objr::bindings::*;
+    autoreleasepool(|pool| {
+        for _ in unsafe{ (0..100).drain_every(10) } {
+            //objects autoreleased here are released every 10 items
+            let _object = NSObject::class().alloc_init(pool);
+        }
+    });
+    ```
+
+    # Safety
+    The pool is pushed and popped from inside `next`, so you must check:
+    * That autoreleased objects from the loop body aren't used after their batch, even though the [ActiveAutoreleasePool]
+      you passed says they live longer.  With the `verify-pools` feature, this is checked for [AutoreleasedCell](crate::bindings::AutoreleasedCell).
+    * That `next` isn't called while a pool pushed after the adapter's is active, for example from inside an [autoreleasepool] in the loop body,
+      or by interleaving two adapters.
+    */
+    unsafe fn drain_every(self, n: usize) -> DrainEvery<Self> {
+        assert!(n > 0, "drain_every(0)");
+        DrainEvery { iter: self, every: n, count: 0, drain: None }
+    }
+}
+impl<I: Iterator> DrainingIterator for I {}
+
+#[cfg(feature="verify-pools")]
+#[test] fn pools_in_order() {
+    use crate::bindings::*;
+    assert_eq!(autoreleasepool_depth(), 0);
+    autoreleasepool(|pool| {
+        let object = NSObject::class().alloc_init(pool);
+        let cell = AutoreleasedCell::autoreleasing(StrongCell::leak(object.clone()), pool);
+        autoreleasepool(|_| {
+            assert_eq!(autoreleasepool_depth(), 2);
+            //still fine in an inner pool
+            let _: &NSObject = &cell;
+        });
+        let _: &NSObject = &cell;
+    });
+}
+
+#[cfg(feature="verify-pools")]
+#[test] #[should_panic(expected="verify-pools: autorelease pool popped out of order, before 1 pools pushed after it")]
+fn pools_out_of_order() {
+    let outer = unsafe{ AutoreleasePool::new() };
+    //popping the outer pool drains this one, so it mustn't be popped again
+    let _inner = std::mem::ManuallyDrop::new(unsafe{ AutoreleasePool::new() });
+    drop(outer);
+}
+
+#[cfg(feature="verify-pools")]
+#[test] #[should_panic(expected="verify-pools: AutoreleasedCell<objr::")]
+fn cell_outlives_pool() {
+    use crate::bindings::*;
+    let outer = unsafe{ AutoreleasePool::new() };
+    let object = NSObject::class().alloc_init(&outer);
+    let inner = unsafe{ AutoreleasePool::new() };
+    //says the outer pool, but the object actually goes into the inner one
+    let cell = AutoreleasedCell::autoreleasing(StrongCell::leak(object.clone()), &outer);
+    drop(inner);
+    let _: &NSObject = &cell;
+}
+
+#[cfg(all(test, feature="mock-runtime"))]
+mod test {
+    use crate::bindings::*;
+    use crate::mock::is_deallocated;
+
+    #[test] fn for_each_drained() {
+        autoreleasepool(|pool| {
+            let objects: Vec<StrongCell<NSObject>> = (0..3).map(|_| NSObject::class().alloc_init(pool)).collect();
+            let mut previous: Option<*const NSObject> = None;
+            objr::bindings::for_each_drained(objects, |pool, object| {
+                //the previous iteration's pool had the only reference
+                if let Some(previous) = previous {
+                    assert!(is_deallocated(previous));
+                }
+                previous = Some(StrongCell::as_ptr(&object));
+                AutoreleasedCell::autoreleasing(StrongCell::leak(object), pool);
+            });
+            assert!(is_deallocated(previous.unwrap()));
+        })
+    }
+
+    #[test] fn drain_every() {
+        autoreleasepool(|pool| {
+            let objects: Vec<StrongCell<NSObject>> = (0..5).map(|_| NSObject::class().alloc_init(pool)).collect();
+            let ptrs: Vec<*const NSObject> = objects.iter().map(StrongCell::as_ptr).collect();
+            for (index, object) in unsafe{ objects.into_iter().drain_every(2) }.enumerate() {
+                //drained before items 2 and 4
+                assert_eq!(ptrs[..index].iter().filter(|p| is_deallocated(**p)).count(), index / 2 * 2);
+                AutoreleasedCell::autoreleasing(StrongCell::leak(object), pool);
+            }
+            assert!(ptrs.iter().all(|p| is_deallocated(*p)));
+            let mut drain = unsafe{ Drain::new() };
+            let object = NSObject::class().alloc_init(&drain);
+            let ptr = StrongCell::as_ptr(&object);
+            AutoreleasedCell::autoreleasing(StrongCell::leak(object), &drain);
+            assert!(!is_deallocated(ptr));
+            drain.drain();
+            assert!(is_deallocated(ptr));
+        })
+    }
+}
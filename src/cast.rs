@@ -46,10 +46,13 @@ objc_instance! {
 objc_cast!(MyNSString,unsafe CFString,as_cfstring,as_cfstring_mut);
 
 
+# #[cfg(not(feature="mock-runtime"))] //the mock runtime has no Foundation classes
+# {
 let a: &MyNSString = unsafe{objc_nsstring!("hello").cast()};
 let b: &CFString = a.as_cfstring();
 
 let c: &CFString = a.into();
+# }
 ```
 
 Also works for mutable types:
@@ -68,12 +71,13 @@ objc_instance! {
 }
 objc_cast!(MyNSString,unsafe CFString,as_cfstring,as_cfstring_mut);
 
+# #[cfg(not(feature="mock-runtime"))] //the mock runtime has no Foundation classes
 autoreleasepool(|pool| {
     let mut nsstring = NSString::with_str_copy("hello", pool);
     let mut_nsstring: &mut MyNSString = unsafe { nsstring.cast_mut() }; //get into our local type
     let cfstring: &mut CFString = mut_nsstring.as_cfstring_mut();
     let cfstring: &mut CFString = mut_nsstring.into();
-})
+});
 ```
 */
 #[macro_export]
@@ -1,16 +1,34 @@
 //SPDX-License-Identifier: MIT OR Apache-2.0
 ///! Implementation of ObjC classes.  Classes are distinct from instances (which could be, for example, protocols).
-use std::ffi::{c_void, CStr};
+use std::ffi::{c_void, CStr, CString};
 use super::performselector::PerformablePointer;
 use super::bindings::*;
 use std::os::raw::c_char;
 use core::marker::PhantomData;
 use std::fmt::Formatter;
+use std::sync::atomic::{AtomicPtr, Ordering};
 
+#[cfg(not(feature="mock-runtime"))]
 #[link(name="objc", kind="dylib")]
 extern "C" {
+    #[cfg(not(feature="gnustep"))]
     fn objc_lookUpClass(name: * const c_char) -> *mut c_void;
+    #[cfg(feature="gnustep")]
+    #[link_name="objc_getClass"]
+    fn objc_lookUpClass(name: * const c_char) -> *mut c_void;
+    fn objc_allocateClassPair(superclass: *mut c_void, name: *const c_char, extra_bytes: usize) -> *mut c_void;
+    fn objc_registerClassPair(class: *mut c_void);
+    fn objc_disposeClassPair(class: *mut c_void);
+    fn object_getClass(object: *mut c_void) -> *mut c_void;
+    fn class_addMethod(class: *mut c_void, name: Sel, imp: *const c_void, types: *const c_char) -> bool;
+    fn class_addIvar(class: *mut c_void, name: *const c_char, size: usize, alignment: u8, types: *const c_char) -> bool;
+    fn class_addProtocol(class: *mut c_void, protocol: *mut c_void) -> bool;
+    fn class_getInstanceVariable(class: *mut c_void, name: *const c_char) -> *mut c_void;
+    fn ivar_getOffset(ivar: *mut c_void) -> isize;
 }
+#[cfg(feature="mock-runtime")]
+use crate::mock::runtime::{objc_getClass as objc_lookUpClass, objc_allocateClassPair, objc_registerClassPair, objc_disposeClassPair, object_getClass,
+    class_addMethod, class_addIvar, class_addProtocol, class_getInstanceVariable, ivar_getOffset};
 
 ///Untyped pointer to ObjC class.
 ///
@@ -78,10 +96,142 @@ impl<T: ObjcClass> Class<T> {
         let dynamic_class = objc_lookUpClass(cstr.as_ptr());
         &*(dynamic_class as *const Self)
     }
+    ///Calls `[Class conformsToProtocol:]`, which is true if the class (or a superclass) declares conformance.
+    pub fn conforms_to_protocol(&self, pool: &ActiveAutoreleasePool, protocol: &Protocol) -> bool {
+        unsafe {
+            Self::perform_primitive(self.assume_nonmut_perform(), Sel::conformsToProtocol_(), pool, (protocol.assume_nonmut_perform(),))
+        }
+    }
     ///Converts to an anyclass
     pub fn as_anyclass(&self) -> &'static AnyClass {
         unsafe{ &*(self as *const _ as *const AnyClass) }
     }
+    ///The offset of the named ivar from the start of an instance, or `None` if the class (and its superclasses) have no such ivar.
+    ///
+    /// Use this to find ivars added with [ClassBuilder::add_ivar()].
+    pub fn ivar_offset(&self, name: &CStr) -> Option<isize> {
+        unsafe {
+            let ivar = class_getInstanceVariable(self as *const Self as *mut c_void, name.as_ptr());
+            if ivar.is_null() { None } else { Some(ivar_getOffset(ivar)) }
+        }
+    }
+}
+
+///Creates a class at runtime.
+///
+/// This is the dynamic counterpart to [objc_subclass!], for when the class isn't known at compile time.  For example, you might
+/// want one class per plugin, or a name that doesn't collide with another copy of your code loaded into the same process.
+///
+/// The new class is a subclass of `T`'s class, and its instances are used from Rust as `T`.
+/// ```
+/// use objr::bindings::*;
+/// use std::ffi::CString;
+/// extern "C" fn answer(_objc_self: &NSObject, _sel: Sel) -> u64 { 42 }
+///
+/// let mut builder = ClassBuilder::<NSObject>::new_unique("PluginDelegate").unwrap();
+/// unsafe{ builder.add_method(Sel::from_str("answer"), answer as *const std::ffi::c_void, &CString::new("Q16@0:8").unwrap()) };
+/// let class = builder.register();
+/// autoreleasepool(|pool| {
+///     let instance = class.alloc_init(pool);
+///     let answer: u64 = unsafe{ NSObject::perform_primitive(instance.assume_nonmut_perform(), Sel::from_str("answer"), pool, ()) };
+///     assert_eq!(answer, 42);
+/// });
+/// ```
+///
+/// If the builder is dropped without calling [Self::register()], the class is disposed.
+#[derive(Debug)]
+pub struct ClassBuilder<T: ObjcClass> {
+    class: *mut c_void,
+    _marker: PhantomData<T>,
+}
+
+impl<T: ObjcClass + 'static> ClassBuilder<T> {
+    ///Starts a subclass of `T`'s class with the given name.  Returns `None` if the name is taken.
+    pub fn new(name: &CStr) -> Option<Self> {
+        let class = unsafe{ objc_allocateClassPair(T::class().as_anyclass() as *const AnyClass as *mut c_void, name.as_ptr(), 0) };
+        if class.is_null() {
+            None
+        }
+        else {
+            Some(ClassBuilder { class, _marker: PhantomData })
+        }
+    }
+    ///Starts a subclass of `T`'s class, named `prefix` or, if that is taken, `prefix_1`, `prefix_2`, etc.
+    ///
+    /// Gives up after [MAX_UNIQUE_NAMES](Self::MAX_UNIQUE_NAMES) names are taken.
+    pub fn new_unique(prefix: &str) -> Result<Self, ClassBuilderError> {
+        let prefix = CString::new(prefix).map_err(|e| ClassBuilderError::InvalidName(e.nul_position()))?;
+        if let Some(builder) = Self::new(&prefix) {
+            return Ok(builder);
+        }
+        let prefix = prefix.into_string().unwrap();
+        (1..Self::MAX_UNIQUE_NAMES).find_map(|n| {
+            //the prefix has no NUL, so neither does this
+            Self::new(&CString::new(format!("{}_{}",prefix,n)).unwrap())
+        }).ok_or(ClassBuilderError::NamesTaken)
+    }
+    ///The number of names [Self::new_unique()] tries.
+    pub const MAX_UNIQUE_NAMES: usize = 1000;
+    ///Adds an instance method.  Returns `false` if the class already declares a method with this selector.
+    ///
+    /// # Safety
+    /// `imp` must be an `extern "C"` function taking the receiver and selector, followed by the method's arguments,
+    /// and `types` must be its type encoding.  See the methods section of [objc_subclass!] for details.
+    pub unsafe fn add_method(&mut self, sel: Sel, imp: *const c_void, types: &CStr) -> bool {
+        class_addMethod(self.class, sel, imp, types.as_ptr())
+    }
+    ///Adds a class method.  Returns `false` if the class already declares a class method with this selector.
+    ///
+    /// # Safety
+    /// As for [Self::add_method()], except the function receives the class rather than an instance.
+    pub unsafe fn add_class_method(&mut self, sel: Sel, imp: *const c_void, types: &CStr) -> bool {
+        class_addMethod(object_getClass(self.class), sel, imp, types.as_ptr())
+    }
+    ///Adds an ivar with the size and alignment of `I`.  Returns `false` if the class already has an ivar of this name.
+    ///
+    /// Once the class is registered, find the ivar with [Class::ivar_offset()].  As with [objc_subclass!] ivars, the ivar is zeroed, and never dropped.
+    pub fn add_ivar<I>(&mut self, name: &CStr, types: &CStr) -> bool {
+        //log2, like objc_subclass! ivar lists
+        let alignment = std::mem::align_of::<I>().trailing_zeros() as u8;
+        unsafe{ class_addIvar(self.class, name.as_ptr(), std::mem::size_of::<I>(), alignment, types.as_ptr()) }
+    }
+    ///Declares conformance to a protocol.  Returns `false` if the class already conforms.
+    pub fn add_protocol(&mut self, protocol: &Protocol) -> bool {
+        unsafe{ class_addProtocol(self.class, protocol as *const Protocol as *mut c_void) }
+    }
+    ///Registers the class with the runtime, so it can be used.
+    pub fn register(self) -> &'static Class<T> {
+        let class = self.class;
+        std::mem::forget(self);
+        unsafe {
+            objc_registerClassPair(class);
+            &*(class as *const Class<T>)
+        }
+    }
+}
+
+///Why [ClassBuilder::new_unique()] couldn't start a class.
+#[derive(Debug)]
+pub enum ClassBuilderError {
+    ///The prefix contains a NUL byte, at this position
+    InvalidName(usize),
+    ///All [ClassBuilder::MAX_UNIQUE_NAMES] names are taken
+    NamesTaken,
+}
+impl std::fmt::Display for ClassBuilderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClassBuilderError::InvalidName(position) => write!(f, "Class name has a NUL byte at position {}", position),
+            ClassBuilderError::NamesTaken => write!(f, "All {} class names are taken", ClassBuilder::<NSObject>::MAX_UNIQUE_NAMES),
+        }
+    }
+}
+impl std::error::Error for ClassBuilderError {}
+
+impl<T: ObjcClass> Drop for ClassBuilder<T> {
+    fn drop(&mut self) {
+        unsafe{ objc_disposeClassPair(self.class) }
+    }
 }
 
 
@@ -125,6 +275,30 @@ impl<T: ObjcClass> Class<T> {
     }
 }
 
+impl AnyClass {
+    ///Looks up a class by its nul-terminated name, caching the result in `cache`.
+    ///
+    /// Used by [objc_class!] on runtimes where dyld does not fix up class references for us.
+    ///
+    /// # Panics
+    /// If the runtime has no class of this name.
+    #[doc(hidden)]
+    pub fn _cached(cache: &AtomicPtr<c_void>, name: &'static [u8]) -> &'static AnyClass {
+        let cached = cache.load(Ordering::Relaxed);
+        if !cached.is_null() {
+            return unsafe{ &*(cached as *const AnyClass) };
+        }
+        let class = unsafe{ objc_lookUpClass(name.as_ptr() as *const c_char) };
+        assert!(!class.is_null(), "No class named {}", String::from_utf8_lossy(&name[..name.len() - 1]));
+        //Each thread has its own mock runtime, so the answer can't be shared.
+        //Otherwise, racing here is harmless since the runtime gives the same answer each time
+        if !cfg!(feature="mock-runtime") {
+            cache.store(class, Ordering::Relaxed);
+        }
+        unsafe{ &*(class as *const AnyClass) }
+    }
+}
+
 impl<T: ObjcClass> std::fmt::Display for Class<T> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let r = unsafe {
@@ -274,12 +448,14 @@ macro_rules! objc_class_newtype {
 
 
 #[test]
+#[cfg_attr(feature="mock-runtime", ignore = "needs Foundation")]
 fn alloc_ns_object() {
     use std::ffi::CString;
     let class = unsafe { Class::<NSObject>::from_str(CString::new("NSObject").unwrap().as_c_str() ) };
     println!("{}",class);
 }
 #[test]
+#[cfg_attr(feature="mock-runtime", ignore = "needs Foundation")]
 fn init_ns_object() {
     use crate::autorelease::AutoreleasePool;
     let pool = unsafe{ AutoreleasePool::new() };
@@ -291,3 +467,32 @@ fn init_ns_object() {
     assert!(description.to_str(&pool).starts_with("<NSObject"))
 }
 
+
+#[test] fn class_builder() {
+    use crate::autorelease::AutoreleasePool;
+    extern "C" fn answer(_objc_self: &NSObject, _sel: Sel) -> u64 { 42 }
+    extern "C" fn class_answer(_class: &AnyClass, _sel: Sel) -> u64 { 43 }
+    let pool = unsafe{ AutoreleasePool::new() };
+    let mut builder = ClassBuilder::<NSObject>::new_unique("ClassBuilderTest").unwrap();
+    unsafe {
+        assert!(builder.add_method(Sel::from_str("answer"), answer as *const c_void, &CString::new("Q16@0:8").unwrap()));
+        assert!(builder.add_class_method(Sel::from_str("answer"), class_answer as *const c_void, &CString::new("Q16@0:8").unwrap()));
+    }
+    assert!(builder.add_ivar::<u32>(&CString::new("count").unwrap(), &CString::new("I").unwrap()));
+    assert!(!builder.add_ivar::<u32>(&CString::new("count").unwrap(), &CString::new("I").unwrap()));
+    let class = builder.register();
+    //the name was taken, so the next one is numbered
+    let second = ClassBuilder::<NSObject>::new_unique("ClassBuilderTest").unwrap().register();
+    assert_ne!(class.as_anyclass(), second.as_anyclass());
+    assert!(matches!(ClassBuilder::<NSObject>::new_unique("ClassBuilder\0Test"), Err(ClassBuilderError::InvalidName(12))));
+
+    assert!(class.ivar_offset(&CString::new("count").unwrap()).unwrap() >= std::mem::size_of::<usize>() as isize);
+    assert!(class.ivar_offset(&CString::new("missing").unwrap()).is_none());
+    let instance = class.alloc_init(&pool);
+    unsafe {
+        let answer: u64 = NSObject::perform_primitive(instance.assume_nonmut_perform(), Sel::from_str("answer"), &pool, ());
+        assert_eq!(answer, 42);
+        let class_answer: u64 = Class::<NSObject>::perform_primitive(class.assume_nonmut_perform(), Sel::from_str("answer"), &pool, ());
+        assert_eq!(class_answer, 43);
+    }
+}
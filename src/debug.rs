@@ -0,0 +1,225 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+//! Tracks the references held by objr's cells, with the `memory-tracing` feature.
+//!
+//! Each time a cell retains an object, adopts a +1 reference (for example from `alloc_init`), releases or autoreleases it,
+//! or gives it away with `into_raw`, the event is recorded in a process-wide registry.  [live_objects()] lists the objects
+//! that cells still hold references to, and [assert_no_leaks()] checks that a closure doesn't leave any behind.
+//!
+//! This counts the references owned by cells, not the runtime's retain count, so references held by ObjC
+//! (say, an `NSArray` containing the object) don't show up.  Nor do references given away with `into_raw` or `leak`,
+//! or stored into an atomic cell, which belong to someone else until they are taken back.
+//!
+//! Adopted references are recorded by [NonNullImmutable::assume_retained()](crate::bindings::NonNullImmutable::assume_retained()) (which is how
+//! `alloc_init` and bindings returning +1 objects make their cells) and the `from_raw` functions.  The `const` constructors like
+//! [StrongCell::assume_retained()](crate::bindings::StrongCell::assume_retained()) can't record anything, so objects adopted with
+//! them aren't tracked; releasing such an object while other cells hold references to it undercounts those references.
+//!
+//! Once cells hold no references to an object, a later object at the same address is a new generation, with its own [LiveObject].
+//!
+//! Each event records the source location of the call into objr that caused it.  When backtraces are enabled, with `RUST_BACKTRACE`
+//! or `RUST_LIB_BACKTRACE` as for [Backtrace::capture()], each event records a backtrace too.  This needs Rust 1.65.
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::fmt::Formatter;
+use std::panic::Location;
+use std::sync::{Arc, Mutex};
+use std::thread::ThreadId;
+
+///Something a cell did to an object's reference count.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Event {
+    ///A cell retained the object, +1
+    Retain,
+    ///A cell took ownership of an existing +1 reference, like the result of `init`
+    Adopt,
+    ///A cell released the object, -1
+    Release,
+    ///A cell autoreleased the object, handing its reference to the pool, -1
+    Autorelease,
+    ///A cell gave its reference away with `into_raw`, -1
+    IntoRaw,
+}
+
+///An [Event], and where it happened.
+#[derive(Clone)]
+pub struct Record {
+    pub event: Event,
+    ///The call into objr that caused the event
+    pub location: &'static Location<'static>,
+    ///The backtrace of the event, if backtraces are enabled
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+///An object that cells hold references to.
+#[derive(Clone)]
+pub struct LiveObject {
+    ///The type of the cell that first saw the object
+    pub type_name: &'static str,
+    pub ptr: *const c_void,
+    ///The number of references held by cells
+    pub references: usize,
+    ///What happened to the object, and where
+    pub events: Vec<Record>,
+    ///Tells apart objects that cells referenced at the same address at different times.  Increases over time.
+    pub generation: u64,
+    thread: ThreadId,
+}
+impl std::fmt::Debug for LiveObject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {:p} ({} references)", self.type_name, self.ptr, self.references)?;
+        for record in &self.events {
+            write!(f, "\n    {:?} at {}", record.event, record.location)?;
+            if let Some(backtrace) = &record.backtrace {
+                write!(f, "\n{}", backtrace)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Registry {
+    ///Keyed by address and generation
+    objects: HashMap<(usize, u64), LiveObject>,
+    ///The current generation at each address that cells hold references to
+    generations: HashMap<usize, u64>,
+    next_generation: u64,
+}
+//the pointers are only compared and printed, never dereferenced
+unsafe impl Send for Registry {}
+
+static REGISTRY: Mutex<Option<Registry>> = Mutex::new(None);
+
+fn with_registry<R>(f: impl FnOnce(&mut Registry) -> R) -> R {
+    //a panic while holding the lock leaves the registry consistent, so don't poison every later test
+    let mut lock = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    f(lock.get_or_insert_with(|| Registry { objects: HashMap::new(), generations: HashMap::new(), next_generation: 0 }))
+}
+
+///Records an event for `ptr`.  Called by the cells.
+#[track_caller]
+pub(crate) fn record(event: Event, type_name: &'static str, ptr: *const c_void) {
+    let backtrace = Backtrace::capture();
+    let record = Record {
+        event,
+        location: Location::caller(),
+        backtrace: if backtrace.status() == BacktraceStatus::Captured { Some(Arc::new(backtrace)) } else { None },
+    };
+    with_registry(|registry| {
+        match event {
+            Event::Retain | Event::Adopt => {
+                let next_generation = &mut registry.next_generation;
+                let generation = *registry.generations.entry(ptr as usize).or_insert_with(|| {
+                    *next_generation += 1;
+                    *next_generation - 1
+                });
+                let object = registry.objects.entry((ptr as usize, generation)).or_insert_with(|| LiveObject {
+                    type_name, ptr, references: 0, events: Vec::new(), generation, thread: std::thread::current().id(),
+                });
+                object.references += 1;
+                object.events.push(record);
+            }
+            Event::Release | Event::Autorelease | Event::IntoRaw => {
+                //references we didn't see adopted are ignored
+                let key = match registry.generations.get(&(ptr as usize)) {
+                    Some(generation) => (ptr as usize, *generation),
+                    None => return,
+                };
+                let object = registry.objects.get_mut(&key).unwrap();
+                object.references -= 1;
+                object.events.push(record);
+                if object.references == 0 {
+                    registry.objects.remove(&key);
+                    registry.generations.remove(&(ptr as usize));
+                }
+            }
+        }
+    })
+}
+
+///Returns the objects that cells hold references to, on any thread.
+pub fn live_objects() -> Vec<LiveObject> {
+    with_registry(|registry| {
+        let mut objects: Vec<LiveObject> = registry.objects.values().cloned().collect();
+        objects.sort_by_key(|o| o.generation);
+        objects
+    })
+}
+
+/**
+Runs `f`, and panics if any object that a cell first referenced during `f`, on this thread, is still referenced afterwards.
+
+Objects from other threads are ignored, so tests can run in parallel.  Make sure any autorelease pool that `f` uses is
+drained before it returns, since an object the pool owns is fine but the cells borrowing from it are not what's checked here.
+
+```
+use objr::bindings::*;
+objr::debug::assert_no_leaks(|| {
+    autoreleasepool(|pool| {
+        let object = NSObject::class().alloc_init(pool);
+        let copy = object.clone();
+        drop((object, copy));
+    })
+});
+```
+*/
+#[track_caller]
+pub fn assert_no_leaks<R, F: FnOnce() -> R>(f: F) -> R {
+    let start = with_registry(|registry| registry.next_generation);
+    let r = f();
+    let thread = std::thread::current().id();
+    let leaks: Vec<LiveObject> = live_objects().into_iter().filter(|o| o.generation >= start && o.thread == thread).collect();
+    if !leaks.is_empty() {
+        let descriptions: Vec<String> = leaks.iter().map(|o| format!("{:?}", o)).collect();
+        panic!("{} objects leaked:\n{}", leaks.len(), descriptions.join("\n"));
+    }
+    r
+}
+
+#[test] fn tracks_cells() {
+    use objr::bindings::*;
+    autoreleasepool(|pool| {
+        let object = NSObject::class().alloc_init(pool);
+        let ptr = StrongCell::as_ptr(&object) as *const c_void;
+        let copy = object.clone();
+        let live = live_objects().into_iter().find(|o| o.ptr == ptr).unwrap();
+        assert_eq!(live.references, 2);
+        assert_eq!(live.events.iter().map(|r| r.event).collect::<Vec<_>>(), vec![Event::Adopt, Event::Retain]);
+        let backtraces = Backtrace::capture().status() == BacktraceStatus::Captured;
+        assert!(live.events.iter().all(|r| r.backtrace.is_some() == backtraces));
+        drop(copy);
+        let raw = StrongCell::into_raw(object);
+        assert!(live_objects().iter().all(|o| o.ptr != ptr));
+        drop(unsafe{ StrongCell::from_raw(raw) });
+    })
+}
+
+#[test] fn assume_retained_recorded() {
+    use objr::bindings::*;
+    autoreleasepool(|pool| {
+        let object = NSObject::class().alloc_init(pool);
+        let ptr = StrongCell::as_ptr(&object) as *const c_void;
+        //a second +1 reference, adopted directly
+        let raw = StrongCell::into_raw(object.clone());
+        let adopted = unsafe{ NSObject::assume_nonnil(raw).assume_retained() };
+        let live = live_objects().into_iter().find(|o| o.ptr == ptr).unwrap();
+        assert_eq!(live.references, 2);
+        drop(adopted);
+        //still held by `object`
+        let live = live_objects().into_iter().find(|o| o.ptr == ptr).unwrap();
+        assert_eq!(live.references, 1);
+        drop(object);
+        assert!(live_objects().iter().all(|o| o.ptr != ptr));
+    })
+}
+
+#[test] #[should_panic(expected = "1 objects leaked:")]
+fn leak_detected() {
+    use objr::bindings::*;
+    assert_no_leaks(|| {
+        autoreleasepool(|pool| {
+            std::mem::forget(NSObject::class().alloc_init(pool));
+        })
+    });
+}
@@ -0,0 +1,389 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+//! ObjC type encodings for Rust types, computed at compile time.
+//!
+//! The runtime describes every method, ivar and property with a [type encoding](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/ObjCRuntimeGuide/Articles/ocrtTypeEncodings.html).
+//! For ObjC types spelled out in a declaration, our procmacros can work these out by themselves.  For types declared
+//! in Rust, such as a `#[repr(C)]` struct passed to a subclass method, or the arguments to a `perform` call, the encoding comes from [Encode].
+
+use std::ffi::c_void;
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+use crate::bindings::{AnyClass, Class, ObjcClass, Protocol, Sel};
+
+///A type encoding, as a tree that can be built in a `const`.
+///
+/// Display this type to get the encoding string the runtime uses.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum Encoding {
+    ///A type encoded as a fixed string, like `i` for `int` or `@` for an object
+    Primitive(&'static str),
+    ///`^type`
+    Pointer(&'static Encoding),
+    ///`[countType]`
+    Array(usize, &'static Encoding),
+    ///`{name=fields}`
+    Struct(&'static str, &'static [Encoding]),
+    ///`(name=fields)`
+    Union(&'static str, &'static [Encoding]),
+    ///`bwidth`, for a bitfield `width` bits wide
+    Bitfield(u8),
+}
+
+///Types with a known ObjC type encoding.
+///
+/// This is implemented for the types in the [Primitive](objr::bindings::Primitive) and [Arguable](objr::bindings::Arguable)
+/// families, like integers, `bool`, [Sel](objr::bindings::Sel) and `()`, and for pointers and references to any [EncodeRef] type,
+/// such as `&NSObject` (`@`), `*mut AnyClass` (`#`) or `*const u32` (`^I`).
+///
+/// For `#[repr(C)]` structs and unions, this can be derived with `#[derive(ObjcEncode)]`:
+/// ```
+/// use objr::bindings::*;
+/// #[derive(ObjcEncode)]
+/// #[repr(C)]
+/// struct Span {
+///     start: u64,
+///     weights: [f32; 2],
+///     owner: *const NSObject,
+/// }
+/// assert_eq!(Span::ENCODING.to_string(), "{Span=Q[2f]@}");
+/// assert_eq!(<*const Span>::ENCODING.to_string(), "^{Span=Q[2f]@}");
+/// ```
+/// The struct is encoded under its Rust name, so if you pass it to ObjC code that declares the same struct, use the same name.
+/// Deriving is not supported for generic types.
+pub trait Encode {
+    const ENCODING: Encoding;
+}
+
+///Types whose pointers have a known ObjC type encoding.
+///
+/// This is how ObjC objects, which are only ever handled by reference, get an encoding.  [objc_instance!](objr::bindings::objc_instance!)
+/// and friends implement it for you, as `@`.  For other types, pointers are usually encoded as `^type`.
+pub trait EncodeRef {
+    ///The encoding of `*const Self`
+    const ENCODING_REF: Encoding;
+}
+
+macro_rules! encode_impl {
+    ($($type:ty => $encoding:literal),*) => {
+        $(
+        impl Encode for $type {
+            const ENCODING: Encoding = Encoding::Primitive($encoding);
+        }
+        impl EncodeRef for $type {
+            const ENCODING_REF: Encoding = Encoding::Pointer(&Self::ENCODING);
+        }
+        )*
+    }
+}
+//On 64-bit Apple platforms, `long` is encoded like `long long`
+encode_impl!(i16 => "s", u16 => "S", i32 => "i", u32 => "I", i64 => "q", u64 => "Q",
+    isize => "q", usize => "Q", f32 => "f", f64 => "d", bool => "B", () => "v", c_void => "v", Sel => ":");
+
+//Like clang, a pointer to any kind of `char` is a C string
+impl Encode for i8 {
+    const ENCODING: Encoding = Encoding::Primitive("c");
+}
+impl EncodeRef for i8 {
+    const ENCODING_REF: Encoding = Encoding::Primitive("*");
+}
+impl Encode for u8 {
+    const ENCODING: Encoding = Encoding::Primitive("C");
+}
+impl EncodeRef for u8 {
+    const ENCODING_REF: Encoding = Encoding::Primitive("*");
+}
+
+impl EncodeRef for AnyClass {
+    const ENCODING_REF: Encoding = Encoding::Primitive("#");
+}
+impl<T: ObjcClass> EncodeRef for Class<T> {
+    const ENCODING_REF: Encoding = Encoding::Primitive("#");
+}
+impl EncodeRef for Protocol {
+    const ENCODING_REF: Encoding = Encoding::Primitive("@");
+}
+
+macro_rules! encode_pointer_impl {
+    ($($type:ty),*) => {
+        $(
+        impl<T: EncodeRef + ?Sized> Encode for $type {
+            const ENCODING: Encoding = T::ENCODING_REF;
+        }
+        impl<T: EncodeRef + ?Sized> EncodeRef for $type {
+            const ENCODING_REF: Encoding = Encoding::Pointer(&T::ENCODING_REF);
+        }
+        )*
+    }
+}
+encode_pointer_impl!(*const T, *mut T, NonNull<T>, Option<NonNull<T>>);
+
+//Can't use the macro for these, because of the lifetimes
+impl<T: EncodeRef + ?Sized> Encode for &T {
+    const ENCODING: Encoding = T::ENCODING_REF;
+}
+impl<T: EncodeRef + ?Sized> EncodeRef for &T {
+    const ENCODING_REF: Encoding = Encoding::Pointer(&T::ENCODING_REF);
+}
+impl<T: EncodeRef + ?Sized> Encode for &mut T {
+    const ENCODING: Encoding = T::ENCODING_REF;
+}
+impl<T: EncodeRef + ?Sized> EncodeRef for &mut T {
+    const ENCODING_REF: Encoding = Encoding::Pointer(&T::ENCODING_REF);
+}
+impl<T: EncodeRef + ?Sized> Encode for Option<&T> {
+    const ENCODING: Encoding = T::ENCODING_REF;
+}
+impl<T: EncodeRef + ?Sized> EncodeRef for Option<&T> {
+    const ENCODING_REF: Encoding = Encoding::Pointer(&T::ENCODING_REF);
+}
+impl<T: EncodeRef + ?Sized> Encode for Option<&mut T> {
+    const ENCODING: Encoding = T::ENCODING_REF;
+}
+impl<T: EncodeRef + ?Sized> EncodeRef for Option<&mut T> {
+    const ENCODING_REF: Encoding = Encoding::Pointer(&T::ENCODING_REF);
+}
+
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    const ENCODING: Encoding = Encoding::Array(N, &T::ENCODING);
+}
+impl<T: Encode, const N: usize> EncodeRef for [T; N] {
+    const ENCODING_REF: Encoding = Encoding::Pointer(&Self::ENCODING);
+}
+
+///With the `verify-sends` feature, the types in a message send must have encodings, so we can check them.
+///
+/// Without the feature, this is implemented for everything.
+#[doc(hidden)]
+#[cfg(feature="verify-sends")]
+pub trait _VerifyEncode: Encode + EncodeRef {}
+#[cfg(feature="verify-sends")]
+impl<T: Encode + EncodeRef> _VerifyEncode for T {}
+#[doc(hidden)]
+#[cfg(not(feature="verify-sends"))]
+pub trait _VerifyEncode {}
+#[cfg(not(feature="verify-sends"))]
+impl<T> _VerifyEncode for T {}
+
+///The encoding of an ivar declared with `objc_subclass!`: `T::ENCODING` if `T` implements [Encode], otherwise `?`,
+/// from [_IvarEncodingFallback].
+///
+/// Inherent associated items are found before trait ones, but only when `T: Encode`.
+#[doc(hidden)]
+pub struct _IvarEncoding<T: ?Sized>(PhantomData<T>);
+impl<T: Encode> _IvarEncoding<T> {
+    pub const ENCODING: Encoding = T::ENCODING;
+}
+#[doc(hidden)]
+pub trait _IvarEncodingFallback {
+    const ENCODING: Encoding = Encoding::Primitive("?");
+}
+impl<T: ?Sized> _IvarEncodingFallback for _IvarEncoding<T> {}
+
+/*These functions build encoding strings at compile time.  Since const fns can't take `&mut` on our MSRV,
+each writer takes the buffer by value and hands it back along with the next position.
+ */
+
+const fn decimal_len(mut value: usize) -> usize {
+    let mut len = 1;
+    while value >= 10 {
+        value /= 10;
+        len += 1;
+    }
+    len
+}
+
+const fn write_decimal<const N: usize>(mut buffer: [u8; N], at: usize, value: usize) -> ([u8; N], usize) {
+    let len = decimal_len(value);
+    let mut remaining = value;
+    let mut index = len;
+    while index > 0 {
+        index -= 1;
+        buffer[at + index] = b'0' + (remaining % 10) as u8;
+        remaining /= 10;
+    }
+    (buffer, at + len)
+}
+
+const fn write_str<const N: usize>(mut buffer: [u8; N], at: usize, str: &str) -> ([u8; N], usize) {
+    let bytes = str.as_bytes();
+    let mut index = 0;
+    while index < bytes.len() {
+        buffer[at + index] = bytes[index];
+        index += 1;
+    }
+    (buffer, at + bytes.len())
+}
+
+const fn fields_len(fields: &[Encoding]) -> usize {
+    let mut len = 0;
+    let mut index = 0;
+    while index < fields.len() {
+        len += fields[index].encoded_len();
+        index += 1;
+    }
+    len
+}
+
+const fn write_fields<const N: usize>(mut buffer: [u8; N], mut at: usize, fields: &[Encoding]) -> ([u8; N], usize) {
+    let mut index = 0;
+    while index < fields.len() {
+        (buffer, at) = fields[index].write(buffer, at);
+        index += 1;
+    }
+    (buffer, at)
+}
+
+impl Encoding {
+    ///Length of the encoding string, in bytes
+    pub const fn encoded_len(&self) -> usize {
+        match self {
+            Encoding::Primitive(str) => str.len(),
+            Encoding::Pointer(pointee) => 1 + pointee.encoded_len(),
+            Encoding::Array(count, element) => 2 + decimal_len(*count) + element.encoded_len(),
+            Encoding::Struct(name, fields) | Encoding::Union(name, fields) => 3 + name.len() + fields_len(fields),
+            Encoding::Bitfield(width) => 1 + decimal_len(*width as usize),
+        }
+    }
+
+    const fn write<const N: usize>(&self, buffer: [u8; N], at: usize) -> ([u8; N], usize) {
+        match self {
+            Encoding::Primitive(str) => write_str(buffer, at, str),
+            Encoding::Pointer(pointee) => {
+                let (buffer, at) = write_str(buffer, at, "^");
+                pointee.write(buffer, at)
+            }
+            Encoding::Array(count, element) => {
+                let (buffer, at) = write_str(buffer, at, "[");
+                let (buffer, at) = write_decimal(buffer, at, *count);
+                let (buffer, at) = element.write(buffer, at);
+                write_str(buffer, at, "]")
+            }
+            Encoding::Struct(name, fields) | Encoding::Union(name, fields) => {
+                let (open, close) = if matches!(self, Encoding::Struct(..)) { ("{", "}") } else { ("(", ")") };
+                let (buffer, at) = write_str(buffer, at, open);
+                let (buffer, at) = write_str(buffer, at, name);
+                let (buffer, at) = write_str(buffer, at, "=");
+                let (buffer, at) = write_fields(buffer, at, fields);
+                write_str(buffer, at, close)
+            }
+            Encoding::Bitfield(width) => {
+                let (buffer, at) = write_str(buffer, at, "b");
+                write_decimal(buffer, at, *width as usize)
+            }
+        }
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Encoding::Primitive(str) => f.write_str(str),
+            Encoding::Pointer(pointee) => write!(f, "^{}", pointee),
+            Encoding::Array(count, element) => write!(f, "[{}{}]", count, element),
+            Encoding::Struct(name, fields) | Encoding::Union(name, fields) => {
+                let (open, close) = if matches!(self, Encoding::Struct(..)) { ("{", "}") } else { ("(", ")") };
+                write!(f, "{}{}=", open, name)?;
+                for field in fields.iter() {
+                    write!(f, "{}", field)?;
+                }
+                f.write_str(close)
+            }
+            Encoding::Bitfield(width) => write!(f, "b{}", width),
+        }
+    }
+}
+
+///Builds `encoding` as a nul-terminated string.  `N` must be one more than [Encoding::encoded_len()].
+#[doc(hidden)]
+pub const fn _type_encoding<const N: usize>(encoding: &Encoding) -> [u8; N] {
+    let (buffer, at) = encoding.write([0; N], 0);
+    assert!(at + 1 == N, "type encoding has the wrong length");
+    buffer
+}
+
+///Frame size of a method argument.  Like clang, arguments smaller than an `int` take up an `int`.
+pub(crate) const fn frame_size(size: usize) -> usize {
+    if size < 4 { 4 } else { size }
+}
+
+///Length of the method type encoding built by [_method_type_encoding], including the trailing nul.
+///
+/// `arguments` are each argument's encoding and size, including `self` and `_cmd`.
+#[doc(hidden)]
+pub const fn _method_type_encoding_len(return_type: &Encoding, arguments: &[(Encoding, usize)]) -> usize {
+    let mut len = return_type.encoded_len();
+    let mut offset = 0;
+    let mut index = 0;
+    while index < arguments.len() {
+        len += arguments[index].0.encoded_len() + decimal_len(offset);
+        offset += frame_size(arguments[index].1);
+        index += 1;
+    }
+    len + decimal_len(offset) + 1
+}
+
+///Builds a method type encoding like `v24@0:8i16f20`, as a nul-terminated string.
+///
+/// `N` must be [_method_type_encoding_len].  This is used by `objc_subclass!` for declarations involving
+/// types that implement [Encode], whose encodings and sizes aren't known to the procmacro.
+#[doc(hidden)]
+pub const fn _method_type_encoding<const N: usize>(return_type: &Encoding, arguments: &[(Encoding, usize)]) -> [u8; N] {
+    let mut frame = 0;
+    let mut index = 0;
+    while index < arguments.len() {
+        frame += frame_size(arguments[index].1);
+        index += 1;
+    }
+    let (mut buffer, mut at) = return_type.write([0; N], 0);
+    (buffer, at) = write_decimal(buffer, at, frame);
+    let mut offset = 0;
+    index = 0;
+    while index < arguments.len() {
+        (buffer, at) = arguments[index].0.write(buffer, at);
+        (buffer, at) = write_decimal(buffer, at, offset);
+        offset += frame_size(arguments[index].1);
+        index += 1;
+    }
+    assert!(at + 1 == N, "method type encoding has the wrong length");
+    buffer
+}
+
+#[test] fn encodings() {
+    #[derive(objr::bindings::ObjcEncode)]
+    #[repr(C)]
+    struct Inner(f64, f64);
+    #[derive(objr::bindings::ObjcEncode)]
+    #[repr(C)]
+    #[allow(dead_code)]
+    union Value {
+        integer: i32,
+        pointer: *const u8,
+    }
+    #[derive(objr::bindings::ObjcEncode)]
+    #[repr(C)]
+    struct Outer {
+        _inner: Inner,
+        _values: [Value; 12],
+        _next: *mut Inner,
+    }
+    assert_eq!(Outer::ENCODING.to_string(), "{Outer={Inner=dd}[12(Value=i*)]^{Inner=dd}}");
+    assert_eq!(Encoding::Bitfield(3).to_string(), "b3");
+
+    use objr::bindings::{NSObject, NSString, AnyClass, Class, Sel};
+    assert_eq!(<&NSObject>::ENCODING.to_string(), "@");
+    assert_eq!(<Option<&mut NSString>>::ENCODING.to_string(), "@");
+    assert_eq!(<*mut *const NSObject>::ENCODING.to_string(), "^@");
+    assert_eq!(<&Class<NSObject>>::ENCODING.to_string(), "#");
+    assert_eq!(<*mut AnyClass>::ENCODING.to_string(), "#");
+    assert_eq!(<*const std::os::raw::c_char>::ENCODING.to_string(), "*");
+    assert_eq!(<*mut c_void>::ENCODING.to_string(), "^v");
+    assert_eq!(<NonNull<u32>>::ENCODING.to_string(), "^I");
+    assert_eq!(Sel::ENCODING.to_string(), ":");
+
+    const RETURN: Encoding = Outer::ENCODING;
+    const ARGUMENTS: [(Encoding, usize); 4] = [(Encoding::Primitive("@"), 8), (Encoding::Primitive(":"), 8), (Inner::ENCODING, 16), (u8::ENCODING, 1)];
+    const ENCODED: [u8; _method_type_encoding_len(&RETURN, &ARGUMENTS)] = _method_type_encoding(&RETURN, &ARGUMENTS);
+    let expected = format!("{}36@0:8{{Inner=dd}}16C32\0", Outer::ENCODING);
+    assert_eq!(std::str::from_utf8(&ENCODED).unwrap(), expected);
+}
@@ -1,13 +1,16 @@
 //SPDX-License-Identifier: MIT OR Apache-2.0
 ///! Support for objc exceptions.
 
+#[cfg(not(feature="mock-runtime"))]
 use std::ffi::c_void;
 
 //Declared in hard-exception.m and compiled with build.rs
+#[cfg(not(feature="mock-runtime"))]
 extern "C" {
     fn hard_exception(call: extern "C" fn(*mut c_void), context: *mut c_void  );
 }
 
+#[cfg(not(feature="mock-runtime"))]
 extern "C" fn thunk_void<F: FnOnce()>(context: &mut Option<F>) -> *mut c_void {
     println!("Thunk_void");
     let f = context.take().unwrap();
@@ -17,12 +20,18 @@ extern "C" fn thunk_void<F: FnOnce()>(context: &mut Option<F>) -> *mut c_void {
 ///This function catches an objc exception raised in the closure.
 ///
 /// Return values are not supported, this is primarily intended to facilitate debugging.
+#[cfg(not(feature="mock-runtime"))]
 pub fn try_unwrap_void<F: FnOnce()>(closure: F){
     println!("Try unwrap void");
     let thunk_fn = thunk_void::<F> as extern "C" fn(&mut Option<F>) -> *mut c_void;
     let mut closure_indirect = Some(closure);
     unsafe{ hard_exception(std::mem::transmute(thunk_fn), std::mem::transmute(&mut closure_indirect)) };
 }
+///The mock runtime has no exceptions, so this just calls the closure.
+#[cfg(feature="mock-runtime")]
+pub fn try_unwrap_void<F: FnOnce()>(closure: F){
+    closure()
+}
 
 
 #[test] fn test_catch() {
@@ -31,12 +31,13 @@ objc_class! {
         @class(NSDate)
     }
 }
+# #[cfg(not(feature="mock-runtime"))] //the mock runtime has no Foundation classes
 autoreleasepool(|pool| {
     //In this library, autoreleasepools are often arguments to ObjC-calling APIs, providing static guarantees you created one.
     //Forgetting this is a common ObjC bug.
     let date = NSDate::class().alloc_init(&pool);
     println!("{}",date); // 2021-06-21 19:03:15 +0000
-})
+});
 ```
 
 Compare this with [[objc_instance!]] for non-class instances.
@@ -90,12 +91,13 @@ impl NSDate {
         }
     }
 }
+# #[cfg(not(feature="mock-runtime"))] //the mock runtime has no Foundation classes
 autoreleasepool(|pool| {
     //In this library, autoreleasepools are often arguments to ObjC-calling APIs, providing compile-time guarantees you created one.
     //Forgetting this is a common ObjC bug.
     let date = NSDate::class().alloc_init(&pool);
     let new_date = date.dateByAddingTimeInterval(&pool, 23.5);
-})
+});
 
 ```
 
@@ -108,15 +110,32 @@ For more examples, see the documentation for [objc_instance!].
 * Participate in [runtime autorelease eliding](objr::performselector::PerformsSelector::perform_autorelease_to_retain()) which reduces memory overhead when calling system code
 This means that for programs that are mostly Rust, codegeneration may be significantly better even than real ObjC programs.
 * Pointer packing for `Option<&NSObject>`
-* Smart pointer system, with support for [bindings::StrongCell] and [bindings::AutoreleasedCell]
-* [Subclassing directly from Rust](objc_subclass!())
+* Smart pointer system, with support for [bindings::StrongCell], [bindings::AutoreleasedCell], [bindings::WeakCell] and [bindings::AtomicStrongCell]
+* [Subclassing directly from Rust](objc_subclass!()), or [at runtime](bindings::ClassBuilder)
+* Compile-time [type encodings](bindings::Encode) for Rust types, derivable for `#[repr(C)]` structs
+* Pass and return [`#[repr(C)]` structs](bindings::Primitive#structs) by value, with the right `objc_msgSend` variant
 * (limited) support for [mutability and exclusive references](objc_instance!()#Mutability) in imported types
+* Generate first-draft bindings from ObjC headers with `objr-bindgen` (in the `bindgen` directory of the repository)
 
 Not yet implemented, but planned or possible:
 
 * iOS support
 * Exceptions (Debug-quality API available now, see [[bindings::try_unwrap_void]])
 
+# Cargo features
+
+* `gnustep`: target the [GNUstep libobjc2](https://github.com/gnustep/libobjc2) runtime and gnustep-base, e.g. on Linux.
+  Selectors, classes and string literals are looked up (once) at runtime rather than fixed up by dyld,
+  messages are sent via `objc_msg_lookup`, and [subclasses](objc_subclass!()) are registered on first use of `class()`.
+* `verify-sends`: check each message send against the runtime's type encoding for the method, and panic if the Rust
+  argument or return types don't match.  This is slow, so it's meant for debug builds and tests.  Argument and return types must implement [bindings::Encode].
+* `verify-pools`: check that autorelease pools are popped in order and that [bindings::AutoreleasedCell]s aren't used
+  after the pool their object went into is drained, and warn when a message is sent with no pool on the thread, as when `assume_autoreleasepool()` is used on a background thread.
+* `memory-tracing`: record the retains, releases and autoreleases done by objr's cells, so tests can find leaked objects
+  with `debug::live_objects()` and `debug::assert_no_leaks()`, with backtraces if `RUST_BACKTRACE` is set.  See the `debug` module.  Needs Rust 1.65.
+* `mock-runtime`: replace the ObjC runtime with an in-process fake written in Rust, so bindings can be unit tested on any machine.
+  See the `mock` module.  This takes precedence over `gnustep` if both are enabled.
+
 # Design limitations
 
 This library intends to follow normal guidelines for safe Rust.  However, calling into ObjC means there's
@@ -147,12 +166,13 @@ to binding Apple technologies to Rust.
 extern crate self as objr;
 pub mod macros;
 mod class;
+mod protocol;
 
 mod objectpointers;
 
 mod nsobject;
 mod nsstring;
-mod autorelease;
+pub mod autorelease;
 mod arguments;
 
 mod performselector;
@@ -164,6 +184,13 @@ mod subclass;
 mod exception;
 mod threadsafety;
 mod cast;
+mod encode;
+#[cfg(feature="verify-sends")]
+mod verify;
+#[cfg(feature="memory-tracing")]
+pub mod debug;
+#[cfg(feature="mock-runtime")]
+pub mod mock;
 
 
 ///This prelude provides a "foundation-like" experience.  This brings
@@ -179,7 +206,7 @@ pub mod foundation {
     pub use super::class::ObjcClass;
     pub use super::nserror::{NSError};
     pub use objr_procmacro::objc_nsstring;
-    pub use super::autorelease::autoreleasepool;
+    pub use super::autorelease::{autoreleasepool,for_each_drained,Drain,DrainEvery,DrainingIterator};
     pub use super::bindings::ObjcInstanceBehavior;
     pub use super::nserror::ResultNSError;
 
@@ -188,13 +215,15 @@ pub mod foundation {
 ///This namespace includes items that are appropriate for writing bindings
 pub mod bindings {
     pub use super::autorelease::{ActiveAutoreleasePool,AutoreleasePool};
-    pub use super::objectpointers::{StrongCell,AutoreleasedCell,StrongMutCell,AutoreleasedMutCell,StrongLifetimeCell};
+    #[cfg(feature="verify-pools")]
+    pub use super::autorelease::autoreleasepool_depth;
+    pub use super::objectpointers::{StrongCell,AutoreleasedCell,StrongMutCell,AutoreleasedMutCell,StrongLifetimeCell,WeakCell,AtomicStrongCell,AtomicOptionalStrongCell};
     pub use super::sel::Sel;
     pub use super::nsobject::NSObjectTrait;
     pub use super::nsobject::NSObject;
     pub use super::objcinstance::{ObjcInstance,OptionalInstanceBehavior,NonNullImmutable,NullableBehavior};
     pub use super::performselector::{PerformsSelector,PerformablePointer,PerformsSelectorSuper};
-    pub use super::class::{Class};
+    pub use super::class::{Class,ClassBuilder,ClassBuilderError};
     pub use super::foundation::*;
     pub use objr::objcinstance::NullableCellBehavior;
     //import macros
@@ -207,9 +236,12 @@ pub mod bindings {
     pub use crate::objc_class_newtype;
     pub use crate::objc_cast;
     pub use crate::cast::ReinterpretCast;
-    pub use objr_procmacro::{__objc_implement_class,ObjcInstance,__static_expr,__static_extern,__static_asciiz_ident_as_selector,__static_asciiz_ident_as_type_encoding,__count,__concat_idents,__static_asciiz,__static_expr3,__concat_3_idents,__type_declr};
+    pub use objr_procmacro::{__objc_implement_class,ObjcInstance,__static_expr,__static_extern,__static_asciiz_ident_as_selector,__static_asciiz_ident_as_type_encoding,__count,__concat_idents,__static_asciiz,__static_expr3,__concat_3_idents,__type_declr,__objc_subclass_ivars,__objc_subclass_methods,__objc_subclass_protocols};
     pub use super::class::AnyClass;
-    pub use super::arguments::{Primitive,Arguable,ArguableBehavior};
+    pub use super::protocol::Protocol;
+    pub use super::arguments::{Primitive,Arguable,ArguableBehavior,IntoArguable,VariadicArgument,LongArguments};
+    pub use super::encode::{Encode,EncodeRef,Encoding};
+    pub use objr_procmacro::{ObjcEncode,ObjcPrimitive};
     pub use super::exception::{try_unwrap_void};
     pub use super::objcinstance::ObjcInstanceBehavior;
     pub use super::threadsafety::ImpliedSyncUse;
@@ -217,6 +249,21 @@ pub mod bindings {
     ///Used by macros, not public API
     #[doc(hidden)]
     pub use super::sel::_SyncWrapper;
+    #[doc(hidden)]
+    pub use super::subclass::_IvarOffset;
+    #[doc(hidden)]
+    pub use super::subclass::_PayloadCell;
+    #[doc(hidden)]
+    pub use super::subclass::{_MethodSignature,_method_signature_sizes};
+    #[doc(hidden)]
+    pub use super::encode::{_method_type_encoding,_method_type_encoding_len,_VerifyEncode,_IvarEncoding,_IvarEncodingFallback,_type_encoding};
+    #[doc(hidden)]
+    pub use super::arguments::{_PrimitiveField,_packed_misaligned};
+    #[doc(hidden)]
+    pub use super::subclass::{_objc_getProperty,_objc_setProperty};
+    #[cfg(any(feature="gnustep", feature="mock-runtime"))]
+    #[doc(hidden)]
+    pub use super::subclass::_register_subclass;
 
     //used by macros
     #[doc(hidden)]
@@ -231,6 +278,8 @@ mod private {
     ///
     /// We are free to implement `Sealed` on any type from inside the crate; this is often necessary to implement some other `Trait: Sealed` on the type.
     pub trait Sealed {}
+    ///Like [Sealed], for traits with a type parameter.
+    pub trait SealedWith<A> {}
 }
 
 
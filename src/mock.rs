@@ -0,0 +1,1120 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+/*! An in-process fake of the ObjC runtime, for unit tests.
+
+With the `mock-runtime` feature, objr sends messages, retains, releases and drains autorelease pools
+against a runtime written in Rust instead of libobjc.  This lets binding crates test their wrappers on
+any machine, and observe exactly what those wrappers do to reference counts.
+
+The mock runtime knows about:
+* classes, declared with [MockClass].  Methods are Rust closures.
+* an `NSObject` root class, which implements `+alloc`, `+new`, `-init` and `-dealloc`.  Like the real runtime, allocating and
+  deallocating run the `.cxx_construct` and `.cxx_destruct` methods of each class
+* objects, with reference counts you can inspect with [retain_count()] and [is_deallocated()], and weak references to them
+* shared objects from [new_shared_object()], which any thread can retain and release, for testing code that does that
+* autorelease pools, including the `objc_autoreleaseReturnValue` / `objc_retainAutoreleasedReturnValue` handoff
+* [objc_subclass!] classes, which are registered on first use as on GNUstep
+* protocols, which classes can declare conformance to with `@protocols` in [objc_subclass!]
+* [objc_nsstring!](crate::foundation::objc_nsstring!) literals, which are created once per thread (given a mock `NSString` class) and released by [reset()]
+
+Each thread has its own runtime, so tests running in parallel don't see each other's classes or objects.
+Selectors are shared by all threads.
+
+```
+use objr::bindings::*;
+use objr::mock::{MockClass, retain_count};
+use std::ffi::c_void;
+objc_class! {
+    pub struct Counter {
+        @class(Counter)
+    }
+}
+objc_selector_group! {
+    trait CounterSelectors {
+        @selector("add:")
+    }
+    impl CounterSelectors for Sel {}
+}
+MockClass::new("Counter")
+    .method("add:", |_receiver: *mut c_void, amount: u64| -> u64 { amount + 1 })
+    .register();
+autoreleasepool(|pool| {
+    let counter = Counter::class().alloc_init(pool);
+    let result: u64 = unsafe{ Counter::perform_primitive(counter.assume_nonmut_perform(), Sel::add_(), pool, (2u64,)) };
+    assert_eq!(result, 3);
+    assert_eq!(retain_count(&*counter), 1);
+});
+```
+
+# Differences from the real runtime
+* Messaging nil panics, rather than returning zero.
+* Messaging a deallocated object, or autoreleasing without a pool, panics.
+* Objects are never freed, so their addresses are not reused.
+* Objects belong to the thread that created them, except for shared objects, which can't be sent messages.
+* Panicking inside a method aborts, since methods are called through `extern "C"`.
+*/
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::rc::Rc;
+use std::sync::Mutex;
+use crate::bindings::{AnyClass, Sel};
+
+struct Method {
+    imp: unsafe extern "C" fn(),
+    ///For methods implemented by closures, handed to the trampoline by [Runtime::pending_closure]
+    closure: Option<Rc<dyn Any>>,
+    ///Type encoding, for methods added by [runtime::class_addMethod]
+    #[cfg_attr(not(any(test, feature="verify-sends")), allow(dead_code))]
+    types: Option<CString>,
+}
+
+struct ClassEntry {
+    name: CString,
+    ///Null for the root class (and the root metaclass's superclass is the root class)
+    superclass: *mut c_void,
+    ///Null for metaclasses
+    metaclass: *mut c_void,
+    ///Keyed by `Sel::ptr()`.  Boxed, so the `Method` handles we give out stay put.
+    methods: HashMap<*const c_void, Box<Method>>,
+    ///Name and `Ivar` handle, which points to the offset
+    ivars: Vec<(CString, *mut c_void)>,
+    protocols: Vec<*mut c_void>,
+    ///Leaked, so they can be handed out as `objc_property_t`
+    properties: Vec<*const MockProperty>,
+    instance_size: usize,
+}
+
+struct MockProperty {
+    name: CString,
+    #[cfg_attr(not(test), allow(dead_code))]
+    attributes: CString,
+}
+
+struct ObjectEntry {
+    class: *mut c_void,
+    retain_count: usize,
+    deallocated: bool,
+}
+
+struct Runtime {
+    classes_by_name: HashMap<CString, *mut c_void>,
+    classes: HashMap<*mut c_void, ClassEntry>,
+    objects: HashMap<*mut c_void, ObjectEntry>,
+    ///Registered protocols
+    protocols_by_name: HashMap<CString, *mut c_void>,
+    ///Every protocol, including ones allocated but not yet registered
+    protocols: HashMap<*mut c_void, CString>,
+    pools: Vec<Vec<*mut c_void>>,
+    ///Returned by `objc_autoreleaseReturnValue` but not yet claimed by `objc_retainAutoreleasedReturnValue`
+    pending_return: Option<*mut c_void>,
+    ///Closure for the method most recently looked up
+    pending_closure: Option<Rc<dyn Any>>,
+    ///Weak references to each object, which are zeroed when it's deallocated
+    weak_locations: HashMap<*mut c_void, Vec<*mut *mut c_void>>,
+    ///Strings created by `objc_nsstring!`, by the address of their cache.  The runtime owns a reference to each.
+    strings: HashMap<usize, *mut c_void>,
+}
+
+thread_local! {
+    static RUNTIME: RefCell<Runtime> = RefCell::new(Runtime::new());
+}
+
+///Interned selector names.  These are process-wide, since `objc_selector_group!` caches selectors in statics.
+static SELECTORS: Mutex<Option<HashMap<CString, usize>>> = Mutex::new(None);
+
+///Retain counts of the objects from [new_shared_object()], by address.  0 means deallocated.
+static SHARED_OBJECTS: Mutex<Option<HashMap<usize, usize>>> = Mutex::new(None);
+
+fn with_shared<R, F: FnOnce(&mut HashMap<usize, usize>) -> R>(f: F) -> R {
+    //a test that panics while holding the lock leaves the counts consistent, so don't poison every later test
+    let mut lock = SHARED_OBJECTS.lock().unwrap_or_else(|e| e.into_inner());
+    f(lock.get_or_insert_with(HashMap::new))
+}
+
+///Retains or releases a shared object.  Returns `false` if `object` isn't one.
+fn shared_retain(object: *mut c_void, retain: bool) -> bool {
+    with_shared(|shared| {
+        match shared.get_mut(&(object as usize)) {
+            None => false,
+            Some(retain_count) => {
+                assert!(*retain_count > 0, "{:?} was used after it was deallocated", object);
+                if retain { *retain_count += 1 } else { *retain_count -= 1 }
+                true
+            }
+        }
+    })
+}
+
+fn with<R, F: FnOnce(&mut Runtime) -> R>(f: F) -> R {
+    RUNTIME.with(|runtime| f(&mut runtime.borrow_mut()))
+}
+
+fn sel(name: &str) -> Sel {
+    let name = CString::new(name).unwrap();
+    Sel::from_ptr(unsafe{ runtime::sel_registerName(name.as_ptr()) })
+}
+
+fn sel_name(sel: Sel) -> String {
+    unsafe{ CStr::from_ptr(sel.ptr() as *const c_char) }.to_string_lossy().into_owned()
+}
+
+///Key for method tables
+fn key(sel: Sel) -> *const c_void {
+    unsafe{ sel.ptr() }
+}
+
+///Some stable, unique address
+fn new_address() -> *mut c_void {
+    Box::into_raw(Box::new([0usize; 2])) as *mut c_void
+}
+
+impl Runtime {
+    fn new() -> Self {
+        let mut runtime = Runtime {
+            classes_by_name: HashMap::new(),
+            classes: HashMap::new(),
+            objects: HashMap::new(),
+            protocols_by_name: HashMap::new(),
+            protocols: HashMap::new(),
+            pools: Vec::new(),
+            pending_return: None,
+            pending_closure: None,
+            weak_locations: HashMap::new(),
+            strings: HashMap::new(),
+        };
+        let root = runtime.allocate_class(std::ptr::null_mut(), CString::new("NSObject").unwrap());
+        let root_meta = runtime.classes[&root].metaclass;
+        //room for isa
+        runtime.classes.get_mut(&root).unwrap().instance_size = std::mem::size_of::<usize>();
+        runtime.add_method(root_meta, sel("alloc"), |class: *mut c_void| -> *mut c_void { unsafe{ create_object(class) } });
+        runtime.add_method(root_meta, sel("new"), |class: *mut c_void| -> *mut c_void { unsafe{ create_object(class) } });
+        runtime.add_method(root, sel("init"), |receiver: *mut c_void| -> *mut c_void { receiver });
+        runtime.add_method(root, sel("dealloc"), |receiver: *mut c_void| { unsafe{ cxx_destruct(receiver) } });
+        runtime.add_method(root, sel("conformsToProtocol:"), |receiver: *mut c_void, protocol: *mut c_void| -> bool {
+            with(|r| { let class = r.live_object(receiver).class; r.conforms_to_protocol(class, protocol) })
+        });
+        runtime.add_method(root_meta, sel("conformsToProtocol:"), |class: *mut c_void, protocol: *mut c_void| -> bool {
+            with(|r| r.conforms_to_protocol(class, protocol))
+        });
+        runtime.register_class(root);
+        runtime
+    }
+
+    fn allocate_class(&mut self, superclass: *mut c_void, name: CString) -> *mut c_void {
+        let class = new_address();
+        let metaclass = new_address();
+        let (meta_superclass, instance_size) = if superclass.is_null() {
+            (class, 0)
+        } else {
+            let entry = &self.classes[&superclass];
+            (entry.metaclass, entry.instance_size)
+        };
+        self.classes.insert(metaclass, ClassEntry {
+            name: name.clone(),
+            superclass: meta_superclass,
+            metaclass: std::ptr::null_mut(),
+            methods: HashMap::new(),
+            ivars: Vec::new(),
+            protocols: Vec::new(),
+            properties: Vec::new(),
+            instance_size: 0,
+        });
+        self.classes.insert(class, ClassEntry {
+            name,
+            superclass,
+            metaclass,
+            methods: HashMap::new(),
+            ivars: Vec::new(),
+            protocols: Vec::new(),
+            properties: Vec::new(),
+            instance_size,
+        });
+        class
+    }
+
+    fn register_class(&mut self, class: *mut c_void) {
+        let name = self.classes[&class].name.clone();
+        self.classes_by_name.insert(name, class);
+    }
+
+    fn add_method<M, F: MockMethod<M>>(&mut self, class: *mut c_void, sel: Sel, f: F) {
+        let method = Method { imp: F::_imp(), closure: Some(Rc::new(f)), types: None };
+        self.class_mut(class).methods.insert(key(sel), Box::new(method));
+    }
+
+    fn class_mut(&mut self, class: *mut c_void) -> &mut ClassEntry {
+        self.classes.get_mut(&class).unwrap_or_else(|| panic!("{:?} is not a class in this thread's mock runtime", class))
+    }
+
+    fn create_object(&mut self, class: *mut c_void) -> *mut c_void {
+        let size = self.classes[&class].instance_size.max(16);
+        let object = unsafe{ std::alloc::alloc_zeroed(std::alloc::Layout::from_size_align(size, 16).unwrap()) } as *mut c_void;
+        self.objects.insert(object, ObjectEntry { class, retain_count: 1, deallocated: false });
+        object
+    }
+
+    ///Whether `class` or one of its superclasses conforms to `protocol`
+    fn conforms_to_protocol(&self, mut class: *mut c_void, protocol: *mut c_void) -> bool {
+        while !class.is_null() {
+            let entry = &self.classes[&class];
+            if entry.protocols.contains(&protocol) {
+                return true;
+            }
+            class = entry.superclass;
+        }
+        false
+    }
+
+    ///Finds the object, panicking if it is unknown or deallocated
+    fn live_object(&mut self, object: *mut c_void) -> &mut ObjectEntry {
+        let entry = self.objects.get_mut(&object).unwrap_or_else(|| panic!("{:?} is not an object in this thread's mock runtime", object));
+        assert!(!entry.deallocated, "{:?} was used after it was deallocated", object);
+        entry
+    }
+
+    ///The class that methods of `receiver` are looked up in
+    fn isa(&mut self, receiver: *mut c_void, sel: Sel) -> *mut c_void {
+        assert!(!receiver.is_null(), "-[nil {}]: the mock runtime does not support messaging nil", sel_name(sel));
+        if let Some(class) = self.classes.get(&receiver) {
+            assert!(!class.metaclass.is_null(), "the mock runtime does not support messaging metaclasses");
+            class.metaclass
+        }
+        else {
+            self.live_object(receiver).class
+        }
+    }
+
+    ///Finds a method declared by `class` itself (not a superclass), and stashes its closure for the trampoline
+    fn own_method(&mut self, class: *mut c_void, sel: Sel) -> Option<unsafe extern "C" fn()> {
+        let method = self.classes[&class].methods.get(&key(sel))?;
+        let imp = method.imp;
+        self.pending_closure = method.closure.clone();
+        Some(imp)
+    }
+
+    ///`class` and its superclasses, most-derived first
+    fn class_chain(&self, mut class: *mut c_void) -> Vec<*mut c_void> {
+        let mut chain = Vec::new();
+        while !class.is_null() {
+            chain.push(class);
+            class = self.classes[&class].superclass;
+        }
+        chain
+    }
+
+    ///Finds the method, starting at `class`, and stashes its closure for the trampoline
+    fn lookup(&mut self, mut class: *mut c_void, sel: Sel) -> unsafe extern "C" fn() {
+        let start = class;
+        while !class.is_null() {
+            let entry = &self.classes[&class];
+            if let Some(method) = entry.methods.get(&key(sel)) {
+                let imp = method.imp;
+                self.pending_closure = method.closure.clone();
+                return imp;
+            }
+            class = entry.superclass;
+        }
+        panic!("unrecognized selector {} sent to an instance of {:?}", sel_name(sel), self.classes[&start].name);
+    }
+
+    ///Completes an unclaimed `objc_autoreleaseReturnValue` by autoreleasing for real
+    fn flush_pending_return(&mut self) {
+        if let Some(object) = self.pending_return.take() {
+            self.autorelease(object);
+        }
+    }
+
+    fn autorelease(&mut self, object: *mut c_void) {
+        self.live_object(object);
+        match self.pools.last_mut() {
+            Some(pool) => pool.push(object),
+            None => panic!("{:?} autoreleased with no autorelease pool in place", object)
+        }
+    }
+}
+
+///Creates an object and runs its `.cxx_construct` methods, superclasses first, as `class_createInstance` does
+unsafe fn create_object(class: *mut c_void) -> *mut c_void {
+    let object = with(|r| r.create_object(class));
+    let sel = sel(".cxx_construct");
+    let chain = with(|r| r.class_chain(class));
+    for class in chain.into_iter().rev() {
+        if let Some(imp) = with(|r| r.own_method(class, sel)) {
+            let imp: unsafe extern "C" fn(*mut c_void, Sel) -> *mut c_void = std::mem::transmute(imp);
+            assert!(!imp(object, sel).is_null(), "the mock runtime does not support a failing .cxx_construct");
+        }
+    }
+    object
+}
+
+///Runs an object's `.cxx_destruct` methods, most-derived first, as `objc_destructInstance` does
+unsafe fn cxx_destruct(object: *mut c_void) {
+    let sel = sel(".cxx_destruct");
+    let chain = with(|r| { let class = r.live_object(object).class; r.class_chain(class) });
+    for class in chain {
+        if let Some(imp) = with(|r| r.own_method(class, sel)) {
+            let imp: unsafe extern "C" fn(*mut c_void, Sel) = std::mem::transmute(imp);
+            imp(object, sel);
+        }
+    }
+}
+
+///Calls `dealloc` on an object whose retain count just reached 0
+unsafe fn dealloc(object: *mut c_void) {
+    let sel = sel("dealloc");
+    let imp = with(|r| { let class = r.isa(object, sel); r.lookup(class, sel) });
+    let imp: unsafe extern "C" fn(*mut c_void, Sel) = std::mem::transmute(imp);
+    imp(object, sel);
+    with(|r| {
+        r.live_object(object).deallocated = true;
+        for location in r.weak_locations.remove(&object).unwrap_or_default() {
+            *location = std::ptr::null_mut();
+        }
+    });
+}
+
+///A Rust closure that can implement a method.
+///
+/// This is implemented for closures like `Fn(*mut c_void, A, B, ...) -> R`, where the first argument is the receiver
+/// and the remaining arguments and return type are the ones the method is called with.
+/// Argument types must be spelled out in the closure.  For example, a method called with [PerformsSelector::perform_result()]
+/// takes a trailing `*mut *const NSError`.
+///
+/// `Signature` is a marker for the closure's signature; you don't need to name it.
+pub trait MockMethod<Signature>: 'static {
+    #[doc(hidden)]
+    fn _imp() -> unsafe extern "C" fn();
+}
+
+macro_rules! mock_method_impl {
+    (
+        $($identifier:ident : $type:ident),*
+    ) => (
+        impl<Func, Ret $(, $type)*> MockMethod<(Ret, $($type,)*)> for Func where Func: Fn(*mut c_void $(, $type)*) -> Ret + 'static {
+            fn _imp() -> unsafe extern "C" fn() {
+                unsafe extern "C" fn trampoline<Func: Fn(*mut c_void $(, $type)*) -> Ret + 'static, Ret $(, $type)*>(receiver: *mut c_void, _sel: Sel $(, $identifier: $type)*) -> Ret {
+                    let closure = with(|r| r.pending_closure.take()).expect("mock method called without being looked up");
+                    let f = closure.downcast_ref::<Func>().unwrap();
+                    f(receiver $(, $identifier)*)
+                }
+                let imp: unsafe extern "C" fn(*mut c_void, Sel $(, $type)*) -> Ret = trampoline::<Func, Ret $(, $type)*>;
+                unsafe{ std::mem::transmute(imp) }
+            }
+        }
+    );
+}
+
+mock_method_impl!();
+mock_method_impl!(a: A);
+mock_method_impl!(a: A, b: B);
+mock_method_impl!(a: A, b: B, c: C);
+mock_method_impl!(a: A, b: B, c: C, d: D);
+mock_method_impl!(a: A, b: B, c: C, d: D, e: E);
+mock_method_impl!(a: A, b: B, c: C, d: D, e: E, f: F);
+mock_method_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G);
+mock_method_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H);
+mock_method_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I);
+mock_method_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J);
+mock_method_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K);
+mock_method_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L);
+mock_method_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L, m: M);
+mock_method_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L, m: M, n: N);
+mock_method_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L, m: M, n: N, o: O);
+mock_method_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L, m: M, n: N, o: O, p: P);
+//16 arguments, plus the error pointer
+mock_method_impl!(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H, i: I, j: J, k: K, l: L, m: M, n: N, o: O, p: P, q: Q);
+
+///Declares a class in this thread's mock runtime.
+///
+/// Declaring a class that already exists adds (or replaces) its methods.
+pub struct MockClass {
+    name: CString,
+    superclass: String,
+    methods: Vec<(Sel, Method)>,
+    class_methods: Vec<(Sel, Method)>,
+}
+
+impl MockClass {
+    ///A new class, inheriting from `NSObject`.
+    pub fn new(name: &str) -> Self {
+        MockClass {
+            name: CString::new(name).unwrap(),
+            superclass: "NSObject".to_owned(),
+            methods: Vec::new(),
+            class_methods: Vec::new(),
+        }
+    }
+    ///Inherits from a class already declared on this thread, instead of `NSObject`.
+    pub fn superclass(mut self, name: &str) -> Self {
+        self.superclass = name.to_owned();
+        self
+    }
+    ///Adds an instance method.  `selector` is in ObjC syntax, such as `"initWithFoo:bar:"`.
+    pub fn method<M, F: MockMethod<M>>(mut self, selector: &str, f: F) -> Self {
+        self.methods.push((sel(selector), Method { imp: F::_imp(), closure: Some(Rc::new(f)), types: None }));
+        self
+    }
+    ///Adds a class method.  Inside `f`, the receiver is the class.
+    pub fn class_method<M, F: MockMethod<M>>(mut self, selector: &str, f: F) -> Self {
+        self.class_methods.push((sel(selector), Method { imp: F::_imp(), closure: Some(Rc::new(f)), types: None }));
+        self
+    }
+    ///Adds the class to this thread's runtime.
+    pub fn register(self) -> &'static AnyClass {
+        let class = with(|r| {
+            let class = match r.classes_by_name.get(&self.name) {
+                Some(class) => *class,
+                None => {
+                    let superclass_name = CString::new(self.superclass).unwrap();
+                    let superclass = *r.classes_by_name.get(&superclass_name).unwrap_or_else(|| panic!("No superclass named {:?}", superclass_name));
+                    let class = r.allocate_class(superclass, self.name);
+                    r.register_class(class);
+                    class
+                }
+            };
+            let metaclass = r.classes[&class].metaclass;
+            r.class_mut(class).methods.extend(self.methods.into_iter().map(|(sel, method)| (key(sel), Box::new(method))));
+            r.class_mut(metaclass).methods.extend(self.class_methods.into_iter().map(|(sel, method)| (key(sel), Box::new(method))));
+            class
+        });
+        unsafe{ &*(class as *const AnyClass) }
+    }
+}
+
+///Creates an instance of the named class, with a retain count of 1.
+///
+/// This does not send `alloc` or `init`.
+pub fn new_object(class: &str) -> *mut c_void {
+    let name = CString::new(class).unwrap();
+    with(|r| {
+        let class = *r.classes_by_name.get(&name).unwrap_or_else(|| panic!("No class named {}", class));
+        r.create_object(class)
+    })
+}
+
+/**
+Creates an object that any thread can retain and release, with a retain count of 1.
+
+It isn't an instance of any class, so it can't be sent messages or autoreleased, but you can wrap it in a cell for a type
+that is `Sync`, and check from any thread that it's retained and released correctly across threads.
+*/
+pub fn new_shared_object() -> *mut c_void {
+    let object = new_address();
+    with_shared(|shared| shared.insert(object as usize, 1));
+    object
+}
+
+///Autoreleases an object, like `objc_autorelease`.  Use this for objects passed out through pointer arguments, such as errors.
+pub fn autorelease(object: *mut c_void) -> *mut c_void {
+    if !object.is_null() {
+        with(|r| {
+            r.flush_pending_return();
+            if !r.classes.contains_key(&object) {
+                r.autorelease(object);
+            }
+        });
+    }
+    object
+}
+
+///Returns a +1 object from a method as +0, like `objc_autoreleaseReturnValue`.
+///
+/// If the caller claims it with `objc_retainAutoreleasedReturnValue` (for example, via [PerformsSelector::perform_autorelease_to_retain()]),
+/// the object skips the autorelease pool, as on the real runtime.
+pub fn autorelease_return(object: *mut c_void) -> *mut c_void {
+    if !object.is_null() {
+        with(|r| {
+            r.flush_pending_return();
+            if !r.classes.contains_key(&object) {
+                r.live_object(object);
+                r.pending_return = Some(object);
+            }
+        });
+    }
+    object
+}
+
+///The retain count of a mock object.
+///
+/// # Panics
+/// If `object` is not an object in this thread's runtime, or a shared object.
+pub fn retain_count<T>(object: *const T) -> usize {
+    if let Some(retain_count) = with_shared(|shared| shared.get(&(object as usize)).copied()) {
+        return retain_count;
+    }
+    with(|r| {
+        r.flush_pending_return();
+        r.objects.get(&(object as *mut c_void)).unwrap_or_else(|| panic!("{:?} is not an object in this thread's mock runtime", object)).retain_count
+    })
+}
+
+///Whether a mock object has been deallocated.
+///
+/// # Panics
+/// If `object` is not an object in this thread's runtime, or a shared object.
+pub fn is_deallocated<T>(object: *const T) -> bool {
+    if let Some(retain_count) = with_shared(|shared| shared.get(&(object as usize)).copied()) {
+        return retain_count == 0;
+    }
+    with(|r| {
+        r.flush_pending_return();
+        r.objects.get(&(object as *mut c_void)).unwrap_or_else(|| panic!("{:?} is not an object in this thread's mock runtime", object)).deallocated
+    })
+}
+
+///Discards all classes, objects and autorelease pools on this thread.
+///
+/// Outstanding pointers into the old runtime are invalid afterwards.
+pub fn reset() {
+    let strings = with(|r| std::mem::take(&mut r.strings));
+    for string in strings.into_values() {
+        unsafe{ runtime::objc_release(string) };
+    }
+    let old = with(|r| std::mem::replace(r, Runtime::new()));
+    drop(old);
+}
+
+///Looks up the string `objc_nsstring!` created on this thread for `cache`.
+pub(crate) fn cached_string(cache: *const c_void) -> Option<*mut c_void> {
+    with(|r| r.strings.get(&(cache as usize)).copied())
+}
+
+///Keeps `string` for [cached_string()], taking ownership of a +1 reference to it.
+pub(crate) fn cache_string(cache: *const c_void, string: *mut c_void) {
+    with(|r| {
+        let old = r.strings.insert(cache as usize, string);
+        debug_assert!(old.is_none(), "string for {:?} cached twice", cache);
+    })
+}
+
+///Entry points used in place of libobjc
+#[allow(non_snake_case)] //named after the functions they replace
+pub(crate) mod runtime {
+    use super::*;
+
+    pub(crate) unsafe fn sel_registerName(name: *const c_char) -> *const c_void {
+        let name = CStr::from_ptr(name);
+        let mut selectors = SELECTORS.lock().unwrap();
+        let selectors = selectors.get_or_insert_with(HashMap::new);
+        if let Some(sel) = selectors.get(name) {
+            return *sel as *const c_void;
+        }
+        //leak a copy, so the selector can point at its own name
+        let sel = CString::from(name).into_raw() as usize;
+        selectors.insert(name.to_owned(), sel);
+        sel as *const c_void
+    }
+
+    pub(crate) unsafe fn objc_getClass(name: *const c_char) -> *mut c_void {
+        let name = CStr::from_ptr(name);
+        with(|r| r.classes_by_name.get(name).copied().unwrap_or(std::ptr::null_mut()))
+    }
+
+    pub(crate) unsafe fn object_getClass(object: *mut c_void) -> *mut c_void {
+        with(|r| {
+            match r.classes.get(&object) {
+                Some(class) => class.metaclass,
+                None => r.live_object(object).class
+            }
+        })
+    }
+
+    pub(crate) unsafe fn objc_allocateClassPair(superclass: *mut c_void, name: *const c_char, _extra_bytes: usize) -> *mut c_void {
+        let name = CStr::from_ptr(name);
+        with(|r| {
+            if r.classes_by_name.contains_key(name) {
+                std::ptr::null_mut()
+            }
+            else {
+                r.allocate_class(superclass, name.to_owned())
+            }
+        })
+    }
+
+    pub(crate) unsafe fn objc_registerClassPair(class: *mut c_void) {
+        with(|r| r.register_class(class))
+    }
+
+    pub(crate) unsafe fn objc_disposeClassPair(class: *mut c_void) {
+        with(|r| {
+            let entry = r.classes.remove(&class).unwrap();
+            r.classes.remove(&entry.metaclass);
+            if r.classes_by_name.get(&entry.name) == Some(&class) {
+                r.classes_by_name.remove(&entry.name);
+            }
+        })
+    }
+
+    pub(crate) unsafe fn class_addMethod(class: *mut c_void, name: Sel, imp: *const c_void, types: *const c_char) -> bool {
+        let types = if types.is_null() { None } else { Some(CStr::from_ptr(types).to_owned()) };
+        with(|r| {
+            let methods = &mut r.class_mut(class).methods;
+            if methods.contains_key(&key(name)) {
+                return false;
+            }
+            methods.insert(key(name), Box::new(Method { imp: std::mem::transmute::<*const c_void, unsafe extern "C" fn()>(imp), closure: None, types }));
+            true
+        })
+    }
+
+    #[cfg(any(test, feature="verify-sends"))]
+    pub(crate) unsafe fn class_getInstanceMethod(mut class: *mut c_void, name: Sel) -> *const c_void {
+        with(|r| {
+            while !class.is_null() {
+                let entry = &r.classes[&class];
+                if let Some(method) = entry.methods.get(&key(name)) {
+                    return &**method as *const Method as *const c_void;
+                }
+                class = entry.superclass;
+            }
+            std::ptr::null()
+        })
+    }
+
+    ///Null for methods implemented by closures, since we don't know their types
+    #[cfg(any(test, feature="verify-sends"))]
+    pub(crate) unsafe fn method_getTypeEncoding(method: *const c_void) -> *const c_char {
+        match &(*(method as *const Method)).types {
+            Some(types) => types.as_ptr(),
+            None => std::ptr::null(),
+        }
+    }
+
+    #[cfg(feature="verify-sends")]
+    pub(crate) unsafe fn class_getSuperclass(class: *const AnyClass) -> *const AnyClass {
+        with(|r| r.classes[&(class as *mut c_void)].superclass as *const AnyClass)
+    }
+
+    #[cfg(feature="verify-sends")]
+    pub(crate) unsafe fn class_isMetaClass(class: *mut c_void) -> bool {
+        with(|r| r.classes[&class].metaclass.is_null())
+    }
+
+    #[cfg(feature="verify-sends")]
+    pub(crate) unsafe fn sel_getName(sel: Sel) -> *const c_char {
+        //our selectors are their names, as on Apple platforms
+        sel.ptr() as *const c_char
+    }
+
+    #[cfg(feature="verify-sends")]
+    pub(crate) unsafe fn class_getName(class: *mut c_void) -> *const c_char {
+        with(|r| r.classes[&class].name.as_ptr())
+    }
+
+    pub(crate) unsafe fn objc_getProtocol(name: *const c_char) -> *mut c_void {
+        let name = CStr::from_ptr(name);
+        with(|r| r.protocols_by_name.get(name).copied().unwrap_or(std::ptr::null_mut()))
+    }
+
+    pub(crate) unsafe fn objc_allocateProtocol(name: *const c_char) -> *mut c_void {
+        let name = CStr::from_ptr(name);
+        with(|r| {
+            if r.protocols.values().any(|existing| existing.as_c_str() == name) {
+                return std::ptr::null_mut();
+            }
+            let protocol = new_address();
+            r.protocols.insert(protocol, name.to_owned());
+            protocol
+        })
+    }
+
+    pub(crate) unsafe fn objc_registerProtocol(protocol: *mut c_void) {
+        with(|r| {
+            let name = r.protocols[&protocol].clone();
+            r.protocols_by_name.insert(name, protocol);
+        })
+    }
+
+    pub(crate) unsafe fn class_addProtocol(class: *mut c_void, protocol: *mut c_void) -> bool {
+        with(|r| {
+            if r.conforms_to_protocol(class, protocol) {
+                return false;
+            }
+            r.class_mut(class).protocols.push(protocol);
+            true
+        })
+    }
+
+    pub(crate) unsafe fn class_addProperty(class: *mut c_void, name: *const c_char, attributes: *const crate::subclass::PropertyAttribute, count: u32) -> bool {
+        let attributes: Vec<String> = (0..count as usize).map(|i| {
+            let attribute = &*attributes.add(i);
+            format!("{}{}", CStr::from_ptr(attribute.name).to_str().unwrap(), CStr::from_ptr(attribute.value).to_str().unwrap())
+        }).collect();
+        let property = MockProperty { name: CStr::from_ptr(name).to_owned(), attributes: CString::new(attributes.join(",")).unwrap() };
+        with(|r| {
+            let entry = r.class_mut(class);
+            if entry.properties.iter().any(|p| (**p).name == property.name) {
+                return false;
+            }
+            entry.properties.push(Box::into_raw(Box::new(property)));
+            true
+        })
+    }
+
+    //nothing in objr reads properties back, except the tests
+    #[cfg(test)]
+    pub(crate) unsafe fn class_getProperty(class: *mut c_void, name: *const c_char) -> *const c_void {
+        let name = CStr::from_ptr(name);
+        with(|r| {
+            let mut class = class;
+            while !class.is_null() {
+                let entry = &r.classes[&class];
+                if let Some(property) = entry.properties.iter().find(|p| (***p).name.as_c_str() == name) {
+                    return *property as *const c_void;
+                }
+                class = entry.superclass;
+            }
+            std::ptr::null()
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) unsafe fn property_getAttributes(property: *const c_void) -> *const c_char {
+        (*(property as *const MockProperty)).attributes.as_ptr()
+    }
+
+    ///Getter for synthesized object properties
+    ///
+    /// # Safety
+    /// `object` must be a live instance with an object at `offset`.
+    pub unsafe fn objc_getProperty(object: *mut c_void, _sel: Sel, offset: isize, atomic: bool) -> *const c_void {
+        let value = *((object as *mut u8).offset(offset) as *const *const c_void);
+        if atomic && !value.is_null() {
+            //so the value survives a concurrent set
+            objc_retain(value);
+            objc_autorelease(value);
+        }
+        value
+    }
+
+    ///Setter for synthesized object properties
+    ///
+    /// # Safety
+    /// `object` must be a live instance with an object (or nil) at `offset`, and `value` must be an object or nil.
+    pub unsafe fn objc_setProperty(object: *mut c_void, _sel: Sel, offset: isize, value: *const c_void, _atomic: bool, should_copy: bool) {
+        let value = if value.is_null() {
+            value
+        }
+        else if should_copy {
+            let sel = sel("copy");
+            let imp: unsafe extern "C" fn(*mut c_void, Sel) -> *const c_void = std::mem::transmute(msg_send_fn(value as *mut c_void, sel));
+            imp(value as *mut c_void, sel)
+        }
+        else {
+            objc_retain(value)
+        };
+        let slot = (object as *mut u8).offset(offset) as *mut *const c_void;
+        let old = std::mem::replace(&mut *slot, value);
+        objc_release(old);
+    }
+
+    pub(crate) unsafe fn class_addIvar(class: *mut c_void, name: *const c_char, size: usize, alignment: u8, _types: *const c_char) -> bool {
+        let name = CStr::from_ptr(name);
+        with(|r| {
+            //like the real runtime, ivars can't be added once the class is registered
+            let registered = r.classes_by_name.get(&r.classes[&class].name) == Some(&class);
+            let entry = r.class_mut(class);
+            if registered || entry.ivars.iter().any(|(ivar_name, _)| ivar_name.as_c_str() == name) {
+                return false;
+            }
+            let align = 1 << alignment;
+            let offset = (entry.instance_size + align - 1) / align * align;
+            entry.instance_size = offset + size;
+            let ivar = Box::into_raw(Box::new(offset as isize)) as *mut c_void;
+            entry.ivars.push((name.to_owned(), ivar));
+            true
+        })
+    }
+
+    pub(crate) unsafe fn class_getInstanceVariable(class: *mut c_void, name: *const c_char) -> *mut c_void {
+        let name = CStr::from_ptr(name);
+        with(|r| {
+            let mut class = class;
+            while !class.is_null() {
+                let entry = &r.classes[&class];
+                if let Some((_, ivar)) = entry.ivars.iter().find(|(ivar_name, _)| ivar_name.as_c_str() == name) {
+                    return *ivar;
+                }
+                class = entry.superclass;
+            }
+            std::ptr::null_mut()
+        })
+    }
+
+    pub(crate) unsafe fn ivar_getOffset(ivar: *mut c_void) -> isize {
+        *(ivar as *const isize)
+    }
+
+    ///Finds the function to call for sending `sel` to `receiver`.  See `msg_send_fn` in arguments.rs
+    pub(crate) unsafe fn msg_send_fn(receiver: *mut c_void, sel: Sel) -> unsafe extern "C" fn() {
+        with(|r| {
+            r.flush_pending_return();
+            let class = r.isa(receiver, sel);
+            r.lookup(class, sel)
+        })
+    }
+
+    ///Finds the function to call for sending `sel` to super, where `class` is the class of the calling implementation.
+    pub(crate) unsafe fn msg_send_super_fn(receiver: *mut c_void, class: *const AnyClass, sel: Sel) -> unsafe extern "C" fn() {
+        with(|r| {
+            r.flush_pending_return();
+            let class = class as *mut c_void;
+            //check the receiver is valid
+            r.isa(receiver, sel);
+            let search = if r.classes.contains_key(&receiver) { r.classes[&class].metaclass } else { class };
+            let superclass = r.classes[&search].superclass;
+            assert!(!superclass.is_null(), "[super {}] sent from a root class", sel_name(sel));
+            r.lookup(superclass, sel)
+        })
+    }
+
+    pub(crate) unsafe fn objc_retain(object: *const c_void) -> *const c_void {
+        let object = object as *mut c_void;
+        if object.is_null() || shared_retain(object, true) { return object; }
+        with(|r| {
+            r.flush_pending_return();
+            //classes are immortal
+            if !r.classes.contains_key(&object) {
+                r.live_object(object).retain_count += 1;
+            }
+        });
+        object
+    }
+
+    pub(crate) unsafe fn objc_release(object: *const c_void) {
+        let object = object as *mut c_void;
+        if object.is_null() || shared_retain(object, false) { return; }
+        let should_dealloc = with(|r| {
+            r.flush_pending_return();
+            if r.classes.contains_key(&object) {
+                return false;
+            }
+            let entry = r.live_object(object);
+            assert!(entry.retain_count > 0, "{:?} over-released", object);
+            entry.retain_count -= 1;
+            entry.retain_count == 0
+        });
+        if should_dealloc {
+            dealloc(object);
+        }
+    }
+
+    pub(crate) unsafe fn objc_autorelease(object: *const c_void) {
+        autorelease(object as *mut c_void);
+    }
+
+    pub(crate) unsafe fn objc_autoreleaseReturnValue(object: *const c_void) -> *const c_void {
+        autorelease_return(object as *mut c_void)
+    }
+
+    pub(crate) unsafe fn objc_retainAutoreleasedReturnValue(object: *const c_void) -> *mut c_void {
+        let object = object as *mut c_void;
+        if object.is_null() { return object; }
+        let claimed = with(|r| {
+            if r.pending_return == Some(object) {
+                //the +1 passes straight to the caller
+                r.pending_return = None;
+                true
+            }
+            else {
+                false
+            }
+        });
+        if !claimed {
+            objc_retain(object);
+        }
+        object
+    }
+
+    ///Stores `object` in the weak reference at `location`, which must not already hold one
+    unsafe fn register_weak(r: &mut Runtime, location: *mut *mut c_void, object: *mut c_void) -> *mut c_void {
+        //like the real runtime, a weak reference to an object that's being deallocated is nil
+        let object = if object.is_null() || r.classes.contains_key(&object) || r.live_object(object).retain_count > 0 { object } else { std::ptr::null_mut() };
+        *location = object;
+        if !object.is_null() && !r.classes.contains_key(&object) {
+            r.weak_locations.entry(object).or_default().push(location);
+        }
+        object
+    }
+    ///Removes the weak reference at `location`, leaving it nil
+    unsafe fn unregister_weak(r: &mut Runtime, location: *mut *mut c_void) {
+        if let Some(locations) = r.weak_locations.get_mut(&*location) {
+            locations.retain(|l| *l != location);
+        }
+        *location = std::ptr::null_mut();
+    }
+
+    pub(crate) unsafe fn objc_initWeak(location: *mut *mut c_void, object: *mut c_void) -> *mut c_void {
+        with(|r| register_weak(r, location, object))
+    }
+
+    pub(crate) unsafe fn objc_storeWeak(location: *mut *mut c_void, object: *mut c_void) -> *mut c_void {
+        with(|r| {
+            unregister_weak(r, location);
+            register_weak(r, location, object)
+        })
+    }
+
+    pub(crate) unsafe fn objc_loadWeakRetained(location: *mut *mut c_void) -> *mut c_void {
+        let object = *location;
+        let live = !object.is_null() && with(|r| r.classes.contains_key(&object) || r.live_object(object).retain_count > 0);
+        if live {
+            objc_retain(object) as *mut c_void
+        }
+        else {
+            std::ptr::null_mut()
+        }
+    }
+
+    pub(crate) unsafe fn objc_copyWeak(to: *mut *mut c_void, from: *mut *mut c_void) {
+        with(|r| register_weak(r, to, *from));
+    }
+
+    pub(crate) unsafe fn objc_destroyWeak(location: *mut *mut c_void) {
+        with(|r| unregister_weak(r, location));
+    }
+
+    pub(crate) unsafe fn objc_autoreleasePoolPush() -> *const c_void {
+        with(|r| {
+            r.flush_pending_return();
+            r.pools.push(Vec::new());
+            r.pools.len() as *const c_void
+        })
+    }
+
+    pub(crate) unsafe fn objc_autoreleasePoolPop(token: *const c_void) {
+        let depth = token as usize;
+        let drained: Vec<*mut c_void> = with(|r| {
+            r.flush_pending_return();
+            assert!(depth > 0 && depth <= r.pools.len(), "autorelease pool popped out of order");
+            r.pools.drain(depth - 1..).flatten().collect()
+        });
+        for object in drained.into_iter().rev() {
+            objc_release(object);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bindings::*;
+    use super::{MockClass, retain_count, is_deallocated, new_object, autorelease, autorelease_return};
+    use std::ffi::c_void;
+
+    objc_class! {
+        pub struct Loader {
+            @class(Loader)
+        }
+    }
+    objc_selector_group! {
+        trait LoaderSelectors {
+            @selector("loadSucceeding:error:")
+            @selector("saveSucceeding:error:")
+        }
+        impl LoaderSelectors for Sel {}
+    }
+
+    fn register_loader() {
+        MockClass::new("NSError").register();
+        MockClass::new("Loader")
+            .method("loadSucceeding:error:", |_receiver: *mut c_void, succeed: bool, error: *mut *const c_void| -> *mut c_void {
+                if succeed {
+                    autorelease_return(new_object("NSObject"))
+                }
+                else {
+                    unsafe{ *error = autorelease(new_object("NSError")) };
+                    std::ptr::null_mut()
+                }
+            })
+            .method("saveSucceeding:error:", |_receiver: *mut c_void, succeed: bool, error: *mut *const c_void| -> bool {
+                if !succeed {
+                    unsafe{ *error = autorelease(new_object("NSError")) };
+                }
+                succeed
+            })
+            .register();
+    }
+
+    #[test] fn strong_cell_clone_drop() {
+        autoreleasepool(|pool| {
+            let object = NSObject::class().alloc_init(pool);
+            let ptr = &*object as *const NSObject;
+            assert_eq!(retain_count(ptr), 1);
+            let clone = object.clone();
+            assert_eq!(retain_count(ptr), 2);
+            drop(clone);
+            assert_eq!(retain_count(ptr), 1);
+            drop(object);
+            assert!(is_deallocated(ptr));
+        })
+    }
+
+    #[test] fn perform_result() {
+        register_loader();
+        autoreleasepool(|pool| {
+            let loader = Loader::class().alloc_init(pool);
+            let inner = unsafe{ AutoreleasePool::new() };
+            let object: *const NSObject = unsafe{ Loader::perform_result(loader.assume_nonmut_perform(), Sel::loadSucceeding_error(), &inner, (true,)) }.unwrap();
+            //owned by the pool
+            assert_eq!(retain_count(object), 1);
+            let error = unsafe{ Loader::perform_result::<_, NSObject>(loader.assume_nonmut_perform(), Sel::loadSucceeding_error(), &inner, (false,)) }.unwrap_err();
+            let error = &*error as *const NSError;
+            assert_eq!(retain_count(error), 1);
+            drop(inner);
+            assert!(is_deallocated(object));
+            assert!(is_deallocated(error));
+        })
+    }
+
+    #[test] fn perform_result_autorelease_to_retain() {
+        register_loader();
+        autoreleasepool(|pool| {
+            let loader = Loader::class().alloc_init(pool);
+            let inner = unsafe{ AutoreleasePool::new() };
+            let object: *const NSObject = unsafe{ Loader::perform_result_autorelease_to_retain(loader.assume_nonmut_perform(), Sel::loadSucceeding_error(), &inner, (true,)) }.unwrap();
+            drop(inner);
+            //handed to us directly, skipping the pool
+            assert_eq!(retain_count(object), 1);
+            let object = unsafe{ NSObject::assume_nonnil(object).assume_retained() };
+            let ptr = &*object as *const NSObject;
+            drop(object);
+            assert!(is_deallocated(ptr));
+        })
+    }
+
+    #[test] fn perform_bool_result() {
+        register_loader();
+        autoreleasepool(|pool| {
+            let loader = Loader::class().alloc_init(pool);
+            let inner = unsafe{ AutoreleasePool::new() };
+            unsafe{ Loader::perform_bool_result(loader.assume_nonmut_perform(), Sel::saveSucceeding_error(), &inner, (true,)) }.unwrap();
+            let error = unsafe{ Loader::perform_bool_result(loader.assume_nonmut_perform(), Sel::saveSucceeding_error(), &inner, (false,)) }.unwrap_err();
+            let error = &*error as *const NSError;
+            assert_eq!(retain_count(error), 1);
+            drop(inner);
+            assert!(is_deallocated(error));
+        })
+    }
+
+    #[test] fn nsstring_cached_per_thread() {
+        use crate::foundation::objc_nsstring;
+        use crate::typealias::NSUInteger;
+        use std::cell::Cell;
+        thread_local! {
+            static DEALLOCATED: Cell<bool> = Cell::new(false);
+        }
+        MockClass::new("NSString")
+            .method("initWithBytes:length:encoding:", |receiver: *mut c_void, _bytes: *const c_void, _length: NSUInteger, _encoding: NSUInteger| -> *mut c_void { receiver })
+            .method("dealloc", |_receiver: *mut c_void| DEALLOCATED.with(|d| d.set(true)))
+            .register();
+        fn literal() -> &'static NSString { objc_nsstring!("example") }
+        let string = literal() as *const NSString;
+        assert_eq!(literal() as *const NSString, string);
+        assert_eq!(retain_count(string), 1);
+        super::reset();
+        assert!(DEALLOCATED.with(|d| d.get()));
+    }
+}
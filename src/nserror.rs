@@ -51,6 +51,7 @@ impl<T> ResultNSError<T> for Result<T,StrongCell<NSError>> {
     }
 }
 
+#[cfg_attr(feature="mock-runtime", ignore = "needs Foundation")]
 #[test] fn check_err() {
     //ensure cell types implement NSError
     fn assert_err<T: std::error::Error>(_t: &T) { }
@@ -12,9 +12,11 @@ use super::bindings::*;
 
 
 //If you fail to Link CoreFoundation, description cannot be found
-#[link(name="CoreFoundation",kind="framework")]
+#[cfg_attr(not(any(feature="gnustep", feature="mock-runtime")), link(name="CoreFoundation",kind="framework"))]
 //If you fail to link Foundation, linker will not understand where NSString symbols come from
-#[link(name="Foundation",kind="framework")]
+#[cfg_attr(not(any(feature="gnustep", feature="mock-runtime")), link(name="Foundation",kind="framework"))]
+//On GNUstep, all of the above lives in gnustep-base
+#[cfg_attr(all(feature="gnustep", not(feature="mock-runtime")), link(name="gnustep-base",kind="dylib"))]
 extern {}
 objc_selector_group!(
         pub trait NSObjectSelectors {
@@ -36,6 +38,8 @@ pub trait NSObjectTrait: Sized + ObjcInstance {
     fn description<'a>(&self, pool: &ActiveAutoreleasePool) -> StrongCell<NSString>;
     //objc_method_declaration!{autoreleased fn description() -> NSString; }
     fn responds_to_selector(&self, pool: &ActiveAutoreleasePool, sel: Sel) -> bool;
+    ///Calls `[instance conformsToProtocol:]`.
+    fn conforms_to_protocol(&self, pool: &ActiveAutoreleasePool, protocol: &Protocol) -> bool;
 
     fn copy(&self, pool: &ActiveAutoreleasePool) -> StrongCell<Self>;
 
@@ -57,6 +61,11 @@ impl<T: ObjcInstance> NSObjectTrait for T {
             Self::perform_primitive(self.assume_nonmut_perform(), Sel::respondsToSelector_(), pool, (sel,))
         }
     }
+    fn conforms_to_protocol(&self, pool: &ActiveAutoreleasePool, protocol: &Protocol) -> bool {
+        unsafe {
+            Self::perform_primitive(self.assume_nonmut_perform(), Sel::conformsToProtocol_(), pool, (protocol.assume_nonmut_perform(),))
+        }
+    }
     fn copy(&self, pool: &ActiveAutoreleasePool) -> StrongCell<Self> {
         unsafe {
             let r = Self::perform(self.assume_nonmut_perform(), Sel::copy(), pool, ());
@@ -2,9 +2,12 @@
 //! Provides NSString
 //!
 use super::bindings::*;
-use std::ffi::{CStr};
+use std::ffi::{c_void, CStr};
 use std::hash::{Hash, Hasher};
 use std::os::raw::{c_char};
+use std::sync::atomic::AtomicPtr;
+#[cfg(not(feature="mock-runtime"))]
+use std::sync::atomic::Ordering;
 use crate::objcinstance::NonNullImmutable;
 use objr::typealias::NSUInteger;
 
@@ -72,10 +75,58 @@ impl NSString {
 			NonNullImmutable::assume_nonnil(instance).assume_retained().assume_mut()
 		}
 	}
+
+	///Creates a string for `literal` once, caching (and leaking) it in `cache`.
+	///
+	/// Used by [objc_nsstring!] on runtimes where we can't emit constant strings.
+	#[doc(hidden)]
+	#[cfg(not(feature="mock-runtime"))]
+	pub fn _cached(cache: &AtomicPtr<c_void>, literal: &'static str) -> &'static NSString {
+		let cached = cache.load(Ordering::Acquire);
+		if !cached.is_null() {
+			return unsafe{ &*(cached as *const NSString) };
+		}
+		let string = Self::_cached_create(literal);
+		let ptr = &*string as *const NSString as *mut c_void;
+		match cache.compare_exchange(std::ptr::null_mut(), ptr, Ordering::AcqRel, Ordering::Acquire) {
+			Ok(_) => {
+				//the cache owns this reference from now on
+				std::mem::forget(string);
+				unsafe{ &*(ptr as *const NSString) }
+			}
+			Err(winner) => {
+				//another thread got there first; ours is released on drop
+				unsafe{ &*(winner as *const NSString) }
+			}
+		}
+	}
+	///Creates a string for `literal` once per thread, keeping it in the mock runtime until [crate::mock::reset()].
+	///
+	/// Each thread has its own mock runtime, so the string can't be shared in `cache`; the runtime
+	/// keys it by `cache`'s address instead.
+	#[doc(hidden)]
+	#[cfg(feature="mock-runtime")]
+	pub fn _cached(cache: &AtomicPtr<c_void>, literal: &'static str) -> &'static NSString {
+		let key = cache as *const AtomicPtr<c_void> as *const c_void;
+		if let Some(cached) = crate::mock::cached_string(key) {
+			return unsafe{ &*(cached as *const NSString) };
+		}
+		let string = Self::_cached_create(literal);
+		let ptr = &*string as *const NSString as *mut c_void;
+		//the mock runtime owns this reference from now on
+		std::mem::forget(string);
+		crate::mock::cache_string(key, ptr);
+		unsafe{ &*(ptr as *const NSString) }
+	}
+	fn _cached_create(literal: &'static str) -> StrongCell<NSString> {
+		let pool = unsafe{ ActiveAutoreleasePool::assume_autoreleasepool() };
+		NSString::with_str_copy(literal, &pool).as_const()
+	}
 }
 
 
 
+#[cfg_attr(feature="mock-runtime", ignore = "needs Foundation")]
 #[test] fn from_str() {
 	use crate::autorelease::AutoreleasePool;
 	let example = "example string here";
@@ -84,6 +135,7 @@ impl NSString {
 	assert_eq!(nsstring.to_str(&pool), example);
 }
 
+#[cfg_attr(feature="mock-runtime", ignore = "needs Foundation")]
 #[test] fn static_str() {
 	use crate::autorelease::AutoreleasePool;
 	let pool = unsafe{ AutoreleasePool::new() };
@@ -93,6 +145,7 @@ impl NSString {
 	assert_eq!(description.to_str(&pool), "My example literal");
 }
 
+#[cfg_attr(feature="mock-runtime", ignore = "needs Foundation")]
 #[test] fn hash_str() {
 	use std::collections::hash_map::DefaultHasher;
 
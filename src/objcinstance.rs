@@ -33,7 +33,10 @@ impl<T: ObjcInstance> NonNullImmutable<T> {
     /// * Object was initialized
     /// * Object is 'static, that is, it has no references to external (Rust) memory.
     /// If this is not the case, see [NonNullImmutable::assume_retained_limited].
+    #[cfg_attr(feature="memory-tracing", track_caller)]
     pub unsafe fn assume_retained(self) -> StrongCell<T> {
+        #[cfg(feature="memory-tracing")]
+        crate::debug::record(crate::debug::Event::Adopt, std::any::type_name::<T>(), self.0.as_ptr() as *const std::ffi::c_void);
         StrongCell::assume_retained(self.0.as_ref())
     }
 
@@ -48,7 +51,10 @@ impl<T: ObjcInstance> NonNullImmutable<T> {
     /// in this object will remain valid for the lifetime specified, which is unbounded.
     /// * That all objc APIs which end up seeing this instance will either only access it for the lifetime specified,
     ///   or will take some other step (usually, copying) the object into a longer lifetime.
+    #[cfg_attr(feature="memory-tracing", track_caller)]
     pub unsafe fn assume_retained_limited<'a>(self) -> StrongLifetimeCell<'a, T> where T: 'a {
+        #[cfg(feature="memory-tracing")]
+        crate::debug::record(crate::debug::Event::Adopt, std::any::type_name::<T>(), self.0.as_ptr() as *const std::ffi::c_void);
         StrongLifetimeCell::assume_retained_limited(self.0.as_ref())
     }
     ///Assumes the object has been autoreleased and converts to an AutoreleasedCell.
@@ -255,7 +261,7 @@ The type will automagically conform to [objr::bindings::ObjcInstance], but will
 # Example
 
 ```
-#![link(name="Foundation",kind="framework")]
+#![cfg_attr(not(any(feature="gnustep", feature="mock-runtime")), link(name="Foundation",kind="framework"))]
 use objr::bindings::*;
 objc_instance! {
     pub struct NSExample;
@@ -551,6 +557,9 @@ macro_rules! objc_instance_newtype {
         ::objr::bindings::__use!($pub no_construct,$newtype,$newtype);
         unsafe impl $(<$($T),+>)? Arguable for $newtype $(<$($T),+>)? {}
         impl $(<$($T),+>)? ObjcInstance for $newtype $(<$($T),+>)? {}
+        impl $(<$($T),+>)? ::objr::bindings::EncodeRef for $newtype $(<$($T),+>)? {
+            const ENCODING_REF: ::objr::bindings::Encoding = ::objr::bindings::Encoding::Primitive("@");
+        }
         impl<'a,$($($T),*)?> From<&'a $newtype $(<$($T),+>)? > for &'a $oldtype {
             fn from(f: &'a $newtype $(<$($T),+>)?) -> &'a $oldtype {
                 unsafe{ f.cast() }
@@ -583,6 +592,7 @@ impl<T: ObjcInstance> OptionalInstanceBehavior<T> for Option<&T> {
     }
 }
 
+#[cfg_attr(feature="mock-runtime", ignore = "needs Foundation")]
 #[test] fn is_kind_of_class() {
     use objr::bindings::autoreleasepool;
     use objr::nsstring::NSString;
@@ -14,6 +14,13 @@ Mutable variants:
 Lifetime variants:
 1.  StrongLifetimeCell - like [StrongCell] but tracks some explicit lifetime.  Often used for objects that borrow Rust storage.
 
+Weak variants:
+1.  WeakCell - a zeroing weak reference, which doesn't keep the object alive.
+
+Atomic variants:
+1.  AtomicStrongCell - a [StrongCell] that can be loaded and replaced from several threads.
+2.  AtomicOptionalStrongCell - like [AtomicStrongCell] but may be nil.
+
 
 See documentation for particular cells.
  */
@@ -27,24 +34,48 @@ use std::fmt::{Formatter};
 use std::hash::{Hash, Hasher};
 use std::mem::forget;
 use std::ops::{DerefMut};
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Mutex;
 use crate::cast::ReinterpretCast;
 use crate::objcinstance::ObjcInstanceBehavior;
 
 
+#[cfg(not(feature="mock-runtime"))]
 extern "C" {
     fn objc_autoreleaseReturnValue(object: *const c_void) -> *const c_void;
 }
 
-///Turning this on may help debug retain/release
-const DEBUG_MEMORY: bool = false;
+///Records a retain, release etc. for the `debug` module, with the `memory-tracing` feature.
+macro_rules! trace_memory {
+    ($event:ident, $type:ty, $ptr:expr) => {
+        #[cfg(feature="memory-tracing")]
+        crate::debug::record(crate::debug::Event::$event, std::any::type_name::<$type>(), $ptr as *const _ as *const c_void);
+    }
+}
 
 
+#[cfg(not(feature="mock-runtime"))]
 #[link(name="objc", kind="dylib")]
 extern "C" {
     fn objc_retain(ptr: *const  c_void) -> *const c_void;
     fn objc_release(ptr: *const c_void);
     fn objc_autorelease(ptr: *const c_void);
 }
+#[cfg(feature="mock-runtime")]
+use crate::mock::runtime::{objc_retain, objc_release, objc_autorelease, objc_autoreleaseReturnValue};
+
+#[cfg(not(feature="mock-runtime"))]
+#[link(name="objc", kind="dylib")]
+extern "C" {
+    fn objc_initWeak(location: *mut *mut c_void, object: *mut c_void) -> *mut c_void;
+    fn objc_storeWeak(location: *mut *mut c_void, object: *mut c_void) -> *mut c_void;
+    fn objc_loadWeakRetained(location: *mut *mut c_void) -> *mut c_void;
+    fn objc_copyWeak(to: *mut *mut c_void, from: *mut *mut c_void);
+    fn objc_destroyWeak(location: *mut *mut c_void);
+}
+#[cfg(feature="mock-runtime")]
+use crate::mock::runtime::{objc_initWeak, objc_storeWeak, objc_loadWeakRetained, objc_copyWeak, objc_destroyWeak};
 
 
 /**
@@ -55,31 +86,37 @@ The pool is used to lexically scope the lifetime of the pointer.
 pub struct AutoreleasedCell<'a, T> {
     ptr: NonNullImmutable<T>,
     ///for lifetime
-    marker: PhantomData<&'a T>
+    marker: PhantomData<&'a T>,
+    ///the pool the object went into, with `verify-pools`
+    #[cfg(feature="verify-pools")]
+    pool: u64,
 }
 
 impl<'a, T: ObjcInstance> AutoreleasedCell<'a, T> {
 
     ///Converts to [Self] by autoreleasing the reference.
+    #[cfg_attr(feature="memory-tracing", track_caller)]
     pub fn autoreleasing(cell: &T, _pool: &'a ActiveAutoreleasePool) -> Self {
+        trace_memory!(Autorelease, T, cell);
         unsafe {
             objc_autorelease(cell as *const _ as *const c_void)
         }
         Self{
             ptr: NonNullImmutable::from_reference(cell),
-            marker: Default::default()
+            marker: Default::default(),
+            #[cfg(feature="verify-pools")]
+            pool: crate::autorelease::current_pool(),
         }
     }
     ///Converts to [Self] by assuming the pointer is already autoreleased.
     ///
     /// This is the case for many objc methods, depending on convention.
     pub unsafe fn assume_autoreleased(ptr: &T, _pool: &'a ActiveAutoreleasePool) -> Self {
-        if DEBUG_MEMORY {
-            println!("assume_autoreleased {} {:p}",std::any::type_name::<T>(), ptr);
-        }
         AutoreleasedCell {
             ptr: NonNullImmutable::from_reference(ptr),
-            marker: PhantomData::default()
+            marker: PhantomData::default(),
+            #[cfg(feature="verify-pools")]
+            pool: crate::autorelease::current_pool(),
         }
     }
 
@@ -94,7 +131,9 @@ impl<'a, T: ObjcInstance> AutoreleasedCell<'a, T> {
         let r =
             AutoreleasedMutCell {
                 ptr: NonNull::new_unchecked(self.ptr.as_ptr() as *mut T),
-                marker: Default::default()
+                marker: Default::default(),
+                #[cfg(feature="verify-pools")]
+                pool: self.pool,
             };
         std::mem::forget(self);
         r
@@ -105,7 +144,9 @@ impl<'a, T: ObjcInstance> AutoreleasedCell<'a, T> {
         unsafe {
             AutoreleasedCell{
                 marker: PhantomData,
-                ptr: NonNullImmutable::assume_nonnil(self.ptr.as_ptr() as *const _ as *const NSObject )
+                ptr: NonNullImmutable::assume_nonnil(self.ptr.as_ptr() as *const _ as *const NSObject ),
+                #[cfg(feature="verify-pools")]
+                pool: self.pool,
             }
         }
     }
@@ -113,6 +154,8 @@ impl<'a, T: ObjcInstance> AutoreleasedCell<'a, T> {
 impl<'a, T: ObjcInstance> std::ops::Deref for AutoreleasedCell<'a, T> {
     type Target = T;
     #[inline] fn deref(&self) -> &T {
+        #[cfg(feature="verify-pools")]
+        crate::autorelease::check_pool(self.pool, std::any::type_name::<T>());
         unsafe{ &*self.ptr.as_ptr() }
     }
 }
@@ -120,13 +163,13 @@ impl<'a, T: ObjcInstance> std::ops::Deref for AutoreleasedCell<'a, T> {
 
 impl<'a, T: ObjcInstance> std::fmt::Display for AutoreleasedCell<'a, T> where T: std::fmt::Display {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let ptr = unsafe{ &*self.ptr.as_ptr() };
+        let ptr: &T = self;
         std::fmt::Display::fmt(ptr, f)
     }
 }
 impl<'a, T: ObjcInstance> std::fmt::Debug for AutoreleasedCell<'a, T> where T: std::fmt::Debug {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let ptr = unsafe {&*(self.ptr.as_ptr())};
+        let ptr: &T = self;
         ptr.fmt(f)
     }
 }
@@ -152,7 +195,9 @@ impl<'a, T: ObjcInstance + ReinterpretCast<Target=U>, U: ObjcInstance> Autorelea
     pub fn safe_cast_into(self) -> AutoreleasedCell<'a, U> {
         AutoreleasedCell{
             ptr: unsafe{NonNullImmutable::assume_nonnil(self.ptr.as_ptr() as *const U)},
-            marker: PhantomData
+            marker: PhantomData,
+            #[cfg(feature="verify-pools")]
+            pool: self.pool,
         }
     }
 }
@@ -165,31 +210,37 @@ The pool is used to lexically scope the lifetime of the pointer.
 pub struct AutoreleasedMutCell<'a, T> {
     ptr: NonNull<T>,
     ///for lifetime
-    marker: PhantomData<&'a T>
+    marker: PhantomData<&'a T>,
+    ///the pool the object went into, with `verify-pools`
+    #[cfg(feature="verify-pools")]
+    pool: u64,
 }
 
 impl<'a, T: ObjcInstance> AutoreleasedMutCell<'a, T> {
 
     ///Converts to [Self] by autoreleasing the reference.
+    #[cfg_attr(feature="memory-tracing", track_caller)]
     pub fn autoreleasing(cell: &mut T, _pool: &'a ActiveAutoreleasePool) -> Self {
+        trace_memory!(Autorelease, T, cell);
         unsafe {
             objc_autorelease(cell as *const _ as *const c_void)
         }
         Self{
             ptr: unsafe{ NonNull::new_unchecked(cell) },
-            marker: Default::default()
+            marker: Default::default(),
+            #[cfg(feature="verify-pools")]
+            pool: crate::autorelease::current_pool(),
         }
     }
     ///Converts to [Self] by assuming the pointer is already autoreleased.
     ///
     /// This is the case for many objc methods, depending on convention.
     pub unsafe fn assume_autoreleased(ptr: &mut T, _pool: &'a ActiveAutoreleasePool) -> Self {
-        if DEBUG_MEMORY {
-            println!("assume_autoreleased {} {:p}",std::any::type_name::<T>(), ptr);
-        }
         Self {
             ptr: NonNull::new_unchecked(ptr),
-            marker: PhantomData::default()
+            marker: PhantomData::default(),
+            #[cfg(feature="verify-pools")]
+            pool: crate::autorelease::current_pool(),
         }
     }
 
@@ -200,6 +251,8 @@ impl<'a, T: ObjcInstance> AutoreleasedMutCell<'a, T> {
             AutoreleasedMutCell {
                 ptr: NonNull::new_unchecked(self.ptr.as_ptr() as *mut _ as *mut NSObject ),
                 marker: PhantomData,
+                #[cfg(feature="verify-pools")]
+                pool: self.pool,
             };
             //there is no drop behavior to speak of, but just in case we add some in the future
             forget(self);
@@ -212,11 +265,15 @@ impl<'a, T: ObjcInstance> AutoreleasedMutCell<'a, T> {
 impl<'a, T: ObjcInstance> std::ops::Deref for AutoreleasedMutCell<'a, T> {
     type Target = T;
     #[inline] fn deref(&self) -> &T {
+        #[cfg(feature="verify-pools")]
+        crate::autorelease::check_pool(self.pool, std::any::type_name::<T>());
         unsafe{ &*self.ptr.as_ptr() }
     }
 }
 impl<'a, T: ObjcInstance> std::ops::DerefMut for AutoreleasedMutCell<'a, T> {
     #[inline] fn deref_mut(&mut self) -> &mut T {
+        #[cfg(feature="verify-pools")]
+        crate::autorelease::check_pool(self.pool, std::any::type_name::<T>());
         unsafe{ &mut *self.ptr.as_mut() }
     }
 }
@@ -224,13 +281,13 @@ impl<'a, T: ObjcInstance> std::ops::DerefMut for AutoreleasedMutCell<'a, T> {
 
 impl<'a, T: ObjcInstance> std::fmt::Display for AutoreleasedMutCell<'a, T> where T: std::fmt::Display {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let ptr = unsafe{ &*self.ptr.as_ptr() };
+        let ptr: &T = self;
         f.write_fmt(format_args!("{}",ptr))
     }
 }
 impl<'a, T: ObjcInstance> std::fmt::Debug for AutoreleasedMutCell<'a, T> where T: std::fmt::Debug {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let ptr = unsafe {&*(self.ptr.as_ptr())};
+        let ptr: &T = self;
         ptr.fmt(f)
     }
 }
@@ -255,7 +312,9 @@ impl<'a, T: ObjcInstance + ReinterpretCast<Target=U>, U: ObjcInstance> Autorelea
     pub fn safe_cast_into(self) -> AutoreleasedMutCell<'a, U> {
         AutoreleasedMutCell{
             ptr: unsafe{NonNull::new_unchecked(self.ptr.as_ptr() as *mut U)},
-            marker: PhantomData
+            marker: PhantomData,
+            #[cfg(feature="verify-pools")]
+            pool: self.pool,
         }
     }
 }
@@ -289,13 +348,12 @@ For an elided 'best case' version, see `RefCell`.
  */
 pub struct StrongCell<T: ObjcInstance>(NonNullImmutable<T>);
 impl<T: ObjcInstance> StrongCell<T> {
+    #[cfg_attr(feature="memory-tracing", track_caller)]
     pub fn retaining(cell: &T) -> Self {
         unsafe {
-            if DEBUG_MEMORY {
-                println!("retain {} {:p}",std::any::type_name::<T>(), cell);
-            }
+            trace_memory!(Retain, T, cell);
             objc_retain(cell as *const T as *const c_void);
-            Self::assume_retained(cell)
+            StrongCell(NonNullImmutable::from_reference(cell))
         }
     }
 
@@ -314,10 +372,10 @@ impl<T: ObjcInstance> StrongCell<T> {
     /// * That the type is retained
     /// * That the type is 'static, that is, it has no references to external (Rust) memory.
     ///   If this is not the case, see [StrongLifetimeCell].
+    ///
+    /// With the `memory-tracing` feature, this doesn't record the reference, since it's `const`.  Prefer [NonNullImmutable::assume_retained()]
+    /// or [Self::from_raw()], which do.
     pub const unsafe fn assume_retained(reference: &T) -> Self {
-        // if DEBUG_MEMORY {
-        //     println!("assume_retained {} {:p}",std::any::type_name::<T>(), reference);
-        // }
         StrongCell(NonNullImmutable::from_reference(reference))
     }
 
@@ -343,6 +401,7 @@ impl<T: ObjcInstance> StrongCell<T> {
     /// Results are not guaranteed to be optimized, in part because inline assembly is not stabilized.
     #[inline(always)] pub fn return_autoreleased(self) -> *const T {
         let ptr = self.0.as_ptr();
+        trace_memory!(Autorelease, T, ptr);
         std::mem::forget(self); //LEAK
         unsafe{ objc_autoreleaseReturnValue(ptr as *const c_void) as *const T }
     }
@@ -354,10 +413,7 @@ impl<T: ObjcInstance> StrongCell<T> {
     /// # Safety
     /// You must comply with all the safety guarantees of [ObjcInstanceBehavior::cast].
     #[inline] pub const unsafe fn cast_into<U: ObjcInstance>(self) -> StrongCell<U> {
-        // if DEBUG_MEMORY {
-        //     println!("cast_into {} => {} {:p}",std::any::type_name::<T>(), std::any::type_name::<U>(), self.0.as_ptr());
-        // }
-        let r = StrongCell::assume_retained(&*(self.0.as_ptr() as *const _ as *const U));
+        let r = StrongCell(NonNullImmutable::from_reference(&*(self.0.as_ptr() as *const _ as *const U)));
         std::mem::forget(self);
         r
     }
@@ -368,6 +424,44 @@ impl<T: ObjcInstance> StrongCell<T> {
             self.cast_into()
         }
     }
+
+    ///Returns a pointer to the object, without affecting its reference count.
+    ///
+    /// This is an associated function rather than a method so it doesn't hide methods on `T`.  Call it as `StrongCell::as_ptr(&cell)`.
+    pub fn as_ptr(cell: &Self) -> *const T {
+        cell.0.as_ptr()
+    }
+    ///Consumes the cell, returning a +1 pointer to the object.
+    ///
+    /// Ownership of the reference passes to you, so the object is not released.  This is for handing the object to C,
+    /// for example as the context pointer of a callback.  To take it back, use [Self::from_raw()], or release it some other way,
+    /// otherwise it leaks.
+    pub fn into_raw(cell: Self) -> *const T {
+        let ptr = cell.0.as_ptr();
+        trace_memory!(IntoRaw, T, ptr);
+        std::mem::forget(cell);
+        ptr
+    }
+    ///Takes ownership of a +1 pointer, such as one returned by [Self::into_raw()].
+    ///
+    /// # Safety
+    /// You are responsible to check:
+    /// * That `ptr` is a non-nil object of type `T`
+    /// * That you own a reference to it, which is transferred to the cell.  Calling this twice on the pointer from one [Self::into_raw()] is a double release.
+    /// * That the object is 'static, as for [Self::assume_retained()].
+    #[cfg_attr(feature="memory-tracing", track_caller)]
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        debug_assert!(!ptr.is_null(), "StrongCell::from_raw with nil");
+        trace_memory!(Adopt, T, ptr);
+        Self::assume_retained(&*ptr)
+    }
+    ///Gives the reference to objc (or anyone else) permanently, returning a reference with unbounded lifetime.
+    ///
+    /// Like `Box::leak`, the object is never released by Rust.  This makes "this object now belongs to objc" explicit,
+    /// when some objc API releases the object later on.
+    pub fn leak(cell: Self) -> &'static T {
+        unsafe{ &*Self::into_raw(cell) }
+    }
 }
 
 impl<T: ObjcInstance> Clone for StrongCell<T> {
@@ -378,9 +472,7 @@ impl<T: ObjcInstance> Clone for StrongCell<T> {
 impl<T: ObjcInstance> Drop for StrongCell<T> {
     fn drop(&mut self) {
         unsafe {
-            if DEBUG_MEMORY {
-                println!("Drop StrongCell<{}> {:p}",std::any::type_name::<T>(), self.0.as_ptr());
-            }
+            trace_memory!(Release, T, self.0.as_ptr());
 
             objc_release(self.0.as_ptr() as *const _ as *const c_void);
         }
@@ -442,13 +534,12 @@ unsafe impl<T: ObjcInstance + Sync> Sync for StrongCell<T> {}
 /// This is typically used for objects that borrow some Rust data
 pub struct StrongLifetimeCell<'a, T: ObjcInstance>(NonNullImmutable<T>,PhantomData<&'a ()>);
 impl<'a, T: ObjcInstance> StrongLifetimeCell<'a, T> {
+    #[cfg_attr(feature="memory-tracing", track_caller)]
     pub fn retaining(cell: &'a T) -> Self {
         unsafe {
-            if DEBUG_MEMORY {
-                println!("retain {} {:p}",std::any::type_name::<T>(), cell);
-            }
+            trace_memory!(Retain, T, cell);
             objc_retain(cell as *const T as *const c_void);
-            Self::assume_retained_limited(cell)
+            StrongLifetimeCell(NonNullImmutable::from_reference(cell), PhantomData)
         }
     }
 
@@ -469,6 +560,9 @@ impl<'a, T: ObjcInstance> StrongLifetimeCell<'a, T> {
     /// in this object will remain valid for the lifetime specified, which is unbounded.
     /// * That all objc APIs which end up seeing this pointer will either only access it for the lifetime specified,
     ///   or will take some other step (usually, copying) the object into a longer lifetime.
+    ///
+    /// With the `memory-tracing` feature, this doesn't record the reference, since it's `const`.  Prefer [NonNullImmutable::assume_retained_limited()]
+    /// or [Self::from_raw()], which do.
     pub const unsafe fn assume_retained_limited(reference: &'a T) -> Self {
         StrongLifetimeCell(NonNullImmutable::from_reference(reference), PhantomData )
     }
@@ -480,7 +574,7 @@ impl<'a, T: ObjcInstance> StrongLifetimeCell<'a, T> {
     /// # Safety
     /// You must comply with all the safety guarantees of [ObjcInstanceBehavior::cast].
     #[inline] pub const unsafe fn cast_into<U: ObjcInstance + 'a>(self) -> StrongLifetimeCell<'a, U>{
-        let r = StrongLifetimeCell::assume_retained_limited(&*(self.0.as_ptr() as *const U));
+        let r = StrongLifetimeCell(NonNullImmutable::from_reference(&*(self.0.as_ptr() as *const U)), PhantomData);
         std::mem::forget(self);
         r
     }
@@ -497,17 +591,49 @@ impl<'a, T: ObjcInstance> StrongLifetimeCell<'a, T> {
     /// Results are not guaranteed to be optimized, in part because inline assembly is not stabilized.
     #[inline(always)] pub fn return_autoreleased(self) -> *mut T {
         let ptr = self.0.as_ptr();
+        trace_memory!(Autorelease, T, ptr);
         std::mem::forget(self); //LEAK
         unsafe{ objc_autoreleaseReturnValue(ptr as *const c_void) as *const T as *mut T }
     }
+
+    ///Returns a pointer to the object, without affecting its reference count.
+    ///
+    /// This is an associated function, called as `StrongLifetimeCell::as_ptr(&cell)`.  See [StrongCell::as_ptr()].
+    pub fn as_ptr(cell: &Self) -> *const T {
+        cell.0.as_ptr()
+    }
+    ///Consumes the cell, returning a +1 pointer to the object.  See [StrongCell::into_raw()].
+    ///
+    /// The pointer isn't bound by `'a`, so it's up to you not to use the object after that.
+    pub fn into_raw(cell: Self) -> *const T {
+        let ptr = cell.0.as_ptr();
+        trace_memory!(IntoRaw, T, ptr);
+        std::mem::forget(cell);
+        ptr
+    }
+    ///Takes ownership of a +1 pointer, such as one returned by [Self::into_raw()].
+    ///
+    /// # Safety
+    /// You are responsible to check:
+    /// * That `ptr` is a non-nil object of type `T`
+    /// * That you own a reference to it, which is transferred to the cell.
+    /// * That the object is valid for `'a`, as for [Self::assume_retained_limited()].
+    #[cfg_attr(feature="memory-tracing", track_caller)]
+    pub unsafe fn from_raw(ptr: *const T) -> Self where T: 'a {
+        debug_assert!(!ptr.is_null(), "StrongLifetimeCell::from_raw with nil");
+        trace_memory!(Adopt, T, ptr);
+        Self::assume_retained_limited(&*ptr)
+    }
+    ///Gives the reference away permanently, without releasing it.  See [StrongCell::leak()].
+    pub fn leak(cell: Self) -> &'a T where T: 'a {
+        unsafe{ &*Self::into_raw(cell) }
+    }
 }
 
 impl<'a, T: ObjcInstance> Drop for StrongLifetimeCell<'a, T> {
     fn drop(&mut self) {
         unsafe {
-            if DEBUG_MEMORY {
-                println!("Drop {} {:p}",std::any::type_name::<T>(), self);
-            }
+            trace_memory!(Release, T, self.0.as_ptr());
             objc_release(self.0.as_ptr() as *const _ as *const c_void);
         }
     }
@@ -557,13 +683,12 @@ impl<'a, T: ObjcInstance + ReinterpretCast<Target=U>, U: ObjcInstance + 'a> Stro
 ///[StrongCell], but mutable
 pub struct StrongMutCell<T: ObjcInstance>(NonNull<T>);
 impl<T: ObjcInstance> StrongMutCell<T> {
+    #[cfg_attr(feature="memory-tracing", track_caller)]
     pub fn retaining(cell: &mut T) -> Self {
-        if DEBUG_MEMORY {
-            println!("retain {} {:p}",std::any::type_name::<T>(), cell);
-        }
+        trace_memory!(Retain, T, cell);
         unsafe {
             objc_retain(cell as *const T as *const c_void);
-            Self::assume_retained(cell)
+            StrongMutCell(NonNull::from(cell))
         }
     }
 
@@ -579,7 +704,7 @@ impl<T: ObjcInstance> StrongMutCell<T> {
     ///
     /// This consumes the cell, e.g. you can't have an exclusive and nonexclusive reference to the same object.
     pub fn as_const(self) -> StrongCell<T> {
-        let r: StrongCell<T> = unsafe{ StrongCell::assume_retained(&self) };
+        let r = StrongCell(NonNullImmutable::from_reference(&*self));
         std::mem::forget(self);
         r
     }
@@ -603,10 +728,9 @@ impl<T: ObjcInstance> StrongMutCell<T> {
     /// This is usually the case for some objc methods with names like `new`, `copy`, `init`, etc.
     /// # Safety
     /// If this isn't actually retained, will UB
+    #[cfg_attr(feature="memory-tracing", track_caller)]
     pub unsafe fn assume_retained(reference: &mut T) -> Self {
-        if DEBUG_MEMORY {
-            println!("assume_retained {} {:p}",std::any::type_name::<T>(), reference);
-        }
+        trace_memory!(Adopt, T, reference);
         //safe because we're using a reference
         StrongMutCell(NonNull::new_unchecked(reference))
     }
@@ -619,6 +743,7 @@ impl<T: ObjcInstance> StrongMutCell<T> {
     /// Results are not guaranteed to be optimized, in part because inline assembly is not stabilized.
     #[inline(always)] pub fn return_autoreleased(self) -> *mut T {
         let ptr = self.0.as_ptr();
+        trace_memory!(Autorelease, T, ptr);
         std::mem::forget(self); //LEAK
         unsafe{ objc_autoreleaseReturnValue(ptr as *const c_void) as *const T as *mut T }
     }
@@ -629,10 +754,40 @@ impl<T: ObjcInstance> StrongMutCell<T> {
     /// # Safety
     /// You must comply with all the safety guarantees of [ObjcInstanceBehavior::cast].
     #[inline] pub unsafe fn cast_into<U: ObjcInstance>(mut self) -> StrongMutCell<U> {
-        let r = StrongMutCell::assume_retained(self.deref_mut().cast_mut());
+        let r = StrongMutCell(NonNull::new_unchecked(self.deref_mut().cast_mut::<U>()));
         std::mem::forget(self);
         r
     }
+
+    ///Returns a pointer to the object, without affecting its reference count.
+    ///
+    /// This is an associated function, called as `StrongMutCell::as_ptr(&cell)`.  See [StrongCell::as_ptr()].
+    pub fn as_ptr(cell: &Self) -> *mut T {
+        cell.0.as_ptr()
+    }
+    ///Consumes the cell, returning a +1 pointer to the object.  See [StrongCell::into_raw()].
+    pub fn into_raw(cell: Self) -> *mut T {
+        let ptr = cell.0.as_ptr();
+        trace_memory!(IntoRaw, T, ptr);
+        std::mem::forget(cell);
+        ptr
+    }
+    ///Takes ownership of a +1 pointer, such as one returned by [Self::into_raw()].
+    ///
+    /// # Safety
+    /// You are responsible to check:
+    /// * That `ptr` is a non-nil object of type `T`
+    /// * That you own a reference to it, which is transferred to the cell.
+    /// * That there are no other references to the object, as for [StrongCell::assume_mut()].
+    #[cfg_attr(feature="memory-tracing", track_caller)]
+    pub unsafe fn from_raw(ptr: *mut T) -> Self {
+        debug_assert!(!ptr.is_null(), "StrongMutCell::from_raw with nil");
+        Self::assume_retained(&mut *ptr)
+    }
+    ///Gives the reference away permanently, without releasing it.  See [StrongCell::leak()].
+    pub fn leak(cell: Self) -> &'static mut T {
+        unsafe{ &mut *Self::into_raw(cell) }
+    }
 }
 
 ///We can implement Send for StrongMutCell conditional on T: Send, since we know the pointer is exclusive from StrongMutCell.
@@ -642,9 +797,7 @@ unsafe impl<T: ObjcInstance + Sync> Sync for StrongMutCell<T> {}
 impl<T: ObjcInstance> Drop for StrongMutCell<T> {
     fn drop(&mut self) {
         unsafe {
-            if DEBUG_MEMORY {
-                println!("Drop {} {:p}",std::any::type_name::<T>(), self);
-            }
+            trace_memory!(Release, T, self.0.as_ptr());
             objc_release(self.0.as_ptr() as *const _ as *const c_void);
         }
     }
@@ -692,4 +845,465 @@ impl<'a, T: ObjcInstance + ReinterpretCast<Target=U>, U: ObjcInstance> StrongMut
     pub fn safe_cast_into(self) -> StrongMutCell<U> {
         unsafe{self.cast_into()}
     }
-}
\ No newline at end of file
+}
+
+
+/**
+A zeroing weak reference to an objc object, like objc `weak`.
+
+This doesn't keep the object alive, so it's useful for delegates and back-pointers that would otherwise be retain cycles.
+When the object is deallocated, the runtime sets the reference to nil.  To use the object, [Self::upgrade()] it to a [StrongCell].
+
+```
+use objr::bindings::*;
+autoreleasepool(|pool| {
+    let object = NSObject::class().alloc_init(pool);
+    let weak = WeakCell::new(&*object);
+    assert!(weak.upgrade().is_some());
+    drop(object);
+    assert!(weak.upgrade().is_none());
+});
+```
+ */
+pub struct WeakCell<T: ObjcInstance> {
+    //the runtime keeps track of where weak references are, so this must not move
+    location: Box<UnsafeCell<*mut c_void>>,
+    _marker: PhantomData<*const T>,
+}
+impl<T: ObjcInstance> WeakCell<T> {
+    ///Creates a weak reference to `object`.
+    pub fn new(object: &T) -> Self {
+        let cell = WeakCell { location: Box::new(UnsafeCell::new(std::ptr::null_mut())), _marker: PhantomData };
+        unsafe{ objc_initWeak(cell.location.get(), object as *const T as *mut c_void) };
+        cell
+    }
+    ///Returns the object, retained, or `None` if it has been deallocated.
+    pub fn upgrade(&self) -> Option<StrongCell<T>> {
+        let ptr = unsafe{ objc_loadWeakRetained(self.location.get()) } as *const T;
+        if ptr.is_null() {
+            None
+        }
+        else {
+            Some(unsafe{ StrongCell::from_raw(ptr) })
+        }
+    }
+    ///Points the reference at another object, or at nil.
+    pub fn set(&mut self, object: Option<&T>) {
+        let ptr = object.map(|o| o as *const T as *mut c_void).unwrap_or(std::ptr::null_mut());
+        unsafe{ objc_storeWeak(self.location.get(), ptr) };
+    }
+}
+impl<T: ObjcInstance> Default for WeakCell<T> {
+    ///A weak reference to nil.
+    fn default() -> Self {
+        WeakCell { location: Box::new(UnsafeCell::new(std::ptr::null_mut())), _marker: PhantomData }
+    }
+}
+impl<T: ObjcInstance> Clone for WeakCell<T> {
+    fn clone(&self) -> Self {
+        let cell = WeakCell { location: Box::new(UnsafeCell::new(std::ptr::null_mut())), _marker: PhantomData };
+        unsafe{ objc_copyWeak(cell.location.get(), self.location.get()) };
+        cell
+    }
+}
+impl<T: ObjcInstance> Drop for WeakCell<T> {
+    fn drop(&mut self) {
+        unsafe{ objc_destroyWeak(self.location.get()) };
+    }
+}
+impl<T: ObjcInstance> std::fmt::Debug for WeakCell<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("WeakCell").field(&self.upgrade()).finish()
+    }
+}
+//Like StrongCell, since an upgrade gives you one.  The runtime's weak functions are threadsafe.
+unsafe impl<T: ObjcInstance + Sync> Send for WeakCell<T> {}
+unsafe impl<T: ObjcInstance + Sync> Sync for WeakCell<T> {}
+
+
+/*Shared implementation of the atomic cells.
+
+The problem is that `load` must retain the object before a concurrent `swap` releases it.  So readers announce themselves in
+a counter before reading the pointer, and a writer that takes an object out waits until every reader that might have read it
+is done, before handing it back (to be released).  Any reader that announced itself before the swap has retained the object
+by then, and any reader that announces itself after reads the new pointer.  That works because both sides write, then read
+the other's variable, all `SeqCst`.
+
+Progress: `load` never waits, it's a fixed number of atomic operations and a retain.  A writer only waits for readers that
+started before it: there are two counters, and `epoch` says which one new readers use.  The writer points new readers at
+the other counter before waiting for one to drain, so the only readers it can be waiting on are ones that were already in
+progress, or had read `epoch` just before it changed.  It does this for both counters.  Writers flip `epoch` one at a
+time, so one writer can wait for another's flips, but not for an unbounded stream of loads.
+ */
+struct AtomicSlot {
+    ptr: AtomicPtr<c_void>,
+    ///Loads in progress, by the value of `epoch` when they started
+    readers: [AtomicUsize; 2],
+    ///Which of `readers` new loads use, 0 or 1
+    epoch: AtomicUsize,
+    ///Held by a writer while it flips `epoch` and waits
+    writer: Mutex<()>,
+}
+impl AtomicSlot {
+    ///`ptr` is +1, or nil
+    fn new(ptr: *mut c_void) -> Self {
+        AtomicSlot { ptr: AtomicPtr::new(ptr), readers: [AtomicUsize::new(0), AtomicUsize::new(0)], epoch: AtomicUsize::new(0), writer: Mutex::new(()) }
+    }
+    ///Returns the object, +1, or nil
+    fn load(&self) -> *mut c_void {
+        let readers = &self.readers[self.epoch.load(Ordering::SeqCst)];
+        readers.fetch_add(1, Ordering::SeqCst);
+        let ptr = self.ptr.load(Ordering::SeqCst);
+        unsafe{ objc_retain(ptr) };
+        readers.fetch_sub(1, Ordering::SeqCst);
+        ptr
+    }
+    ///Waits for any `load` that might have read an object we took out
+    fn wait_for_readers(&self) {
+        //the lock protects nothing but the flips, so a panic while holding it doesn't matter
+        let _writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        for _ in 0..2 {
+            let draining = self.epoch.fetch_xor(1, Ordering::SeqCst);
+            while self.readers[draining].load(Ordering::SeqCst) != 0 {
+                std::thread::yield_now();
+            }
+        }
+    }
+    ///Stores `new` (+1, or nil) and returns the old object (+1, or nil)
+    fn swap(&self, new: *mut c_void) -> *mut c_void {
+        let old = self.ptr.swap(new, Ordering::SeqCst);
+        self.wait_for_readers();
+        old
+    }
+    ///Stores `new` if the current object is `current`, returning the old object (+1).  Otherwise returns `Err` and stores nothing.
+    fn compare_exchange(&self, current: *mut c_void, new: *mut c_void) -> Result<*mut c_void, ()> {
+        match self.ptr.compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(old) => {
+                self.wait_for_readers();
+                Ok(old)
+            }
+            Err(_) => Err(())
+        }
+    }
+}
+impl Drop for AtomicSlot {
+    fn drop(&mut self) {
+        unsafe{ objc_release(*self.ptr.get_mut()) };
+    }
+}
+
+///Converts a +1 pointer from [AtomicSlot] to a cell
+unsafe fn strong_from_slot<T: ObjcInstance>(ptr: *mut c_void) -> Option<StrongCell<T>> {
+    if ptr.is_null() { None } else { Some(StrongCell::from_raw(ptr as *const T)) }
+}
+///Converts a cell to a +1 pointer for [AtomicSlot]
+fn strong_into_slot<T: ObjcInstance>(cell: StrongCell<T>) -> *mut c_void {
+    StrongCell::into_raw(cell) as *mut c_void
+}
+
+/**
+A [StrongCell] that can be loaded and replaced from several threads, without a `Mutex`.
+
+This is useful for shared state like "the current document".  Each [Self::load()] retains the object, so it stays alive while
+you use it even if another thread replaces it.
+
+```
+use objr::bindings::*;
+autoreleasepool(|pool| {
+    let current = AtomicStrongCell::new(NSObject::class().alloc_init(pool));
+    let first = current.load();
+    let previous = current.swap(NSObject::class().alloc_init(pool));
+    assert!(std::ptr::eq(&*previous, &*first));
+    assert!(!std::ptr::eq(&*current.load(), &*first));
+});
+```
+
+# Progress
+[Self::load()] never waits for other threads.  [Self::store()], [Self::swap()] and [Self::compare_exchange()] take effect
+immediately, but then wait for the loads that were already in progress to finish before returning, so that no thread is still
+about to retain the object they took out.  Loads that start later don't hold them up, so writers finish promptly even when
+other threads load in a tight loop.  Writers do wait for each other during this step.
+
+For a cell that may be nil, see [AtomicOptionalStrongCell].
+ */
+pub struct AtomicStrongCell<T: ObjcInstance> {
+    slot: AtomicSlot,
+    _marker: PhantomData<StrongCell<T>>,
+}
+impl<T: ObjcInstance> AtomicStrongCell<T> {
+    pub fn new(cell: StrongCell<T>) -> Self {
+        AtomicStrongCell { slot: AtomicSlot::new(strong_into_slot(cell)), _marker: PhantomData }
+    }
+    ///Returns the current object, retained.
+    pub fn load(&self) -> StrongCell<T> {
+        unsafe{ strong_from_slot(self.slot.load()).unwrap() }
+    }
+    ///Replaces the object.  The old one is released.
+    pub fn store(&self, cell: StrongCell<T>) {
+        drop(self.swap(cell))
+    }
+    ///Replaces the object, returning the old one.
+    pub fn swap(&self, cell: StrongCell<T>) -> StrongCell<T> {
+        unsafe{ strong_from_slot(self.slot.swap(strong_into_slot(cell))).unwrap() }
+    }
+    ///Replaces the object with `new` if it is currently `current` (by identity).
+    ///
+    /// On success, returns the old object.  Otherwise, returns `new` back to you.
+    pub fn compare_exchange(&self, current: &T, new: StrongCell<T>) -> Result<StrongCell<T>, StrongCell<T>> {
+        let new_ptr = strong_into_slot(new);
+        match self.slot.compare_exchange(current as *const T as *mut c_void, new_ptr) {
+            Ok(old) => Ok(unsafe{ strong_from_slot(old).unwrap() }),
+            Err(()) => Err(unsafe{ strong_from_slot(new_ptr).unwrap() }),
+        }
+    }
+    ///Returns the object, without any atomic operations, since we have exclusive access.
+    pub fn into_inner(self) -> StrongCell<T> {
+        let ptr = self.slot.ptr.load(Ordering::Relaxed);
+        forget(self);
+        unsafe{ strong_from_slot(ptr).unwrap() }
+    }
+}
+impl<T: ObjcInstance> std::fmt::Debug for AtomicStrongCell<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("AtomicStrongCell").field(&self.load()).finish()
+    }
+}
+//Like StrongCell, which we hand out from any thread
+unsafe impl<T: ObjcInstance + Sync> Send for AtomicStrongCell<T> {}
+unsafe impl<T: ObjcInstance + Sync> Sync for AtomicStrongCell<T> {}
+
+///Like [AtomicStrongCell], but may be nil.
+///
+/// Writers wait for loads in progress, as described in [AtomicStrongCell#progress].
+pub struct AtomicOptionalStrongCell<T: ObjcInstance> {
+    slot: AtomicSlot,
+    _marker: PhantomData<StrongCell<T>>,
+}
+impl<T: ObjcInstance> AtomicOptionalStrongCell<T> {
+    pub fn new(cell: Option<StrongCell<T>>) -> Self {
+        AtomicOptionalStrongCell { slot: AtomicSlot::new(cell.map(strong_into_slot).unwrap_or(std::ptr::null_mut())), _marker: PhantomData }
+    }
+    ///Returns the current object, retained.
+    pub fn load(&self) -> Option<StrongCell<T>> {
+        unsafe{ strong_from_slot(self.slot.load()) }
+    }
+    ///Replaces the object.  The old one is released.
+    pub fn store(&self, cell: Option<StrongCell<T>>) {
+        drop(self.swap(cell))
+    }
+    ///Replaces the object, returning the old one.
+    pub fn swap(&self, cell: Option<StrongCell<T>>) -> Option<StrongCell<T>> {
+        unsafe{ strong_from_slot(self.slot.swap(cell.map(strong_into_slot).unwrap_or(std::ptr::null_mut()))) }
+    }
+    ///Takes the object out, leaving nil.
+    pub fn take(&self) -> Option<StrongCell<T>> {
+        self.swap(None)
+    }
+    ///Replaces the object with `new` if it is currently `current` (by identity, `None` meaning nil).
+    ///
+    /// On success, returns the old object.  Otherwise, returns `new` back to you.
+    pub fn compare_exchange(&self, current: Option<&T>, new: Option<StrongCell<T>>) -> Result<Option<StrongCell<T>>, Option<StrongCell<T>>> {
+        let current_ptr = current.map(|c| c as *const T as *mut c_void).unwrap_or(std::ptr::null_mut());
+        let new_ptr = new.map(strong_into_slot).unwrap_or(std::ptr::null_mut());
+        match self.slot.compare_exchange(current_ptr, new_ptr) {
+            Ok(old) => Ok(unsafe{ strong_from_slot(old) }),
+            Err(()) => Err(unsafe{ strong_from_slot(new_ptr) }),
+        }
+    }
+    ///Returns the object, without any atomic operations, since we have exclusive access.
+    pub fn into_inner(self) -> Option<StrongCell<T>> {
+        let ptr = self.slot.ptr.load(Ordering::Relaxed);
+        forget(self);
+        unsafe{ strong_from_slot(ptr) }
+    }
+}
+impl<T: ObjcInstance> Default for AtomicOptionalStrongCell<T> {
+    ///A nil cell.
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+impl<T: ObjcInstance> std::fmt::Debug for AtomicOptionalStrongCell<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("AtomicOptionalStrongCell").field(&self.load()).finish()
+    }
+}
+unsafe impl<T: ObjcInstance + Sync> Send for AtomicOptionalStrongCell<T> {}
+unsafe impl<T: ObjcInstance + Sync> Sync for AtomicOptionalStrongCell<T> {}
+
+#[cfg(all(test, feature="mock-runtime"))]
+mod test {
+    use crate::bindings::*;
+    use crate::mock::{retain_count, is_deallocated, new_shared_object};
+    use std::ffi::c_void;
+
+    objc_class! {
+        pub struct Shared {
+            @class(Shared)
+        }
+    }
+    //only made by `new_shared_object`, which any thread can retain and release
+    unsafe impl Sync for Shared {}
+
+    #[test] fn weak_cell() {
+        autoreleasepool(|pool| {
+            let object = NSObject::class().alloc_init(pool);
+            let ptr = &*object as *const NSObject;
+            let weak = WeakCell::new(&*object);
+            //weak references don't retain
+            assert_eq!(retain_count(ptr), 1);
+            let clone = weak.clone();
+            let upgraded = weak.upgrade().unwrap();
+            assert_eq!(retain_count(ptr), 2);
+            drop(upgraded);
+            drop(object);
+            assert!(is_deallocated(ptr));
+            assert!(weak.upgrade().is_none());
+            assert!(clone.upgrade().is_none());
+        })
+    }
+
+    #[test] fn weak_cell_set() {
+        autoreleasepool(|pool| {
+            let first = NSObject::class().alloc_init(pool);
+            let second = NSObject::class().alloc_init(pool);
+            let mut weak = WeakCell::new(&*first);
+            weak.set(Some(&second));
+            assert_eq!(weak.upgrade().as_deref().map(|o| o as *const NSObject), Some(&*second as *const NSObject));
+            //moving the cell doesn't move the weak reference
+            let moved = vec![weak];
+            drop(second);
+            assert!(moved[0].upgrade().is_none());
+            let mut weak = WeakCell::<NSObject>::default();
+            assert!(weak.upgrade().is_none());
+            weak.set(Some(&first));
+            weak.set(None);
+            assert!(weak.upgrade().is_none());
+            drop(weak);
+            let ptr = &*first as *const NSObject;
+            drop(first);
+            assert!(is_deallocated(ptr));
+        })
+    }
+
+    #[test] fn atomic_strong_cell() {
+        autoreleasepool(|pool| {
+            let first = NSObject::class().alloc_init(pool);
+            let second = NSObject::class().alloc_init(pool);
+            let (first_ptr, second_ptr) = (&*first as *const NSObject, &*second as *const NSObject);
+            let cell = AtomicStrongCell::new(first);
+            let loaded = cell.load();
+            assert_eq!(retain_count(first_ptr), 2);
+            //the cell doesn't match, so we get `second` back
+            let second = cell.compare_exchange(unsafe{ &*second_ptr }, second).unwrap_err();
+            let previous = cell.compare_exchange(&loaded, second).unwrap();
+            assert_eq!(&*previous as *const NSObject, first_ptr);
+            assert_eq!(retain_count(second_ptr), 1);
+            drop(previous);
+            drop(loaded);
+            assert!(is_deallocated(first_ptr));
+            cell.store(NSObject::class().alloc_init(pool));
+            assert!(is_deallocated(second_ptr));
+            let inner = cell.into_inner();
+            assert_eq!(retain_count(&*inner), 1);
+        })
+    }
+
+    #[test] fn atomic_optional_strong_cell() {
+        autoreleasepool(|pool| {
+            let cell = AtomicOptionalStrongCell::<NSObject>::default();
+            assert!(cell.load().is_none());
+            let object = NSObject::class().alloc_init(pool);
+            let ptr = &*object as *const NSObject;
+            assert!(cell.compare_exchange(None, Some(object)).unwrap().is_none());
+            assert_eq!(retain_count(ptr), 1);
+            assert_eq!(cell.load().as_deref().map(|o| o as *const NSObject), Some(ptr));
+            let taken = cell.take();
+            assert!(cell.load().is_none());
+            cell.store(taken);
+            drop(cell);
+            assert!(is_deallocated(ptr));
+        })
+    }
+
+    //each object is only owned by a cell, so a load that retained one after the writer that took it out released it would panic
+    #[test] fn atomic_cells_threads() {
+        let new = || unsafe{ StrongCell::from_raw(new_shared_object() as *const Shared) };
+        let strong = AtomicStrongCell::new(new());
+        let optional = AtomicOptionalStrongCell::default();
+        let mut created = vec![StrongCell::as_ptr(&strong.load()) as usize];
+        std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4).map(|_| scope.spawn(|| {
+                let mut created = Vec::new();
+                for i in 0..10000 {
+                    let (first, second) = (new(), new());
+                    created.extend([StrongCell::as_ptr(&first) as usize, StrongCell::as_ptr(&second) as usize]);
+                    match i % 3 {
+                        0 => strong.store(first),
+                        1 => drop(strong.compare_exchange(&strong.load(), first)),
+                        _ => drop(strong.swap(first)),
+                    }
+                    if i % 2 == 0 {
+                        drop(optional.compare_exchange(optional.load().as_deref(), Some(second)));
+                    }
+                    else {
+                        drop(optional.swap(Some(second)));
+                        drop(optional.take());
+                    }
+                }
+                created
+            })).collect();
+            for thread in threads {
+                created.extend(thread.join().unwrap());
+            }
+        });
+        let last = (Some(strong.into_inner()), optional.into_inner());
+        for object in [&last.0, &last.1].into_iter().flatten() {
+            assert_eq!(retain_count(StrongCell::as_ptr(object)), 1);
+        }
+        drop(last);
+        assert!(created.iter().all(|p| is_deallocated(*p as *const Shared)));
+    }
+
+    #[test] fn atomic_swap_while_loading() {
+        let new = || unsafe{ StrongCell::from_raw(new_shared_object() as *const Shared) };
+        let strong = AtomicStrongCell::new(new());
+        let done = std::sync::atomic::AtomicBool::new(false);
+        let started = std::sync::Barrier::new(5);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    started.wait();
+                    while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                        drop(strong.load());
+                    }
+                });
+            }
+            started.wait();
+            //with the loads overlapping, there's rarely a moment when none is in progress
+            for _ in 0..1000 {
+                drop(strong.swap(new()));
+            }
+            done.store(true, std::sync::atomic::Ordering::Relaxed);
+        });
+        assert_eq!(retain_count(StrongCell::as_ptr(&strong.into_inner())), 1);
+    }
+
+    #[test] fn raw_round_trip() {
+        autoreleasepool(|pool| {
+            let object = NSObject::class().alloc_init(pool);
+            let ptr = StrongCell::as_ptr(&object);
+            //a context pointer, as we'd give to a C callback
+            let context = StrongCell::into_raw(object) as *mut c_void;
+            assert_eq!(retain_count(ptr), 1);
+            let object = unsafe{ StrongCell::<NSObject>::from_raw(context as *const NSObject) };
+            let mutable = unsafe{ object.assume_mut() };
+            let raw = StrongMutCell::into_raw(mutable);
+            let mutable = unsafe{ StrongMutCell::from_raw(raw) };
+            let leaked = StrongMutCell::leak(mutable);
+            assert_eq!(retain_count(ptr), 1);
+            drop(unsafe{ StrongCell::from_raw(&*leaked) });
+            assert!(is_deallocated(ptr));
+        })
+    }
+}
@@ -3,6 +3,7 @@
 use std::ffi::c_void;
 use super::arguments::{Arguments};
 use super::arguments::Primitive;
+use super::arguments::VariadicArguments;
 use super::objectpointers::{AutoreleasedCell};
 use super::sel::Sel;
 use super::objcinstance::ObjcInstance;
@@ -45,11 +46,14 @@ unsafe impl <O: ObjcClass + 'static> PerformableSuper for O {
         unsafe{ std::mem::transmute(Self::class()) }
     }
 }
+#[cfg(not(feature="mock-runtime"))]
 #[link(name="objc",kind="dylib")]
 extern {
     //https://clang.llvm.org/docs/AutomaticReferenceCounting.html#arc-runtime-objc-retainautoreleasedreturnvalue
     pub(crate) fn objc_retainAutoreleasedReturnValue(id: *const c_void) -> *mut c_void;
 }
+#[cfg(feature="mock-runtime")]
+pub(crate) use crate::mock::runtime::objc_retainAutoreleasedReturnValue;
 
 
 ///Trait that provides `PerformSelector` implementations.  Autoimplelmented for `T: PerformablePointer`
@@ -99,6 +103,42 @@ pub trait PerformsSelector  {
     ///# Safety
     ///See the safety section of [crate::bindings::objc_instance!].
     unsafe fn perform_result_autorelease_to_retain<A: Arguments, R: ObjcInstance>(receiver: *mut Self, selector: Sel, pool: &ActiveAutoreleasePool, args: A) -> Result<*const R, AutoreleasedCell<'_, NSError>>;
+
+    ///Performs a variadic method, like `+[NSArray arrayWithObjects:]`, returning a primitive type.
+    ///
+    /// `args` are the named arguments, and `variadic` are the arguments for the `...`, both as tuples.
+    /// # Safety
+    /// See the safety section of [crate::bindings::objc_instance!].  In addition, the variadic arguments must be what the method
+    /// expects, including any terminator, since it can't tell what was passed.
+    unsafe fn perform_variadic_primitive<A: Arguments, V: VariadicArguments<A>, R: Primitive>(receiver: *mut Self, selector: Sel, pool: &ActiveAutoreleasePool, args: A, variadic: V) -> R;
+
+    ///Performs a variadic method, returning the specified [ObjcInstance].  You must coerce this into some type according to your knowledge of ObjC convention.
+    ///
+    /// ```no_run
+    /// use objr::bindings::*;
+    /// objc_class! {
+    ///     pub struct NSArray {
+    ///         @class(NSArray)
+    ///     }
+    /// }
+    /// autoreleasepool(|pool| {
+    ///     let first = objc_nsstring!("first");
+    ///     let second = objc_nsstring!("second");
+    ///     //nil-terminated, as the method expects
+    ///     let array: *const NSArray = unsafe{ Class::<NSArray>::perform_variadic(NSArray::class().assume_nonmut_perform(), Sel::from_str("arrayWithObjects:"), pool,
+    ///         (first.assume_nonmut_perform(),), (second.assume_nonmut_perform(), std::ptr::null_mut::<NSString>())) };
+    /// });
+    /// ```
+    /// # Safety
+    /// See [Self::perform_variadic_primitive()].
+    unsafe fn perform_variadic<A: Arguments, V: VariadicArguments<A>, R: ObjcInstance>(receiver: *mut Self, selector: Sel, pool: &ActiveAutoreleasePool, args: A, variadic: V) -> *const R;
+
+    ///Performs a variadic method, returning the specified [ObjcInstance].
+    ///
+    /// This variant assumes 1) the calling convention is +0, 2) the type returned to you is +1, as [Self::perform_autorelease_to_retain()].
+    /// # Safety
+    /// See [Self::perform_variadic_primitive()].
+    unsafe fn perform_variadic_autorelease_to_retain<A: Arguments, V: VariadicArguments<A>, R: ObjcInstance>(receiver: *mut Self, selector: Sel, pool: &ActiveAutoreleasePool, args: A, variadic: V) -> *const R;
 }
 
 ///implementation detail of perform_autorelease_to_strong_nonnull
@@ -148,6 +188,20 @@ impl<T: PerformablePointer> PerformsSelector for T  {
     #[inline] unsafe fn perform_result_autorelease_to_retain<'a, A: Arguments, R: ObjcInstance>(receiver: *mut Self, selector: Sel, pool: &'a ActiveAutoreleasePool, args: A) -> Result<*const R, AutoreleasedCell<'a, NSError>> {
        Arguments::invoke_error_trampoline_strong(receiver as *mut c_void, selector, pool, args)
     }
+
+    #[inline] unsafe fn perform_variadic_primitive<A: Arguments, V: VariadicArguments<A>, R: Primitive>(receiver: *mut Self, selector: Sel, pool: &ActiveAutoreleasePool, args: A, variadic: V) -> R {
+        V::invoke_primitive(receiver as *mut c_void, selector, pool, args, variadic)
+    }
+
+    #[inline] unsafe fn perform_variadic<A: Arguments, V: VariadicArguments<A>, R: ObjcInstance>(receiver: *mut Self, selector: Sel, pool: &ActiveAutoreleasePool, args: A, variadic: V) -> *const R {
+        let ptr: *mut c_void = V::invoke_primitive(receiver as *mut c_void, selector, pool, args, variadic);
+        ptr as *const R
+    }
+
+    #[inline] unsafe fn perform_variadic_autorelease_to_retain<A: Arguments, V: VariadicArguments<A>, R: ObjcInstance>(receiver: *mut Self, selector: Sel, pool: &ActiveAutoreleasePool, args: A, variadic: V) -> *const R {
+        let ptr: *mut c_void = V::invoke_primitive(receiver as *mut c_void, selector, pool, args, variadic);
+        objc_retainAutoreleasedReturnValue(ptr) as *const R
+    }
 }
 
 ///Variants of the perform functions that talk to `super` instead of `self`.  In general, this is supported on classes.
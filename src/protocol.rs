@@ -0,0 +1,43 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+//! ObjC protocol objects, as used by `conformsToProtocol:`.
+use std::ffi::{c_void, CStr};
+use super::bindings::*;
+
+#[cfg(not(feature="mock-runtime"))]
+#[link(name="objc", kind="dylib")]
+extern "C" {
+    fn objc_getProtocol(name: *const std::os::raw::c_char) -> *mut c_void;
+}
+#[cfg(feature="mock-runtime")]
+use crate::mock::runtime::objc_getProtocol;
+
+///Untyped pointer to an ObjC protocol (`Protocol *`).
+///
+/// Typically you get one with [Protocol::named()] and pass it to [NSObjectTrait::conforms_to_protocol()].
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct Protocol(c_void);
+unsafe impl Arguable for Protocol {}
+impl ObjcInstance for Protocol {}
+//protocols are immutable after they are registered
+unsafe impl Send for Protocol {}
+unsafe impl Sync for Protocol {}
+
+impl PartialEq for Protocol {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Protocol {
+    ///Looks up a protocol by name, like `objc_getProtocol`.
+    ///
+    /// Returns `None` if the runtime has not seen the protocol.  Note that the runtime only knows about
+    /// protocols that some loaded image declares or refers to, for example with `@protocols` in [objc_subclass!].
+    pub fn named(name: &CStr) -> Option<&'static Self> {
+        unsafe {
+            let protocol = objc_getProtocol(name.as_ptr());
+            (protocol as *const Self).as_ref()
+        }
+    }
+}
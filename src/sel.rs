@@ -2,11 +2,15 @@
 
 use std::ffi::{c_void, CString};
 use std::os::raw::c_char;
+use std::sync::atomic::{AtomicPtr, Ordering};
 
+#[cfg(not(feature="mock-runtime"))]
 #[link(name="objc", kind="dylib")]
 extern "C" {
     fn sel_registerName(string: *const c_char) -> *const c_void;
 }
+#[cfg(feature="mock-runtime")]
+use crate::mock::runtime::sel_registerName;
 
 ///ObjC-compatible selector.  This type is repr-transparent and can go over the wire as an arg.
 #[derive(Copy,Clone,Debug)]
@@ -27,6 +31,21 @@ impl Sel {
         Sel(ptr)
     }
 
+    ///Registers a selector from its nul-terminated name, caching the result in `cache`.
+    ///
+    /// Used by [crate::bindings::objc_selector_group!()] on runtimes where dyld does not fix up selector references for us.
+    #[doc(hidden)]
+    pub fn _cached(cache: &AtomicPtr<c_void>, name: &'static [u8]) -> Self {
+        let cached = cache.load(Ordering::Relaxed);
+        if !cached.is_null() {
+            return Sel(cached);
+        }
+        let sel = unsafe{ sel_registerName(name.as_ptr() as *const c_char) };
+        //racing here is harmless since the runtime gives the same answer each time
+        cache.store(sel as *mut c_void, Ordering::Relaxed);
+        Sel(sel)
+    }
+
 }
 
 ///Primarily used by [objc_subclass!] and similar.
@@ -37,6 +56,7 @@ unsafe impl<T> core::marker::Sync for _SyncWrapper<T> {}
 
 
 //this magic is needed for dyld to think our program is objc and fixup our symbols
+#[cfg(not(any(feature="gnustep", feature="mock-runtime")))]
 #[link_section = "__DATA,__objc_imageinfo,regular,no_dead_strip"]
 #[export_name = "\x01L_OBJC_IMAGE_INFO"]
 #[used]
@@ -1,5 +1,317 @@
 //SPDX-License-Identifier: MIT OR Apache-2.0
 
+use core::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+///Storage for an ivar offset (`OBJC_IVAR_$_Class.ivar`).
+///
+/// The runtime rewrites this value when the superclass layout differs from what we knew at compile time,
+/// so it can't be an ordinary immutable static.
+#[repr(transparent)]
+#[doc(hidden)]
+pub struct _IvarOffset(UnsafeCell<u32>);
+unsafe impl Sync for _IvarOffset {}
+impl _IvarOffset {
+    pub const fn new(offset: u32) -> Self {
+        _IvarOffset(UnsafeCell::new(offset))
+    }
+    ///Pointer suitable for the `offset` field of an ivar list.
+    pub const fn as_ptr(&self) -> *const u32 {
+        self.0.get()
+    }
+    ///Reads the runtime value, not the value known at compile time.
+    #[inline] pub fn get(&self) -> u32 {
+        unsafe{ std::ptr::read_volatile(self.0.get()) }
+    }
+}
+
+///Storage for a `payload: uninitialized T`, which can be initialized once.
+///
+/// This is a minimal `OnceCell` that is `Sync`, since ObjC objects can be used from several threads.
+/// `state` is `PAYLOAD_EMPTY`, `PAYLOAD_WRITING` or `PAYLOAD_READY`, and the value is only read once it's ready.
+#[doc(hidden)]
+pub struct _PayloadCell<T> {
+    state: AtomicU8,
+    value: UnsafeCell<Option<T>>,
+}
+const PAYLOAD_EMPTY: u8 = 0;
+const PAYLOAD_WRITING: u8 = 1;
+const PAYLOAD_READY: u8 = 2;
+//Shared references only read the value after it's ready, and only one thread can write it.
+unsafe impl<T: Send + Sync> Sync for _PayloadCell<T> {}
+impl<T> Default for _PayloadCell<T> {
+    fn default() -> Self {
+        _PayloadCell{ state: AtomicU8::new(PAYLOAD_EMPTY), value: UnsafeCell::new(None) }
+    }
+}
+impl<T> _PayloadCell<T> {
+    ///Stores the value, or gives it back if the cell was already initialized (or is being initialized on another thread).
+    pub fn set(&self, value: T) -> Result<(),T> {
+        if self.state.compare_exchange(PAYLOAD_EMPTY, PAYLOAD_WRITING, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            return Err(value);
+        }
+        //we're the only writer, and nobody reads until the state is ready
+        unsafe{ *self.value.get() = Some(value) };
+        self.state.store(PAYLOAD_READY, Ordering::Release);
+        Ok(())
+    }
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == PAYLOAD_READY {
+            unsafe{ (*self.value.get()).as_ref() }
+        }
+        else {
+            None
+        }
+    }
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.value.get_mut().as_mut()
+    }
+}
+
+///Sizes of a method implementation's arguments and return type, used to check them against the ObjC declaration.
+#[doc(hidden)]
+pub trait _MethodSignature {
+    const ARGUMENT_SIZES: &'static [usize];
+    const RETURN_SIZE: usize;
+}
+macro_rules! method_signature_impl {
+    ($($type:ident),*) => {
+        impl<R $(, $type)*> _MethodSignature for unsafe extern "C" fn($($type),*) -> R {
+            const ARGUMENT_SIZES: &'static [usize] = &[$(std::mem::size_of::<$type>()),*];
+            const RETURN_SIZE: usize = std::mem::size_of::<R>();
+        }
+    }
+}
+//self and _cmd, plus up to 16 arguments and the error pointer
+method_signature_impl!(S,C);
+method_signature_impl!(S,C,A);
+method_signature_impl!(S,C,A,B);
+method_signature_impl!(S,C,A,B,D);
+method_signature_impl!(S,C,A,B,D,E);
+method_signature_impl!(S,C,A,B,D,E,F);
+method_signature_impl!(S,C,A,B,D,E,F,G);
+method_signature_impl!(S,C,A,B,D,E,F,G,H);
+method_signature_impl!(S,C,A,B,D,E,F,G,H,I);
+method_signature_impl!(S,C,A,B,D,E,F,G,H,I,J);
+method_signature_impl!(S,C,A,B,D,E,F,G,H,I,J,K);
+method_signature_impl!(S,C,A,B,D,E,F,G,H,I,J,K,L);
+method_signature_impl!(S,C,A,B,D,E,F,G,H,I,J,K,L,M);
+method_signature_impl!(S,C,A,B,D,E,F,G,H,I,J,K,L,M,N);
+method_signature_impl!(S,C,A,B,D,E,F,G,H,I,J,K,L,M,N,O);
+method_signature_impl!(S,C,A,B,D,E,F,G,H,I,J,K,L,M,N,O,P);
+method_signature_impl!(S,C,A,B,D,E,F,G,H,I,J,K,L,M,N,O,P,Q);
+method_signature_impl!(S,C,A,B,D,E,F,G,H,I,J,K,L,M,N,O,P,Q,T);
+
+///Argument sizes and return size of `function`, in a const context.
+#[doc(hidden)]
+pub const fn _method_signature_sizes<F: _MethodSignature>(_function: &F) -> (&'static [usize], usize) {
+    (F::ARGUMENT_SIZES, F::RETURN_SIZE)
+}
+
+#[cfg(not(feature="mock-runtime"))]
+#[link(name="objc", kind="dylib")]
+extern "C" {
+    ///Used by synthesized property getters.  Handles atomicity.
+    #[doc(hidden)]
+    #[link_name="objc_getProperty"]
+    pub fn _objc_getProperty(objc_self: *mut core::ffi::c_void, sel: crate::bindings::Sel, offset: isize, atomic: bool) -> *const core::ffi::c_void;
+    ///Used by synthesized property setters.  Handles retain/release, copy and atomicity.
+    #[doc(hidden)]
+    #[link_name="objc_setProperty"]
+    pub fn _objc_setProperty(objc_self: *mut core::ffi::c_void, sel: crate::bindings::Sel, offset: isize, value: *const core::ffi::c_void, atomic: bool, should_copy: bool);
+}
+#[cfg(feature="mock-runtime")]
+#[doc(hidden)]
+pub use crate::mock::runtime::{objc_getProperty as _objc_getProperty, objc_setProperty as _objc_setProperty};
+
+///Registers subclasses with the runtime at first use, for runtimes that don't read our Mach-O sections.
+#[cfg(any(feature="gnustep", feature="mock-runtime"))]
+mod dynamic {
+    use std::ffi::{c_void, CStr, CString};
+    use std::os::raw::c_char;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicPtr, Ordering};
+    use crate::bindings::{AnyClass, Sel};
+    #[cfg(feature="mock-runtime")]
+    use crate::mock::runtime::{objc_getClass, objc_allocateClassPair, objc_registerClassPair, object_getClass, class_addMethod, class_addIvar, class_getInstanceVariable, ivar_getOffset, sel_registerName, objc_getProtocol, objc_allocateProtocol, objc_registerProtocol, class_addProtocol, class_addProperty};
+
+    #[cfg(all(feature="gnustep", not(feature="mock-runtime")))]
+    #[link(name="objc", kind="dylib")]
+    extern "C" {
+        fn objc_getClass(name: *const c_char) -> *mut c_void;
+        fn objc_allocateClassPair(superclass: *mut c_void, name: *const c_char, extra_bytes: usize) -> *mut c_void;
+        fn objc_registerClassPair(class: *mut c_void);
+        fn object_getClass(object: *mut c_void) -> *mut c_void;
+        fn class_addMethod(class: *mut c_void, name: Sel, imp: *const c_void, types: *const c_char) -> bool;
+        fn class_addIvar(class: *mut c_void, name: *const c_char, size: usize, alignment: u8, types: *const c_char) -> bool;
+        fn class_getInstanceVariable(class: *mut c_void, name: *const c_char) -> *mut c_void;
+        fn ivar_getOffset(ivar: *mut c_void) -> isize;
+        fn sel_registerName(name: *const c_char) -> *const c_void;
+        fn objc_getProtocol(name: *const c_char) -> *mut c_void;
+        fn objc_allocateProtocol(name: *const c_char) -> *mut c_void;
+        fn objc_registerProtocol(protocol: *mut c_void);
+        fn class_addProtocol(class: *mut c_void, protocol: *mut c_void) -> bool;
+        fn class_addProperty(class: *mut c_void, name: *const c_char, attributes: *const PropertyAttribute, count: u32) -> bool;
+    }
+
+    //These mirror the layouts declared by `__objc_subclass_implpart_a!` and friends.
+    #[repr(C)]
+    struct ClassRo {
+        flags: u32,
+        instance_start: u32,
+        instance_size: u32,
+        reserved: u32,
+        ivar_layout: *const c_void,
+        name: *const c_char,
+        base_method_list: *const List<Method>,
+        base_protocols: *const ProtocolList,
+        ivars: *const List<Ivar>,
+        weak_ivar_layout: *const c_void,
+        base_properties: *const List<Property>,
+    }
+    ///A list header, followed inline by `count` elements.
+    #[repr(C)]
+    struct List<T> {
+        magic: u32,
+        count: u32,
+        first: T,
+    }
+    impl<T> List<T> {
+        unsafe fn iter<'a>(list: *const Self) -> impl Iterator<Item=&'a T> where T: 'a {
+            let (first, count) = if list.is_null() { (std::ptr::null(), 0) } else { (&(*list).first as *const T, (*list).count as usize) };
+            (0..count).map(move |i| &*first.add(i))
+        }
+    }
+    #[repr(C)]
+    struct Method {
+        name: *const c_char,
+        types: *const c_char,
+        imp: *const c_void,
+    }
+    #[repr(C)]
+    struct Ivar {
+        offset: *const u32,
+        name: *const c_char,
+        r#type: *const c_char,
+        //log2, like objc4's `alignment_raw`
+        alignment: u32,
+        size: u32,
+    }
+
+    #[repr(C)]
+    struct Property {
+        name: *const c_char,
+        attributes: *const c_char,
+    }
+    ///`objc_property_attribute_t`
+    #[repr(C)]
+    pub(crate) struct PropertyAttribute {
+        pub(crate) name: *const c_char,
+        pub(crate) value: *const c_char,
+    }
+    ///Like [List], but the count is pointer-sized
+    #[repr(C)]
+    struct ProtocolList {
+        count: usize,
+        first: *const Protocol,
+    }
+    impl ProtocolList {
+        unsafe fn iter<'a>(list: *const Self) -> impl Iterator<Item=&'a Protocol> {
+            let (first, count) = if list.is_null() { (std::ptr::null(), 0) } else { (&(*list).first as *const *const Protocol, (*list).count) };
+            (0..count).map(move |i| &**first.add(i))
+        }
+    }
+    ///The start of `protocol_t`
+    #[repr(C)]
+    struct Protocol {
+        isa: *const c_void,
+        mangled_name: *const c_char,
+    }
+
+    static REGISTER_LOCK: Mutex<()> = Mutex::new(());
+
+    unsafe fn add_methods(class: *mut c_void, list: *const List<Method>) {
+        for method in List::iter(list) {
+            class_addMethod(class, Sel::from_ptr(sel_registerName(method.name)), method.imp, method.types);
+        }
+    }
+
+    ///Registers a class described by `class_ro`/`metaclass_ro` with the runtime, caching the result in `cache`.
+    ///
+    /// # Safety
+    /// `class_ro` and `metaclass_ro` must point to metadata emitted by [objc_subclass!].
+    #[doc(hidden)]
+    pub unsafe fn _register_subclass(cache: &AtomicPtr<c_void>, superclass: &'static [u8], class_ro: *const c_void, metaclass_ro: *const c_void) -> &'static AnyClass {
+        let class_ro = &*(class_ro as *const ClassRo);
+        let metaclass_ro = &*(metaclass_ro as *const ClassRo);
+        if cfg!(feature="mock-runtime") {
+            //each thread has its own mock runtime, so a process-wide cache would hand out classes from other threads
+            let existing = objc_getClass(class_ro.name);
+            if !existing.is_null() {
+                return &*(existing as *const AnyClass);
+            }
+        }
+        let cached = cache.load(Ordering::Acquire);
+        if !cached.is_null() {
+            return &*(cached as *const AnyClass);
+        }
+        let _guard = REGISTER_LOCK.lock().unwrap();
+        //someone may have beaten us to the lock
+        let cached = cache.load(Ordering::Acquire);
+        if !cached.is_null() {
+            return &*(cached as *const AnyClass);
+        }
+        let superclass_ptr = objc_getClass(superclass.as_ptr() as *const c_char);
+        assert!(!superclass_ptr.is_null(), "No superclass named {}", String::from_utf8_lossy(&superclass[..superclass.len() - 1]));
+        let class = objc_allocateClassPair(superclass_ptr, class_ro.name, 0);
+        //Nil here means the name is taken, perhaps by another copy of this code
+        assert!(!class.is_null(), "Can't register class {:?}", std::ffi::CStr::from_ptr(class_ro.name));
+        add_methods(class, class_ro.base_method_list);
+        add_methods(object_getClass(class), metaclass_ro.base_method_list);
+        for protocol in ProtocolList::iter(class_ro.base_protocols) {
+            //We only know the name, so if nobody has declared the protocol yet, declare an empty one
+            let mut runtime_protocol = objc_getProtocol(protocol.mangled_name);
+            if runtime_protocol.is_null() {
+                let new_protocol = objc_allocateProtocol(protocol.mangled_name);
+                if new_protocol.is_null() {
+                    //someone else declared it in the meantime
+                    runtime_protocol = objc_getProtocol(protocol.mangled_name);
+                }
+                else {
+                    objc_registerProtocol(new_protocol);
+                    runtime_protocol = new_protocol;
+                }
+            }
+            class_addProtocol(class, runtime_protocol);
+        }
+        for ivar in List::iter(class_ro.ivars) {
+            class_addIvar(class, ivar.name, ivar.size as usize, ivar.alignment as u8, ivar.r#type);
+        }
+        for property in List::iter(class_ro.base_properties) {
+            //class_addProperty wants the attribute string split into name/value pairs, like `T` and `@"NSString"`
+            let attributes: Vec<(CString, CString)> = CStr::from_ptr(property.attributes).to_bytes().split(|b| *b == b',')
+                .map(|a| (CString::new(&a[..1]).unwrap(), CString::new(&a[1..]).unwrap())).collect();
+            let attributes: Vec<PropertyAttribute> = attributes.iter().map(|(name, value)| PropertyAttribute { name: name.as_ptr(), value: value.as_ptr() }).collect();
+            class_addProperty(class, property.name, attributes.as_ptr(), attributes.len() as u32);
+        }
+        objc_registerClassPair(class);
+        //Now that the runtime has laid out our ivars, tell our accessors where they live
+        for ivar in List::iter(class_ro.ivars) {
+            let runtime_ivar = class_getInstanceVariable(class, ivar.name);
+            let offset = &*(ivar.offset as *const super::_IvarOffset);
+            *offset.0.get() = ivar_getOffset(runtime_ivar) as u32;
+        }
+        if !cfg!(feature="mock-runtime") {
+            cache.store(class, Ordering::Release);
+        }
+        &*(class as *const AnyClass)
+    }
+}
+#[cfg(any(feature="gnustep", feature="mock-runtime"))]
+pub use dynamic::_register_subclass;
+#[cfg(feature="mock-runtime")]
+pub(crate) use dynamic::PropertyAttribute;
+
 #[macro_export]
 #[doc(hidden)]
 macro_rules! __objc_sublcass_implpart_method_prelude {
@@ -28,26 +340,69 @@ macro_rules! __objc_sublcass_implpart_method_prelude {
 #[macro_export]
 #[doc(hidden)]
 macro_rules! __objc_subclass_implpart_a {
-    ($pub:vis,$identifier:ident,$objcname:ident,$superclass:ident,
+    ($pub:vis,$identifier:ident,$objcname:ident,$superclass:ident,$root:ident,
     //these ivars are imported from external scope to achieve macro hygiene
     $CLASS_NAME:ident,
-    $NSSUPER_CLASS:ident,$OBJC_EMPTY_CACHE:ident) => {
+    $NSSUPER_CLASS:ident,$OBJC_EMPTY_CACHE:ident,
+    //class methods, or null
+    $METACLASSMETHODLISTEXPR:expr) => {
         objr::bindings::__mod!(subclass_impl_,$objcname, {
             #[repr(C)]
-            pub struct IvarListT {
-                //some dispute about whether this is the size of ivar_list_t,
-                //a magic number, or both.  In practice it's 32
-                pub magic: u32,
-                pub count: u32,
-                //todo: support multiple ivars.  For now, just inline the contents of an ivar, which are
-                //points to FRAGILE_BASE_CLASS_OFFSET
+            pub struct IvarT {
+                //points to `OBJC_IVAR_$_Class.ivar`
                 pub offset: *const u32,
                 pub name: *const u8,
                 pub r#type: *const u8,
+                //log2 of the alignment
                 pub alignment: u32,
                 pub size: u32
             }
+            #[repr(C)]
+            pub struct IvarListT<const SIZE: usize> {
+                //some dispute about whether this is the size of ivar_t,
+                //a magic number, or both.  In practice it's 32
+                pub magic: u32,
+                pub count: u32,
+                pub ivars: [IvarT; SIZE],
+            }
             use core::ffi::c_void;
+            //see protocol_t in objc-runtime-new.h
+            #[repr(C)]
+            pub struct ProtocolT {
+                //filled in by the runtime
+                pub isa: *const c_void,
+                pub mangled_name: *const u8,
+                pub protocols: *const c_void,
+                pub instance_methods: *const c_void,
+                pub class_methods: *const c_void,
+                pub optional_instance_methods: *const c_void,
+                pub optional_class_methods: *const c_void,
+                pub instance_properties: *const c_void,
+                //size of this struct
+                pub size: u32,
+                pub flags: u32,
+                pub extended_method_types: *const c_void,
+                pub demangled_name: *const u8,
+                pub class_properties: *const c_void,
+            }
+            #[repr(C)]
+            pub struct PropertyT {
+                pub name: *const u8,
+                pub attributes: *const u8,
+            }
+            #[repr(C)]
+            pub struct PropertyListT<const SIZE: usize> {
+                //size of PropertyT
+                pub entsize: u32,
+                pub count: u32,
+                pub properties: [PropertyT; SIZE],
+            }
+            #[repr(C)]
+            pub struct ProtocolListT<const SIZE: usize> {
+                //unlike the other lists, the count is pointer-sized
+                pub count: usize,
+                pub list: [*const ProtocolT; SIZE],
+            }
             //see https://opensource.apple.com/source/objc4/objc4-680/runtime/objc-runtime-new.h.auto.html
             #[repr(C)]
             pub struct ClassRoT {
@@ -61,7 +416,7 @@ macro_rules! __objc_subclass_implpart_a {
                 pub name: *const u8,
                 pub base_method_list: *const c_void, //MethodListT
                 pub base_protocols: *const c_void,
-                pub ivars: *const IvarListT,
+                pub ivars: *const c_void, //IvarListT
                 pub weak_ivar_layout: *const c_void,
                 pub base_properties: *const c_void,
             }
@@ -69,6 +424,10 @@ macro_rules! __objc_subclass_implpart_a {
             pub const RO_FLAGS_METACLASS: u32 = 1;
             pub const RO_FLAGS_HIDDEN:u32 = 1<<4;
             pub const RO_FLAGS_ARR:u32 = 1<<7;
+            //class has .cxx_construct and/or .cxx_destruct
+            pub const RO_HAS_CXX_STRUCTORS: u32 = 1<<2;
+            //class has .cxx_destruct but no .cxx_construct
+            pub const RO_HAS_CXX_DTOR_ONLY: u32 = 1<<8;
 
             pub const CLASS_FLAGS: u32 =RO_FLAGS_HIDDEN | RO_FLAGS_ARR;
 
@@ -88,11 +447,43 @@ macro_rules! __objc_subclass_implpart_a {
                 pub vtable: *const c_void,
                 pub ro: *const ClassRoT
             }
-            //And some external symbols (only relies on $superclass)
+            objr::bindings::__static_asciiz!("__TEXT,__objc_classname,cstring_literals",pub $CLASS_NAME,$objcname);
+
+            //declare metaclass RoT
+            objr::bindings::__static_expr!("__DATA,__objc_const", "_OBJC_METACLASS_RO_$_",$objcname,
+                pub static METACLASS_RO: objr::bindings::_SyncWrapper<ClassRoT> =
+                objr::bindings::_SyncWrapper(ClassRoT {
+                    flags: METACLASS_FLAGS,
+                    instance_start: 40,
+                    instance_size: 40,
+                    reserved:0,
+                    ivar_layout: std::ptr::null(),
+                    name: &CLASS_NAME as *const u8,
+                    base_method_list: $METACLASSMETHODLISTEXPR,
+                    base_protocols: std::ptr::null(),
+                    ivars: std::ptr::null(),
+                    weak_ivar_layout:std::ptr::null(),
+                    base_properties: std::ptr::null(),
+                });
+            );
+            objr::__objc_subclass_implpart_metaclass!($objcname,$superclass,$root,$NSSUPER_CLASS,$OBJC_EMPTY_CACHE);
+        });
+    }
+}
+
+///Declares the metaclass, and the external symbols it needs.
+#[cfg(not(any(feature="gnustep", feature="mock-runtime")))]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __objc_subclass_implpart_metaclass {
+    ($objcname:ident,$superclass:ident,$root:ident,$NSSUPER_CLASS:ident,$OBJC_EMPTY_CACHE:ident) => {
+            //And some external symbols (only relies on $superclass and $root)
             #[link(name="CoreFoundation",kind="framework")]
             extern {
-                #[link_name="OBJC_METACLASS_$_NSObject"]
-                pub static NSOBJECT_METACLASS: *const c_void;
+                //Every metaclass's isa is the root metaclass, usually NSObject's
+                objr::bindings::__static_extern!("OBJC_METACLASS_$_",$root,
+                    pub static ROOT_METACLASS: *const c_void;
+                );
 
                 //In addition to that, we likely want symbols for whatever
                 //our superclass is, if distinct
@@ -109,66 +500,53 @@ macro_rules! __objc_subclass_implpart_a {
                 #[link_name="_objc_empty_cache"]
                 pub static $OBJC_EMPTY_CACHE: *const c_void;
             }
-            objr::bindings::__static_asciiz!("__TEXT,__objc_classname,cstring_literals",pub $CLASS_NAME,$objcname);
-
-            //declare metaclass RoT
-            objr::bindings::__static_expr!("__DATA,__objc_const", "_OBJC_METACLASS_RO_$_",$objcname,
-                static METACLASS_RO: objr::bindings::_SyncWrapper<ClassRoT> =
-                objr::bindings::_SyncWrapper(ClassRoT {
-                    flags: METACLASS_FLAGS,
-                    instance_start: 40,
-                    instance_size: 40,
-                    reserved:0,
-                    ivar_layout: std::ptr::null(),
-                    name: &CLASS_NAME as *const u8,
-                    base_method_list: std::ptr::null(),
-                    base_protocols: std::ptr::null(),
-                    ivars: std::ptr::null(),
-                    weak_ivar_layout:std::ptr::null(),
-                    base_properties: std::ptr::null(),
-                });
-            );
 
             //metaclass instance can go in prelude
             objr::bindings::__static_expr!("__DATA,__objc_data", "OBJC_METACLASS_$_",$objcname,
                 pub static METACLASS: objr::bindings::_SyncWrapper<CLASST> = objr::bindings::_SyncWrapper(CLASST {
-                    isa: unsafe{ &NSOBJECT_METACLASS},
+                    isa: unsafe{ &ROOT_METACLASS},
                     superclass: unsafe{ &NSSUPER_METACLASS},
                     cache: unsafe{ &OBJC_EMPTY_CACHE},
                     vtable: std::ptr::null(),
                     ro: &METACLASS_RO.0
                 });
             );
-        });
     }
 }
 
+///On GNUstep (and the mock runtime), the metaclass is created by the runtime when we register the class.
+#[cfg(any(feature="gnustep", feature="mock-runtime"))]
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __objc_subclass_implpart_metaclass {
+    ($objcname:ident,$superclass:ident,$root:ident,$NSSUPER_CLASS:ident,$OBJC_EMPTY_CACHE:ident) => {}
+}
+
 #[macro_export]
 #[doc(hidden)]
 macro_rules! __objc_subclass_implpart_class_ro {
     ($objcname:ident,
-        $payload:ty,$CLASS_NAME:expr,$IVARLISTEXPR:expr,$METHODLISTEXPR:expr) => {
-        objr::bindings::__type_declr!(payload_for_implpart_class_ro_, $objcname, $payload);
+        $INSTANCESIZE:expr,$CLASS_NAME:expr,$IVARLISTEXPR:expr,$METHODLISTEXPR:expr,$PROTOCOLLISTEXPR:expr,$PROPERTYLISTEXPR:expr) => {
         objr::bindings::__mod!(class_ro_,$objcname, {
-            type Payload = objr::bindings::__concat_idents!("super::payload_for_implpart_class_ro_",$objcname);
             type ClassRoT = objr::bindings::__concat_3_idents!("super::subclass_impl_",$objcname,"::ClassRoT");
             objr::bindings::__static_expr!("__DATA,__objc_const", "_OBJC_CLASS_RO_$_",$objcname,
                 pub static CLASS_RO: objr::bindings::_SyncWrapper<ClassRoT> = objr::bindings::_SyncWrapper(ClassRoT {
-                    flags: objr::bindings::__concat_3_idents!("super::subclass_impl_",$objcname,"::CLASS_FLAGS"),
+                    flags: objr::bindings::__concat_3_idents!("super::subclass_impl_",$objcname,"::CLASS_FLAGS")
+                        | objr::bindings::__concat_3_idents!("super::implpart_method_list_",$objcname,"::STRUCTOR_FLAGS"),
                     //not sure where these come from
                     instance_start: 8,
-                    //8 plus whatever the size of our payload is
-                    instance_size: 8 + std::mem::size_of::<Payload>() as u32,
+                    //8 (for isa) plus our ivars
+                    instance_size: $INSTANCESIZE as u32,
                     reserved:0,
                     ivar_layout: std::ptr::null(),
                     name: &objr::bindings::__concat_3_idents!("super::subclass_impl_",$objcname,"::CLASS_NAME") as *const u8,
                     //In the case that we have methods, we want this to be the method list
                     base_method_list: $METHODLISTEXPR,
-                    base_protocols: std::ptr::null(),
+                    base_protocols: $PROTOCOLLISTEXPR,
                     //in the case that we have ivars, we need a ptr to ivar layout here
                     ivars: $IVARLISTEXPR,
                     weak_ivar_layout: std::ptr::null(),
-                    base_properties: std::ptr::null(),
+                    base_properties: $PROPERTYLISTEXPR,
                 });
             );
         });
@@ -176,134 +554,130 @@ macro_rules! __objc_subclass_implpart_class_ro {
     }
 }
 
-///Declares a method list
+///Declares the instance and class method lists, and the property list
 #[macro_export]
 #[doc(hidden)]
 macro_rules! __objc_subclass_implpart_method_list {
     (
-        $objcname:ident,
-        [$($objcmethod: literal, $methodfn: expr),+]
+        $identifier:ident,$objcname:ident,
+        [$($objcmethod: literal, $methodfn: expr),*],
+        [$($property:tt)*]
     ) => {
-        //method prelude
-
-                objr::bindings::__mod!(implpart_method_list_,$objcname, {
-                    //declare idents inside the prelude
-                    objr::__objc_sublcass_implpart_method_prelude!(MethodT,MethodListT);
-                    $(
-                        objr::bindings::__static_asciiz_ident_as_selector!("__TEXT,__objc_methname,cstring_literals","METHNAME_",$methodfn,$objcmethod);
-                        /*todo: The real objc compiler deduplicates these values across different functions.
-                        I'm unclear on exactly what the value of deduplicating this is.  From studying compiled binaries
-                        it appears that the *linker* also deduplicates local (`L`) symbols of this type, so I'm
-                        uncertain if deduplicating this at the compile phase has any effect really.
-
-                        Leaving this for now.
-                        */
-                        objr::bindings::__static_asciiz_ident_as_type_encoding!("__TEXT,__objc_methtype,cstring_literals","METHTYPE_",$methodfn,$objcmethod);
-                    )+
-
-                    const COUNT: usize = objr::bindings::__count!($($methodfn),*);
-                    objr::bindings::__static_expr!("__DATA,__objc_const","_OBJC_$_INSTANCE_METHODS_",$objcname,
-                        pub static METHOD_LIST: objr::bindings::_SyncWrapper<MethodListT<COUNT>> = objr::bindings::_SyncWrapper(
-                            MethodListT {
-                                magic: 24,
-                                count: COUNT as u32,
-                                methods: [
-                                    $(
-                                        MethodT {
-                                            name: & objr::bindings::__concat_idents!("METHNAME_",$methodfn) as *const u8,
-                                            types: & objr::bindings::__concat_idents!("METHTYPE_",$methodfn) as *const u8,
-                                            imp: objr::bindings::__concat_idents!("super::",$methodfn) as *const core::ffi::c_void
-                                        }
-                                    ),*
-                                ]
-
-                            }
-                        );
-                    );
-                });
+        objr::bindings::__objc_subclass_methods!($identifier, $objcname, [$($objcmethod, $methodfn),*], [$($property)*]);
+    }
+}
 
+///Declares the protocol list
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __objc_subclass_implpart_protocol_list {
+    ($objcname:ident, [$($protocol:ident),*]) => {
+        objr::bindings::__objc_subclass_protocols!($objcname, [$($protocol),*]);
     }
 }
-///Declares an ivarlist (e.g., payload variants)
+
+///Declares an ivarlist, the offset symbol for each ivar, and accessors on the wrapper type.
+///
+/// A payload is declared as a single ivar named `payload`.
 #[macro_export]
 #[doc(hidden)]
 macro_rules! __objc_subclass_implpart_ivar_list {
-    ($objcname: ident, $payloadtype:ty) => {
-        objr::bindings::__type_declr!(payload_,$objcname,$payloadtype);
-
-        objr::bindings::__mod!(ivar_list_, $objcname, {
-            type Payload = objr::bindings::__concat_idents!("super::payload_",$objcname);
-            objr::bindings::__static_asciiz!("__TEXT,__objc_methname,cstring_literals",IVAR_NAME,"payload");
-            //don't explain to objc what type this is
-            objr::bindings::__static_asciiz!("__TEXT,__objc_methtype,cstring_literals",IVAR_TYPE,"?");
-
-            //This symbol seems involved in solving the fragile base class problem.
-            //I am told that if the superclass changes its layout, this type.
-            //will be updated to point to the new layout.
-            //By default, we put this to 8 since we think our type starts at position 8
-            //into the object?
-            objr::bindings::__static_expr3!("__DATA,__objc_ivar", "OBJC_IVAR_$_",$objcname,".payload",
-            pub static FRAGILE_BASE_CLASS_OFFSET: u32 = 8;
-            );
-            type IvarListT = objr::bindings::__concat_3_idents!("super::subclass_impl_",$objcname,"::IvarListT");
-            objr::bindings::__static_expr!("__DATA,__objc_const", "_OBJC_INSTANCE_VARIABLES_",$objcname,
-                pub static IVAR_LIST: objr::bindings::_SyncWrapper<IvarListT> = objr::bindings::_SyncWrapper(
-                    IvarListT {
-                        magic: 32,
-                        count: 1,
-                        offset: &FRAGILE_BASE_CLASS_OFFSET,
-                        name: &IVAR_NAME as *const u8,
-                    r#type: &IVAR_TYPE as *const u8,
-                    alignment: std::mem::align_of::<Payload>() as u32,
-                    size: std::mem::size_of::<Payload>() as u32,
-                    }
-                );
-            );
-        });
+    ($pub:vis, $identifier:ident, $objcname:ident, { $($ivar:ident : $ivarty:ty),* }) => {
+        objr::bindings::__objc_subclass_ivars!($pub, $identifier, $objcname, { $($ivar : $ivarty),* });
+    }
+}
 
+///Declares the `.cxx_construct` and `.cxx_destruct` implementations for a `payload: T`.
+///
+/// The runtime calls these when the object is allocated and deallocated, the same way it runs the C++ constructors and destructors
+/// of an ObjC++ class's ivars.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __objc_subclass_implpart_payload_structors {
+    ($identifier:ident, $ivar_mut:ident, $payload:ty) => {
+        impl $identifier {
+            #[doc(hidden)]
+            extern "C" fn _objr_cxx_construct(objc_self: &Self, _sel: objr::bindings::Sel) -> *const Self {
+                //the memory is zeroed rather than a valid payload, so don't drop it
+                unsafe{ std::ptr::write(objc_self.$ivar_mut(), <$payload as Default>::default()) };
+                objc_self
+            }
+            #[doc(hidden)]
+            extern "C" fn _objr_cxx_destruct(objc_self: &Self, _sel: objr::bindings::Sel) {
+                unsafe{ std::ptr::drop_in_place(objc_self.$ivar_mut() as *mut $payload) }
+            }
+        }
     }
 }
-///This macro implements some methods on the wrapper type
-///to access the underlying payload.
+
+///Declares the accessors for a `payload: uninitialized T`, which is stored in a `_PayloadCell` ivar named `_payload`.
 #[macro_export]
 #[doc(hidden)]
-macro_rules! __objc_subclass_impl_payload_access {
-    ($pub:vis, $identifier:ident,$payload:ty,$objcname:ident) => {
+macro_rules! __objc_subclass_implpart_payload_init {
+    ($pub:vis, $identifier:ident, $payload:ty) => {
         impl $identifier {
-            /// Gets a mutable reference to the underlying payload.
+            ///Initializes the payload.  Call this once, typically from your `init` method.
+            ///
+            /// Initializing the payload twice is a bug.  Debug builds panic; release builds keep the first value and drop `value`.
+            #[allow(dead_code)]
+            $pub fn init_payload(&self, value: $payload) {
+                let result = self._payload().set(value);
+                debug_assert!(result.is_ok(), "{} payload was initialized twice", stringify!($identifier));
+            }
+            ///Gets the payload, or `None` if [Self::init_payload()] has not been called.
+            #[allow(dead_code)]
+            $pub fn try_payload(&self) -> Option<&$payload> {
+                self._payload().get()
+            }
+            ///Gets the payload.
+            ///
+            /// # Panics
+            /// If [Self::init_payload()] has not been called.
+            #[allow(dead_code)]
+            $pub fn payload(&self) -> &$payload {
+                self.try_payload().unwrap_or_else(|| panic!("{} payload was used before init_payload", stringify!($identifier)))
+            }
+            ///Gets a mutable reference to the payload.
             ///
             /// # Safety
             /// You must guarantee you are called from an exclusive, mutable context.
             ///
-            /// # Design
-            /// Similar to `UnsafeCell`, but
-            /// 1.  Difficult to initialize a cell here
-            /// 2.  I'm not sure if `UnsafeCell` is FFI-safe
-            /// 3.  In practice, you need to initialize the objc memory close to 100% of the time to avoid UB.
+            /// # Panics
+            /// If [Self::init_payload()] has not been called.
             #[allow(dead_code)]
             $pub unsafe fn payload_mut(&self) -> &mut $payload {
-                //convert to u8 to get byte offset
-                let self_addr = (self as *const _ as *const u8);
-                //offset by FRAGILE_BASE_CLASS
-                //Note that a real objc compiler will optimize `FRAGILE_BASE_CLASS_OFFSET` to 8
-                //when the superclass is known to be `NSObject` (e.g. the class is not fragile).
-                //I am skipping that optimization for now.
-                //todo: Maybe optimize this further
-
-                //Note that we need to read_volatile here to get the real runtime payload,
-                //not the payload known at compile time
-                let payload_addr = self_addr.offset(std::ptr::read_volatile(&objr::bindings::__concat_3_idents!("ivar_list_",$objcname,"::FRAGILE_BASE_CLASS_OFFSET")) as isize);
-
-                let payload_typed_addr =std::mem::transmute(payload_addr);
-                payload_typed_addr
-            }
-            #[allow(dead_code)]
-            $pub fn payload(&self) -> &$payload {
-                unsafe { self.payload_mut() } //coerce to non-mut
+                self._payload_mut().get_mut().unwrap_or_else(|| panic!("{} payload was used before init_payload", stringify!($identifier)))
             }
         }
     }
 }
+
+///Expression for a pointer to one of our lists, or null if the list is empty (as clang does).
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __objc_subclass_implpart_list_ptr {
+    ($objcname:ident, INSTANCE) => {
+        if objr::bindings::__concat_3_idents!("super::implpart_method_list_",$objcname,"::INSTANCE_METHOD_COUNT") == 0 { std::ptr::null() }
+        else { &objr::bindings::__concat_3_idents!("super::implpart_method_list_",$objcname,"::INSTANCE_METHOD_LIST").0 as *const _ as *const core::ffi::c_void }
+    };
+    ($objcname:ident, CLASS) => {
+        if objr::bindings::__concat_3_idents!("super::implpart_method_list_",$objcname,"::CLASS_METHOD_COUNT") == 0 { std::ptr::null() }
+        else { &objr::bindings::__concat_3_idents!("super::implpart_method_list_",$objcname,"::CLASS_METHOD_LIST").0 as *const _ as *const core::ffi::c_void }
+    };
+    ($objcname:ident, PROTOCOL) => {
+        if objr::bindings::__concat_3_idents!("super::protocol_list_",$objcname,"::PROTOCOL_COUNT") == 0 { std::ptr::null() }
+        else { &objr::bindings::__concat_3_idents!("super::protocol_list_",$objcname,"::PROTOCOL_LIST").0 as *const _ as *const core::ffi::c_void }
+    };
+    ($objcname:ident, IVAR) => {
+        if objr::bindings::__concat_3_idents!("super::ivar_list_",$objcname,"::IVAR_COUNT") == 0 { std::ptr::null() }
+        else { &objr::bindings::__concat_3_idents!("super::ivar_list_",$objcname,"::IVAR_LIST").0 as *const _ as *const core::ffi::c_void }
+    };
+    ($objcname:ident, PROPERTY) => {
+        if objr::bindings::__concat_3_idents!("super::property_list_",$objcname,"::PROPERTY_COUNT") == 0 { std::ptr::null() }
+        else { &objr::bindings::__concat_3_idents!("super::property_list_",$objcname,"::PROPERTY_LIST").0 as *const _ as *const core::ffi::c_void }
+    };
+}
+#[cfg(not(any(feature="gnustep", feature="mock-runtime")))]
 #[macro_export]
 #[doc(hidden)]
 macro_rules! __objc_subclass_implpart_finalize {
@@ -344,94 +718,69 @@ macro_rules! __objc_subclass_implpart_finalize {
     }
 }
 
-///Emits the subclass impl in the case have a payload
-#[macro_export]
-#[doc(hidden)]
-macro_rules! __objc_subclass_impl_with_payload_no_methods {
-    (
-    $pub:vis,$identifier:ident,$objcname:ident,$superclass:ident,$payload:ty
-    ) => {
-        objr::__objc_subclass_implpart_a!($pub,$identifier,$objcname,$superclass,
-        //declare these identifiers into our local scope
-        CLASS_NAME,NSSUPER_CLASS,OBJC_EMPTY_CACHE);
-        //payload variant requires an ivar list
-        objr::__objc_subclass_implpart_ivar_list!($objcname,$payload);
-
-        objr::__objc_subclass_implpart_class_ro!($objcname,$payload,CLASS_NAME,&(objr::bindings::__concat_3_idents!("super::ivar_list_",$objcname,"::IVAR_LIST")).0,
-            std::ptr::null() //Since we have no methods, we pass null for METHODLISTEXPR
-        );
-        objr::__objc_subclass_implpart_finalize!($pub,$identifier,$objcname,$superclass,NSSUPER_CLASS,OBJC_EMPTY_CACHE);
-        objr::__objc_subclass_impl_payload_access!($pub,$identifier,$payload,$objcname);
-
-    }
-}
-#[macro_export]
-#[doc(hidden)]
-macro_rules! __objc_subclass_impl_no_payload_no_methods {
-    ($pub:vis,$identifier:ident,$objcname:ident,$superclass:ident) => {
-                objr::__objc_subclass_implpart_a!($pub,$identifier,$objcname,$superclass,
-        //declare these identifiers into our local scope
-        CLASS_NAME,NSSUPER_CLASS,OBJC_EMPTY_CACHE);
-
-                objr::__objc_subclass_implpart_class_ro!($objcname,
-                (), //for the no-payload case, use an empty type
-                CLASS_NAME,
-                //IVAREXPRESSION: use the null pointer since we have no payload
-                    std::ptr::null(),
-                //METHLISTEXPRESSION: Use the null pointer since we have no methods
-                    std::ptr::null()
-                );
-                objr::__objc_subclass_implpart_finalize!($pub,$identifier,$objcname,$superclass,NSSUPER_CLASS,OBJC_EMPTY_CACHE);
-    }
-}
-
+///libobjc2 (and the mock runtime) don't read classes out of Mach-O sections, so instead we register
+/// our class metadata with the runtime the first time the class is used.
+#[cfg(any(feature="gnustep", feature="mock-runtime"))]
 #[macro_export]
 #[doc(hidden)]
-macro_rules! __objc_subclass_impl_no_payload_with_methods {
+macro_rules! __objc_subclass_implpart_finalize {
     ($pub:vis,$identifier:ident,$objcname:ident,$superclass:ident,
-    [ $($objcmethod:literal => $methodfn:expr $(,)* )+ ]
+    //these are imported into our scope
+        $NSSUPER_CLASS:expr,$OBJC_EMPTY_CACHE:expr
     ) => {
+        objr::bindings::__mod!(subclass_finalize_,$objcname, {
+            pub static CLASS: core::sync::atomic::AtomicPtr<core::ffi::c_void> = core::sync::atomic::AtomicPtr::new(core::ptr::null_mut());
+        });
 
-                objr::__objc_subclass_implpart_a!($pub,$identifier,$objcname,$superclass,
-                //declare these identifiers into our local scope
-                CLASS_NAME,NSSUPER_CLASS,OBJC_EMPTY_CACHE);
-
-                objr::__objc_subclass_implpart_method_list!( $objcname, [$($objcmethod, $methodfn),*]);
-
-                objr::__objc_subclass_implpart_class_ro!($objcname,
-                (), //for the no-payload case, use an empty type
-                CLASS_NAME,
-                //use the null pointer for our ivar expression since we have no payload
-                    std::ptr::null(),
-                //transmute our method_list into c_void
-                    unsafe{ std::mem::transmute(&(objr::bindings::__concat_3_idents!("super::implpart_method_list_",$objcname,"::METHOD_LIST")).0) }
-                );
-                objr::__objc_subclass_implpart_finalize!($pub,$identifier,$objcname,$superclass,NSSUPER_CLASS,OBJC_EMPTY_CACHE);
+        objr::bindings::objc_instance! {
+            pub struct $identifier;
+        }
+        impl objr::bindings::ObjcClass for $identifier {
+            #[inline] fn class() -> &'static ::objr::bindings::Class<Self> {
+                unsafe {
+                    let class = objr::bindings::_register_subclass(&objr::bindings::__concat_3_idents!("subclass_finalize_",$objcname,"::CLASS"),
+                        concat!(stringify!($superclass),"\0").as_bytes(),
+                        &objr::bindings::__concat_3_idents!("class_ro_",$objcname,"::CLASS_RO").0 as *const _ as *const core::ffi::c_void,
+                        &objr::bindings::__concat_3_idents!("subclass_impl_",$objcname,"::METACLASS_RO").0 as *const _ as *const core::ffi::c_void,
+                    );
+                    &*(class as *const ::objr::bindings::AnyClass as *const ::objr::bindings::Class<Self>)
+                }
+            }
+        }
     }
 }
 
-///Variant with payload and methods
+///Emits the subclass impl.  A payload is passed as an ivar named `payload`, and `payload: ()` as no ivars.
 #[macro_export]
 #[doc(hidden)]
-
-macro_rules! __objc_subclass_impl_with_payload_with_methods {
-($pub: vis, $identifier:ident,$objcname:ident,$superclass:ident,$payload:ty, [$($objcmethod:literal => $methodfn:expr $(,)* )+ ]) =>
-    {
-        objr::__objc_subclass_implpart_a!($pub,$identifier,$objcname,$superclass,
-                //declare these identifiers into our local scope
-                CLASS_NAME,NSSUPER_CLASS,OBJC_EMPTY_CACHE);
-        //variant with payload
-        objr::__objc_subclass_implpart_ivar_list!($objcname,$payload);
-        //variant with methods
-        objr::__objc_subclass_implpart_method_list!( $objcname, [$($objcmethod, $methodfn),* ]);
+macro_rules! __objc_subclass_impl {
+    //classes are rooted in NSObject unless we're told otherwise
+    ($pub:vis,$identifier:ident,$objcname:ident,$superclass:ident,[],$($rest:tt)*) => {
+        objr::__objc_subclass_impl!($pub,$identifier,$objcname,$superclass,[NSObject],$($rest)*);
+    };
+    ($pub:vis,$identifier:ident,$objcname:ident,$superclass:ident,[$root:ident],
+    [$($protocol:ident),*],
+    { $($ivar:ident : $ivarty:ty),* },
+    [$($property:tt)*],
+    [$($objcmethod:literal => $methodfn:expr),*]
+    ) => {
+        objr::__objc_subclass_implpart_a!($pub,$identifier,$objcname,$superclass,$root,
+            //declare these identifiers into our local scope
+            CLASS_NAME,NSSUPER_CLASS,OBJC_EMPTY_CACHE,
+            objr::__objc_subclass_implpart_list_ptr!($objcname, CLASS));
+        objr::__objc_subclass_implpart_protocol_list!($objcname, [$($protocol),*]);
+        objr::__objc_subclass_implpart_ivar_list!($pub,$identifier,$objcname,{ $($ivar : $ivarty),* });
+        objr::__objc_subclass_implpart_method_list!($identifier,$objcname, [$($objcmethod, $methodfn),*], [$($property)*]);
         objr::__objc_subclass_implpart_class_ro!($objcname,
-        $payload,
-        CLASS_NAME,
-        unsafe {std::mem::transmute(&(objr::bindings::__concat_3_idents!("super::ivar_list_",$objcname,"::IVAR_LIST")).0)},
-        unsafe{ std::mem::transmute(&objr::bindings::__concat_3_idents!("super::implpart_method_list_",$objcname,"::METHOD_LIST").0) }
+            //8 (for isa) plus our ivars
+            objr::bindings::__concat_3_idents!("super::ivar_list_",$objcname,"::INSTANCE_SIZE"),
+            CLASS_NAME,
+            objr::__objc_subclass_implpart_list_ptr!($objcname, IVAR),
+            objr::__objc_subclass_implpart_list_ptr!($objcname, INSTANCE),
+            objr::__objc_subclass_implpart_list_ptr!($objcname, PROTOCOL),
+            objr::__objc_subclass_implpart_list_ptr!($objcname, PROPERTY)
         );
         objr::__objc_subclass_implpart_finalize!($pub,$identifier,$objcname,$superclass,NSSUPER_CLASS,OBJC_EMPTY_CACHE);
-        objr::__objc_subclass_impl_payload_access!($pub, $identifier,$payload,$objcname);
     }
 }
 
@@ -467,15 +816,19 @@ macro_rules! __objc_subclass_impl_with_payload_with_methods {
 /// Where the left part is an ObjC declaration and the right part is a Rust function.  Couple of notes:
 ///
 /// 1.  Rust function must be `extern "C"`.  Failing to do this is UB.
-/// 2.  The first two arguments to the Rust function are the pointer to Self, and the selector.
+/// 2.  The first two arguments to the Rust function are the pointer to Self (or the class, for `+` methods), and the selector.
 ///     (arguments that are repr-transparent to these are OK as well).
 /// 3.  All arguments and return values must be FFI-safe.
+/// 4.  The Rust function must take the declared number of arguments, and where the macro knows the size of a declared type,
+///     the Rust argument or return type must have that size.  Otherwise, compilation fails with an error like
+///     ``objc_subclass!: the return type of `-(id) init` is declared as `id` (8 bytes), but the Rust function's is a different size``.
+///     Rust types named in the declaration (see [Structs](#structs)) aren't checked.
 ///
 /// Here's a simple example
 /// ```
 /// # fn main() {} //error[E0433]: failed to resolve: could not find `subclass_impl_Example` in the crate root
 /// use objr::bindings::*;
-/// extern "C" fn example(objcSelf: Example, //repr-transparent to the pointer type
+/// extern "C" fn example(objcSelf: &Example, //a reference has the layout of the object pointer
 ///                     sel: Sel) {
 ///     println!("Hello from rustdoc!");
 /// }
@@ -510,7 +863,8 @@ macro_rules! __objc_subclass_impl_with_payload_with_methods {
 /// selector and uses special runtime flags to indicate this selector should be called.  This allows
 /// compiler synthesis to co-exist with a user-written `deinit`.
 ///
-/// This is not currently supported by the macro but may be added in the future.
+/// The macro does the same for [`payload: T`](#t) payloads, so you don't need a `dealloc` just to drop the payload.
+/// You may also declare `.cxx_construct` or `.cxx_destruct` methods yourself, and the runtime flags will be set accordingly.
 ///
 /// ## Arguments
 /// The first argument to your C function is a pointer to `self`, and the second argument is a selector-pointer.
@@ -522,54 +876,273 @@ macro_rules! __objc_subclass_impl_with_payload_with_methods {
 ///
 /// For the selector argument, typically you use `Sel`.  `*const c_void` and `*const c_char` are also allowed.
 ///
-/// # Payloads
-/// Your ObjC type may have its own storage, inside the object.  This obviates the need
-/// to allocate any external storage or somehow map between Rust and ObjC memory.
-///
-/// Currently, a single field is supported.  However, this field can be a Rust struct.
-/// Payloads may also be 0-sized, for example `()` may be used.
-///
-/// To specify a payload, you use one of the following "payload specifiers"
-///
-/// ## `()`
-/// Indicates a zero-sized payload.
-///
-/// Note that there is a subtle difference between using the tokens `()` and specifying a payload of 0-size (ex, `unsafe ininitialized nondrop ()`).
-/// In the former case, we emit no payload to objc.  In the latter case, we emit storage of 0 size.  The `()` syntax is preferred.
-///
-/// ## `unsafe uninitialized nondrop T`
-///
-/// Storage for type T will be created.  This is
-/// * uninitialized.  It is UB to read this before initialization.  Presumably, you need to write an objc `init` method and ensure it is called.
-///   If you somehow read this memory without initialization, this is UB.
-/// * nondrop.  Drop will never be called on this type
-/// * `unsafe`, no memory management is performed.
-///
+/// ## Structs
+/// Declarations may use common structs from the system frameworks, like `NSRange`, `CGPoint`, `CGRect`,
+/// `CGAffineTransform`, `NSEdgeInsets` or `MTLClearColor`, as well as structs and unions declared inline in C syntax,
+/// like `struct Flags { unsigned int a : 1; char name[8]; }`.  The Rust function takes a `#[repr(C)]` type
+/// with the same layout.
 ///
+/// Any other type name in a declaration is taken to be a Rust type in scope that implements [objr::bindings::Encode], usually with
+/// `#[derive(ObjcEncode)]`.  Its encoding and size are worked out at compile time.
 /// ```
 /// # fn main() {} //error[E0433]: failed to resolve: could not find `subclass_impl_Example` in the crate root
 /// use objr::bindings::*;
+/// #[derive(ObjcEncode)]
+/// #[repr(C)]
+/// pub struct Span {
+///     start: u64,
+///     length: u64,
+/// }
+/// extern "C" fn set_span(objc_self: &Example, sel: Sel, span: Span) { }
 /// objc_subclass! {
-///     //Declare a Rust type named `Example`, which maps to the underlying objc class
 ///     pub struct Example {
-///         //In the ObjC runtime, our type will be named `Example`
 ///         @class(Example)
-///         //And will have `NSNull` as its superclass
-///         @superclass(NSNull)
-///         //The following storage will be allocated.  See the payload section.
-///         payload: unsafe uninitialized nondrop u8,
-///         methods: ["-(id) init" => unsafe init]
+///         @superclass(NSObject)
+///         payload: (),
+///         methods: [ "-(void) setSpan:(Span) span" => unsafe set_span ]
 ///     }
 /// }
+/// ```
+///
+/// ## Class methods
+/// Declarations beginning with `+` are class methods.  They go in the metaclass's method list, so they can
+/// be used for factory methods, or for overriding class methods like `+layerClass`.
+///
+/// The first argument to a class method is the class object rather than an instance.  Typically you use
+/// `&Class<Example>` (or `&AnyClass`, or `*const c_void`).
+/// ```
+/// # fn main() {} //error[E0433]: failed to resolve: could not find `subclass_impl_Example` in the crate root
+/// use objr::bindings::*;
+/// extern "C" fn shared_count(class: &Class<Example>, sel: Sel) -> u64 {
+///     42
+/// }
+/// objc_subclass! {
+///     pub struct Example {
+///         @class(Example)
+///         @superclass(NSObject)
+///         payload: (),
+///         methods: [ "+(unsigned long long) sharedCount" => unsafe shared_count ]
+///     }
+/// }
+/// ```
+///
+/// `+initialize` is sent by the runtime before the first message to the class, as usual.  If you declare `+load`, on Apple
+/// platforms the class is also emitted into the non-lazy class list so the runtime calls it at launch.  With the `gnustep`
+/// and `mock-runtime` features, classes are registered on first use, so `+load` is not guaranteed to be called; prefer `+initialize`.
+///
+/// # Protocols
+/// Many Cocoa APIs check `conformsToProtocol:` before calling a delegate.  To declare conformance, list the protocols after the superclass:
+/// ```
+/// # fn main() {} //error[E0433]: failed to resolve: could not find `subclass_impl_Example` in the crate root
+/// use objr::bindings::*;
+/// objc_subclass! {
+///     pub struct Example {
+///         @class(Example)
+///         @superclass(NSObject)
+///         @protocols(NSCopying, NSApplicationDelegate)
+///         payload: (),
+///         methods: []
+///     }
+/// }
+/// ```
+///
+/// This only declares conformance; it's up to you to implement the protocol's required methods.
+/// You can check conformance with [NSObjectTrait::conforms_to_protocol()] or [Class::conforms_to_protocol()].
+///
+/// # Root classes
+/// On Apple platforms, the macro emits the metaclass itself, and the metaclass's `isa` must point at the metaclass of the
+/// class hierarchy's root.  This is `NSObject` unless you say otherwise.  To subclass a class with a different root, like `NSProxy`,
+/// name the root after the superclass:
+/// ```
+/// # fn main() {} //error[E0433]: failed to resolve: could not find `subclass_impl_Example` in the crate root
+/// use objr::bindings::*;
+/// objc_subclass! {
+///     pub struct Example {
+///         @class(Example)
+///         @superclass(NSProxy)
+///         @root(NSProxy)
+///         payload: (),
+///         methods: [
+///             "-(void) forwardInvocation:(NSInvocation *) invocation" => unsafe forward_invocation,
+///             "-(id) methodSignatureForSelector:(SEL) sel" => unsafe method_signature
+///         ]
+///     }
+/// }
+/// # extern "C" fn forward_invocation(objc_self: &Example, sel: Sel, invocation: *const core::ffi::c_void) {}
+/// # extern "C" fn method_signature(objc_self: &Example, sel: Sel, for_sel: Sel) -> *const core::ffi::c_void { std::ptr::null() }
+/// ```
+///
+/// Getting the root wrong is UB when sending class messages.  With the `gnustep` and `mock-runtime` features,
+/// the runtime creates the metaclass for us, so `@root` has no effect.
+///
+/// # Properties
+/// Bindings (`valueForKey:`), KVO, and Interface Builder find properties through the class's property list.  To declare
+/// properties, add a `properties:` block before `methods:`, using ObjC syntax:
+/// ```
+/// # fn main() {} //error[E0433]: failed to resolve: could not find `subclass_impl_Example` in the crate root
+/// use objr::bindings::*;
+/// objc_subclass! {
+///     pub struct Example {
+///         @class(Example)
+///         @superclass(NSObject)
+///         ivars: { _title: Option<StrongCell<NSString>>, _count: u32 },
+///         properties: [
+///             @property(nonatomic, copy) NSString *title;
+///             @synthesize title = _title;
+///             @property(nonatomic) unsigned int count;
+///             @synthesize count = _count;
+///             //declared, but implemented in `methods:`
+///             @property(readonly) NSString *summary;
+///         ],
+///         methods: [ "-(id) summary" => unsafe summary ]
+///     }
+/// }
+/// # extern "C" fn summary(objc_self: &Example, sel: Sel) -> *const NSString { std::ptr::null() }
+/// ```
+///
+/// Each `@property` is emitted into the property list with the same attribute string clang would emit (e.g. `T@"NSString",C,N,V_title`).
+/// The supported attributes are `readonly`/`readwrite`, `nonatomic`/`atomic`, `strong`/`retain`, `copy`, `weak`, `assign`,
+/// `getter=` and `setter=`.  Object properties default to `strong`, as under ARC.
+///
+/// `@synthesize name = ivar;` (or `@synthesize name;` for an ivar of the same name) generates the getter, and unless the property
+/// is `readonly`, the setter, and adds them to the method list.
+/// * Object properties must be backed by a pointer-sized ivar, such as `Option<StrongCell<T>>`.  The accessors go through
+///   `objc_getProperty`/`objc_setProperty`, so `strong`, `copy` and `atomic` behave as they do in ObjC.  As in ObjC, a `strong` or
+///   `copy` ivar is released when the object is deallocated.
+/// * Other properties read and write the ivar directly.
+/// * `weak` properties can't be synthesized.
+///
+/// Properties without `@synthesize` are only declared, so you must implement the accessors yourself in `methods:`.
+///
+/// # Payloads
+/// Your ObjC type may have its own storage, inside the object.  This obviates the need
+/// to allocate any external storage or somehow map between Rust and ObjC memory.
+///
+/// A payload is a single field, although this field can be a Rust struct.  To declare several named
+/// fields that ObjC can see, use [ivars](#ivars) instead.
+/// Payloads may also be 0-sized, for example `()` may be used.
+///
+/// To specify a payload, you use one of the following "payload specifiers"
+///
+/// ## `()`
+/// Indicates a zero-sized payload.
+///
+/// Note that there is a subtle difference between using the tokens `()` and specifying a payload of 0-size (ex, `unsafe ininitialized nondrop ()`).
+/// In the former case, we emit no payload to objc.  In the latter case, we emit storage of 0 size.  The `()` syntax is preferred.
+///
+/// ## `T`
+/// Storage for type T will be created, which is managed for you:
+/// * When the object is allocated, the payload is initialized with `T::default()`, so `T` must implement [Default].
+///   Your `init` may replace it with `*payload_mut() = value`.
+/// * When the object is deallocated, the payload is dropped, after any `dealloc` you write.
+///
+/// This works like C++ ivars in ObjC++: the macro emits `.cxx_construct` and `.cxx_destruct` methods, and sets the flag
+/// that tells the runtime to call them.
+///
+/// ```
+/// # fn main() {} //error[E0433]: failed to resolve: could not find `subclass_impl_Example` in the crate root
+/// use objr::bindings::*;
+/// #[derive(Default)]
+/// struct Payload {
+///     names: Vec<String>,
+/// }
+/// objc_subclass! {
+///     pub struct Example {
+///         @class(Example)
+///         @superclass(NSObject)
+///         payload: Payload,
+///         methods: []
+///     }
+/// }
+/// ```
+///
+/// ## `uninitialized T`
+/// Like `T`, but for payloads that can't have a default value, so your `init` must supply one.  The payload
+/// starts out uninitialized, and you initialize it once with `init_payload(value)`.  It is dropped at dealloc if it was initialized.
+///
+/// * `payload()` returns the payload, and panics if it hasn't been initialized.  `try_payload()` returns an `Option` instead.
+/// * `unsafe payload_mut()` works as for the other payloads, and also panics if the payload hasn't been initialized.
+/// * Calling `init_payload` a second time is a bug.  In debug builds it panics; in release builds the second value is dropped.
+///
+/// ```
+/// # fn main() {} //error[E0433]: failed to resolve: could not find `subclass_impl_Example` in the crate root
+/// use objr::bindings::*;
+/// objc_subclass! {
+///     pub struct Example {
+///         @class(Example)
+///         @superclass(NSObject)
+///         payload: uninitialized std::fs::File,
+///         methods: ["-(id) init" => unsafe init]
+///     }
+/// }
+/// extern "C" fn init(objc_self: &Example, sel: Sel) -> *const Example {
+///     let new_self: &Example = unsafe{ &*Example::perform_super(objc_self.assume_nonmut_perform(), Sel::init(), &ActiveAutoreleasePool::assume_autoreleasepool(), ())};
+///     new_self.init_payload(std::fs::File::open("/dev/null").unwrap());
+///     new_self
+/// }
+/// ```
+///
+/// ## `unsafe uninitialized nondrop T`
+///
+/// Storage for type T will be created.  This is
+/// * uninitialized.  It is UB to read this before initialization.  Presumably, you need to write an objc `init` method and ensure it is called.
+///   If you somehow read this memory without initialization, this is UB.
+/// * nondrop.  Drop will never be called on this type
+/// * `unsafe`, no memory management is performed.
+///
+///
+/// ```
+/// # fn main() {} //error[E0433]: failed to resolve: could not find `subclass_impl_Example` in the crate root
+/// use objr::bindings::*;
+/// objc_subclass! {
+///     //Declare a Rust type named `Example`, which maps to the underlying objc class
+///     pub struct Example {
+///         //In the ObjC runtime, our type will be named `Example`
+///         @class(Example)
+///         //And will have `NSNull` as its superclass
+///         @superclass(NSNull)
+///         //The following storage will be allocated.  See the payload section.
+///         payload: unsafe uninitialized nondrop u8,
+///         methods: ["-(id) init" => unsafe init]
+///     }
+/// }
+///
+///     extern "C" fn init(objcSelf: *mut Example, sel: Sel) -> *const Example {
+///         let new_self: &Example = unsafe{ &*(Example::perform_super(objcSelf,  Sel::init(), &ActiveAutoreleasePool::assume_autoreleasepool(), ()))};
+///         //initialize the payload to 5
+///         *(unsafe{new_self.payload_mut()}) = 5;
+///         //return self per objc convention
+///         new_self
+///     }
+///```
+/// ## `ivars`
+///
+/// Instead of `payload:`, you may declare a list of named ivars:
+///
+/// ```
+/// # fn main() {} //error[E0433]: failed to resolve: could not find `subclass_impl_Example` in the crate root
+/// use objr::bindings::*;
+/// objc_subclass! {
+///     pub struct Example {
+///         @class(Example)
+///         @superclass(NSObject)
+///         ivars: { count: u32, delegate: StrongCell<NSObject> },
+///         methods: []
+///     }
+/// }
+/// ```
+///
+/// Each ivar is emitted into the class's ivar list with its name and a type encoding, and gets its own `OBJC_IVAR_$_Example.count`
+/// offset symbol, just like a clang-compiled class.  This means the debugger, `class_copyIvarList` and KVC (`valueForKey:`) can see them.
+///
+/// For each ivar we generate an accessor pair on the wrapper type, `count()` and `unsafe count_mut()`, which work like
+/// `payload()` and `payload_mut()`.
+///
+/// Type encodings come from [Encode](crate::bindings::Encode), and types that don't implement it are encoded as `?`.  Like clang, ivars holding
+/// an object in a cell, like `StrongCell<NSObject>` or `Option<StrongCell<NSObject>>`, name its class: `@"NSObject"`.
+///
+/// Ivars have the same rules as `unsafe uninitialized nondrop` payloads: they are not initialized or dropped for you.
 ///
-///     extern "C" fn init(objcSelf: *mut Example, sel: Sel) -> *const Example {
-///         let new_self: &Example = unsafe{ &*(Example::perform_super(objcSelf,  Sel::init(), &ActiveAutoreleasePool::assume_autoreleasepool(), ()))};
-///         //initialize the payload to 5
-///         *(unsafe{new_self.payload_mut()}) = 5;
-///         //return self per objc convention
-///         new_self
-///     }
-///```
 /// ### Payload memory management
 /// One thing to keep in mind is that in general, memory management is significantly
 /// different in ObjC and most Rust patterns simply do not work.
@@ -594,7 +1167,7 @@ macro_rules! __objc_subclass_impl_with_payload_with_methods {
 ///     4.  I'm not gonna be the safety police and tell you not to use raw pointers,
 ///         but you are on your own as far as the unbounded lifetimes of ObjC objects.
 ///
-/// Keep in mind that for several of these, you need to implement your own dealloc that calls drop.
+/// Keep in mind that for several of these, you need to implement your own dealloc that calls drop, unless you use a [`payload: T`](#t).
 ///
 /// ### Coda on init
 ///
@@ -610,54 +1183,93 @@ macro_rules! __objc_subclass_impl_with_payload_with_methods {
 ///
 /// The alternative is to write into your payload_mut with [std::ptr::write], which does not drop the uninitialized value.
 ///
+/// All of this is avoided by the [`T`](#t) and [`uninitialized T`](#uninitialized-t) payloads, which are always in a valid state.
+///
 #[macro_export]
 macro_rules! objc_subclass {
     (
         $pub:vis struct $identifier:ident {
             @class($objcname:ident)
             @superclass($superclass:ident)
+            $(@root($root:ident))?
+            $(@protocols($($protocol:ident),* $(,)?))?
             payload: unsafe uninitialized nondrop $payload:ty,
-            methods: []
+            $(properties: [ $($property:tt)* ],)?
+            methods: [ $($objcmethod:literal => unsafe $methodfn:expr),* $(,)? ]
         }
     ) => {
-        objr::__objc_subclass_impl_with_payload_no_methods!($pub,$identifier,$objcname,$superclass,$payload);
+        objr::__objc_subclass_impl!($pub,$identifier,$objcname,$superclass,[$($root)?],[$($($protocol),*)?],{ payload: $payload },
+            [$($($property)*)?],
+            [ $($objcmethod => $methodfn),* ]
+        );
     };
     (
         $pub:vis struct $identifier:ident {
             @class($objcname:ident)
             @superclass($superclass:ident)
-            payload: (),
-            methods: []
+            $(@root($root:ident))?
+            $(@protocols($($protocol:ident),* $(,)?))?
+            ivars: { $($ivar:ident : $ivarty:ty),+ $(,)? },
+            $(properties: [ $($property:tt)* ],)?
+            methods: [ $($objcmethod:literal => unsafe $methodfn:expr),* $(,)? ]
         }
     ) => {
-        objr::__objc_subclass_impl_no_payload_no_methods!($pub,$identifier,$objcname,$superclass);
+        objr::__objc_subclass_impl!($pub,$identifier,$objcname,$superclass,[$($root)?],[$($($protocol),*)?],{ $($ivar : $ivarty),+ },
+            [$($($property)*)?],
+            [ $($objcmethod => $methodfn),* ]
+        );
     };
-        (
+    (
         $pub:vis struct $identifier:ident {
             @class($objcname:ident)
             @superclass($superclass:ident)
+            $(@root($root:ident))?
+            $(@protocols($($protocol:ident),* $(,)?))?
             payload: (),
-            methods: [ $($objcmethod:literal => unsafe $methodfn:expr $(,)?)+ ]
+            $(properties: [ $($property:tt)* ],)?
+            methods: [ $($objcmethod:literal => unsafe $methodfn:expr),* $(,)? ]
         }
     ) => {
-        objr::__objc_subclass_impl_no_payload_with_methods!($pub,$identifier,$objcname,$superclass,
-            [ $($objcmethod => $methodfn )* ]
+        objr::__objc_subclass_impl!($pub,$identifier,$objcname,$superclass,[$($root)?],[$($($protocol),*)?],{ },
+            [$($($property)*)?],
+            [ $($objcmethod => $methodfn),* ]
         );
     };
     (
         $pub:vis struct $identifier:ident {
             @class($objcname:ident)
             @superclass($superclass:ident)
-            payload: unsafe uninitialized nondrop $payload:ty,
-            methods: [ $($objcmethod:literal => unsafe $methodfn:expr $(,)?)+ ]
+            $(@root($root:ident))?
+            $(@protocols($($protocol:ident),* $(,)?))?
+            payload: uninitialized $payload:ty,
+            $(properties: [ $($property:tt)* ],)?
+            methods: [ $($objcmethod:literal => unsafe $methodfn:expr),* $(,)? ]
         }
     ) => {
-        objr::__objc_subclass_impl_with_payload_with_methods!($pub,$identifier,$objcname,$superclass,$payload,
-            [ $($objcmethod => $methodfn )* ]
+        objr::__objc_subclass_impl!($pub,$identifier,$objcname,$superclass,[$($root)?],[$($($protocol),*)?],{ _payload: objr::bindings::_PayloadCell<$payload> },
+            [$($($property)*)?],
+            [ "-(id) .cxx_construct" => $identifier::_objr_cxx_construct, "-(void) .cxx_destruct" => $identifier::_objr_cxx_destruct $(, $objcmethod => $methodfn)* ]
         );
+        objr::__objc_subclass_implpart_payload_structors!($identifier, _payload_mut, objr::bindings::_PayloadCell<$payload>);
+        objr::__objc_subclass_implpart_payload_init!($pub, $identifier, $payload);
+    };
+    (
+        $pub:vis struct $identifier:ident {
+            @class($objcname:ident)
+            @superclass($superclass:ident)
+            $(@root($root:ident))?
+            $(@protocols($($protocol:ident),* $(,)?))?
+            payload: $payload:ty,
+            $(properties: [ $($property:tt)* ],)?
+            methods: [ $($objcmethod:literal => unsafe $methodfn:expr),* $(,)? ]
+        }
+    ) => {
+        objr::__objc_subclass_impl!($pub,$identifier,$objcname,$superclass,[$($root)?],[$($($protocol),*)?],{ payload: $payload },
+            [$($($property)*)?],
+            [ "-(id) .cxx_construct" => $identifier::_objr_cxx_construct, "-(void) .cxx_destruct" => $identifier::_objr_cxx_destruct $(, $objcmethod => $methodfn)* ]
+        );
+        objr::__objc_subclass_implpart_payload_structors!($identifier, payload_mut, $payload);
     };
-
-
 }
 
 #[cfg(test)]
@@ -753,6 +1365,157 @@ mod test {
         assert!(*ex.payload() == 5);
     }
 
+    mod example_drop_payload {
+        use objr::bindings::*;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        pub static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
+        pub struct DropPayload {
+            pub value: u8,
+        }
+        impl Default for DropPayload {
+            fn default() -> Self { DropPayload { value: 3 } }
+        }
+        impl Drop for DropPayload {
+            fn drop(&mut self) {
+                DROP_COUNT.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        objc_subclass! {
+            pub struct ExampleDropPayload {
+                @class(ExampleDropPayload)
+                @superclass(NSObject)
+                payload: DropPayload,
+                methods: []
+            }
+        }
+    }
+
+    #[test] fn drop_payload() {
+        use objr::bindings::*;
+        use std::sync::atomic::Ordering;
+        use example_drop_payload::{ExampleDropPayload, DropPayload, DROP_COUNT};
+        let pool = unsafe{ AutoreleasePool::new() };
+        let ex = ExampleDropPayload::class().alloc_init(&pool);
+        //constructed with Default
+        assert_eq!(ex.payload().value, 3);
+        //replacing the payload drops the old one
+        unsafe{ *ex.payload_mut() = DropPayload { value: 4 } };
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 1);
+        assert_eq!(ex.payload().value, 4);
+        std::mem::drop(ex);
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 2);
+    }
+
+    mod example_init_payload {
+        use objr::bindings::*;
+        objc_subclass! {
+            pub struct ExampleInitPayload {
+                @class(ExampleInitPayload)
+                @superclass(NSObject)
+                payload: uninitialized String,
+                methods: [ "-(id) initWithLength:(unsigned long long) length" => unsafe init_with_length ]
+            }
+        }
+        extern "C" fn init_with_length(objc_self: &ExampleInitPayload, _sel: Sel, length: u64) -> *const ExampleInitPayload {
+            let new_self: &ExampleInitPayload = unsafe{ &*ExampleInitPayload::perform_super(objc_self.assume_nonmut_perform(), Sel::init(), &ActiveAutoreleasePool::assume_autoreleasepool(), ()) };
+            new_self.init_payload("a".repeat(length as usize));
+            new_self
+        }
+        //payloads don't need to be Send or Sync
+        objc_subclass! {
+            pub struct ExampleRcPayload {
+                @class(ExampleRcPayload)
+                @superclass(NSObject)
+                payload: uninitialized std::rc::Rc<std::cell::RefCell<u32>>,
+                methods: []
+            }
+        }
+    }
+
+    #[test] fn init_payload() {
+        use objr::bindings::*;
+        use example_init_payload::ExampleInitPayload;
+        let pool = unsafe{ AutoreleasePool::new() };
+        let ex = ExampleInitPayload::class().alloc_init(&pool);
+        assert!(ex.try_payload().is_none());
+        ex.init_payload("hello".to_owned());
+        assert_eq!(ex.payload(), "hello");
+
+        let ex = unsafe {
+            let alloc = ExampleInitPayload::class().alloc(&pool);
+            let ex: *const ExampleInitPayload = ExampleInitPayload::perform(alloc, Sel::from_str("initWithLength:"), &pool, (3u64,));
+            ExampleInitPayload::assume_nonnil(ex).assume_retained()
+        };
+        assert_eq!(ex.payload(), "aaa");
+
+        let shared = std::rc::Rc::new(std::cell::RefCell::new(1));
+        let ex = example_init_payload::ExampleRcPayload::class().alloc_init(&pool);
+        ex.init_payload(shared.clone());
+        *ex.payload().borrow_mut() += 1;
+        assert_eq!(*shared.borrow(), 2);
+        std::mem::drop(ex);
+        assert_eq!(std::rc::Rc::strong_count(&shared), 1);
+    }
+
+    #[test] fn payload_cell_threads() {
+        use objr::bindings::_PayloadCell;
+        let cell = _PayloadCell::default();
+        let wins = std::sync::atomic::AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let (cell, wins) = (&cell, &wins);
+                scope.spawn(move || {
+                    if cell.set(thread).is_ok() {
+                        wins.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    }
+                    //whoever won, readers see a complete value or none
+                    if let Some(value) = cell.get() {
+                        assert!(*value < 8);
+                    }
+                });
+            }
+        });
+        assert_eq!(wins.into_inner(), 1);
+        assert!(cell.get().is_some());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test] #[should_panic(expected="initialized twice")] fn init_payload_twice() {
+        use objr::bindings::*;
+        use example_init_payload::ExampleInitPayload;
+        let pool = unsafe{ AutoreleasePool::new() };
+        let ex = ExampleInitPayload::class().alloc_init(&pool);
+        ex.init_payload("hello".to_owned());
+        ex.init_payload("world".to_owned());
+    }
+
+    mod example_proxy {
+        use objr::bindings::*;
+        objc_subclass! {
+            pub struct ExampleProxy {
+                @class(ExampleProxy)
+                @superclass(NSProxy)
+                @root(NSProxy)
+                payload: (),
+                methods: [ "-(id) init" => unsafe init ]
+            }
+        }
+        //NSProxy has no init to chain to
+        extern "C" fn init(objc_self: &ExampleProxy, _sel: Sel) -> *const ExampleProxy {
+            objc_self
+        }
+    }
+
+    #[test] fn proxy_root() {
+        use objr::bindings::*;
+        use example_proxy::ExampleProxy;
+        //the mock runtime has no NSProxy, so declare a stand-in
+        #[cfg(feature="mock-runtime")]
+        crate::mock::MockClass::new("NSProxy").register();
+        let pool = unsafe{ AutoreleasePool::new() };
+        let _proxy = ExampleProxy::class().alloc_init(&pool);
+    }
+
     mod multiple {
         use objr::bindings::*;
         objc_subclass! {
@@ -819,8 +1582,9 @@ mod test {
 
     mod multiple_methods {
         use objr::bindings::*;
-        extern "C" fn sample(_objc_self: &mut Methods1, _sel: Sel) {
+        extern "C" fn sample(objc_self: &mut Methods1, _sel: Sel) -> *const Methods1 {
             println!("init from rust");
+            objc_self
         }
         objc_subclass! {
             struct Methods1 {
@@ -844,6 +1608,290 @@ mod test {
         }
     }
 
+    mod example_ivars {
+        use objr::bindings::*;
+        objc_subclass! {
+            pub struct ExampleIvars {
+                @class(ExampleIvars)
+                @superclass(NSObject)
+                ivars: { count: u32, delegate: StrongCell<NSObject>, maybe: Option<u32>, ratio: f64 },
+                methods: [
+                    "-(id) init" => unsafe init
+                ]
+            }
+        }
+        extern "C" fn init(objc_self: &ExampleIvars, _sel: Sel) -> *const ExampleIvars {
+            let pool = unsafe{ ActiveAutoreleasePool::assume_autoreleasepool() };
+            let new_self: &ExampleIvars = unsafe{ &*ExampleIvars::perform_super(objc_self.assume_nonmut_perform(), Sel::init(), &pool, () ) };
+            unsafe {
+                std::ptr::write(new_self.count_mut(), 5);
+                std::ptr::write(new_self.delegate_mut(), NSObject::class().alloc_init(&pool));
+            }
+            new_self
+        }
+        ///The type encodings in the ivar list
+        pub fn encodings() -> Vec<String> {
+            ivar_list_ExampleIvars::IVAR_LIST.0.ivars.iter().map(|ivar| unsafe{ std::ffi::CStr::from_ptr(ivar.r#type as *const _) }.to_str().unwrap().to_owned()).collect()
+        }
+    }
+
+    #[test] fn ivars() {
+        use objr::bindings::*;
+        let pool = unsafe{ AutoreleasePool::new() };
+        let ex = example_ivars::ExampleIvars::class().alloc_init(&pool);
+        assert_eq!(*ex.count(), 5);
+        let count_addr = ex.count() as *const u32 as usize;
+        let delegate_addr = ex.delegate() as *const StrongCell<NSObject> as usize;
+        assert!(count_addr >= &*ex as *const _ as usize + 8);
+        assert!(delegate_addr >= count_addr + 4);
+        assert_eq!(delegate_addr % std::mem::align_of::<StrongCell<NSObject>>(), 0);
+        //ivars are nondrop
+        unsafe{ std::ptr::drop_in_place(ex.delegate_mut()) };
+        //Option<u32> isn't a u32, and doesn't implement Encode
+        assert_eq!(example_ivars::encodings(), ["I", "@\"NSObject\"", "?", "d"]);
+    }
+
+    mod example_class_methods {
+        use objr::bindings::*;
+        objc_subclass! {
+            pub struct ExampleClassMethods {
+                @class(ExampleClassMethods)
+                @superclass(NSObject)
+                payload: (),
+                methods: [
+                    "+(int) answer" => unsafe answer,
+                    "-(int) instanceAnswer" => unsafe instance_answer
+                ]
+            }
+        }
+        extern "C" fn answer(_class: &Class<ExampleClassMethods>, _sel: Sel) -> i32 {
+            42
+        }
+        extern "C" fn instance_answer(_objc_self: &ExampleClassMethods, _sel: Sel) -> i32 {
+            -42
+        }
+    }
+
+    #[test] fn class_methods() {
+        use objr::bindings::*;
+        use example_class_methods::ExampleClassMethods;
+        let pool = unsafe{ AutoreleasePool::new() };
+        let answer: i32 = unsafe{ Class::<ExampleClassMethods>::perform_primitive(ExampleClassMethods::class().assume_nonmut_perform(), Sel::from_str("answer"), &pool, ()) };
+        assert_eq!(answer, 42);
+        let ex = ExampleClassMethods::class().alloc_init(&pool);
+        let instance_answer: i32 = unsafe{ ExampleClassMethods::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("instanceAnswer"), &pool, ()) };
+        assert_eq!(instance_answer, -42);
+    }
+
+    mod example_protocols {
+        use objr::bindings::*;
+        objc_subclass! {
+            pub struct ExampleProtocols {
+                @class(ExampleProtocols)
+                @superclass(NSObject)
+                @protocols(NSCopying, NSSecureCoding)
+                payload: (),
+                methods: []
+            }
+        }
+        objc_subclass! {
+            pub struct ExampleOtherProtocol {
+                @class(ExampleOtherProtocol)
+                @superclass(NSObject)
+                @protocols(NSFastEnumeration)
+                payload: (),
+                methods: []
+            }
+        }
+    }
+
+    #[test] fn protocols() {
+        use objr::bindings::*;
+        use example_protocols::{ExampleProtocols, ExampleOtherProtocol};
+        let pool = unsafe{ AutoreleasePool::new() };
+        let class = ExampleProtocols::class();
+        let other = ExampleOtherProtocol::class();
+        let copying = Protocol::named(&std::ffi::CString::new("NSCopying").unwrap()).unwrap();
+        let enumeration = Protocol::named(&std::ffi::CString::new("NSFastEnumeration").unwrap()).unwrap();
+        assert!(class.conforms_to_protocol(&pool, copying));
+        assert!(!class.conforms_to_protocol(&pool, enumeration));
+        assert!(other.conforms_to_protocol(&pool, enumeration));
+        let instance = class.alloc_init(&pool);
+        assert!(instance.conforms_to_protocol(&pool, copying));
+        assert!(!instance.conforms_to_protocol(&pool, enumeration));
+    }
+
+    mod example_properties {
+        use objr::bindings::*;
+        objc_subclass! {
+            pub struct ExampleProperties {
+                @class(ExampleProperties)
+                @superclass(NSObject)
+                ivars: { _title: Option<StrongCell<NSObject>>, _count: u32 },
+                properties: [
+                    @property(nonatomic, strong) NSObject *title;
+                    @synthesize title = _title;
+                    @property(nonatomic) unsigned int count;
+                    @synthesize count = _count;
+                    @property(readonly, copy) NSString *summary;
+                ],
+                methods: []
+            }
+        }
+    }
+
+    #[test] fn properties() {
+        use objr::bindings::*;
+        use example_properties::ExampleProperties;
+        use std::ffi::{c_void, CStr, CString};
+        #[cfg(not(feature="mock-runtime"))]
+        use std::os::raw::c_char;
+        #[cfg(not(feature="mock-runtime"))]
+        #[link(name="objc", kind="dylib")]
+        extern "C" {
+            fn class_getProperty(class: *mut c_void, name: *const c_char) -> *const c_void;
+            fn property_getAttributes(property: *const c_void) -> *const c_char;
+        }
+        #[cfg(feature="mock-runtime")]
+        use crate::mock::runtime::{class_getProperty, property_getAttributes};
+        let attributes = |name: &str| unsafe {
+            let name = CString::new(name).unwrap();
+            let property = class_getProperty(ExampleProperties::class() as *const _ as *mut c_void, name.as_ptr());
+            assert!(!property.is_null());
+            CStr::from_ptr(property_getAttributes(property)).to_str().unwrap().to_owned()
+        };
+        let pool = unsafe{ AutoreleasePool::new() };
+        assert_eq!(attributes("title"), "T@\"NSObject\",&,N,V_title");
+        assert_eq!(attributes("count"), "TI,N,V_count");
+        assert_eq!(attributes("summary"), "T@\"NSString\",R,C");
+
+        let ex = ExampleProperties::class().alloc_init(&pool);
+        let title = NSObject::class().alloc_init(&pool);
+        unsafe {
+            let _: () = ExampleProperties::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("setTitle:"), &pool, (title.assume_nonmut_perform(),));
+            let _: () = ExampleProperties::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("setCount:"), &pool, (5u32,));
+            let got_title: *const c_void = ExampleProperties::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("title"), &pool, ());
+            let got_count: u32 = ExampleProperties::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("count"), &pool, ());
+            assert_eq!(got_title, &*title as *const NSObject as *const c_void);
+            assert_eq!(got_count, 5);
+            assert!(ex._title().is_some());
+            //release our title
+            let _: () = ExampleProperties::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("setTitle:"), &pool, (std::ptr::null_mut::<c_void>(),));
+            assert!(ex._title().is_none());
+        }
+    }
+
+    mod example_released_properties {
+        use objr::bindings::*;
+        objc_subclass! {
+            pub struct ExampleReleasedProperties {
+                @class(ExampleReleasedProperties)
+                @superclass(NSObject)
+                ivars: { _title: Option<StrongCell<NSObject>>, _summary: Option<StrongCell<NSObject>> },
+                properties: [
+                    @property(nonatomic, strong) NSObject *title;
+                    @synthesize title = _title;
+                    @property(readonly, copy) NSObject *summary;
+                    @synthesize summary = _summary;
+                ],
+                methods: []
+            }
+        }
+        //the payload is the ivar, so it's released by both `.cxx_destruct`s
+        objc_subclass! {
+            pub struct ExamplePayloadProperty {
+                @class(ExamplePayloadProperty)
+                @superclass(NSObject)
+                payload: Option<StrongCell<NSObject>>,
+                properties: [
+                    @property(nonatomic) NSObject *title;
+                    @synthesize title = payload;
+                ],
+                methods: []
+            }
+        }
+    }
+
+    #[cfg(feature="mock-runtime")]
+    #[test] fn properties_released() {
+        use objr::bindings::*;
+        use example_released_properties::{ExampleReleasedProperties, ExamplePayloadProperty};
+        use crate::mock::{is_deallocated, retain_count};
+        let pool = unsafe{ AutoreleasePool::new() };
+        let ex = ExampleReleasedProperties::class().alloc_init(&pool);
+        let title = NSObject::class().alloc_init(&pool);
+        let title_ptr = &*title as *const NSObject;
+        unsafe {
+            let _: () = ExampleReleasedProperties::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("setTitle:"), &pool, (title.assume_nonmut_perform(),));
+        }
+        drop(title);
+        assert_eq!(retain_count(title_ptr), 1);
+        drop(ex);
+        assert!(is_deallocated(title_ptr));
+
+        let ex = ExamplePayloadProperty::class().alloc_init(&pool);
+        let title = NSObject::class().alloc_init(&pool);
+        let title_ptr = &*title as *const NSObject;
+        unsafe {
+            let _: () = ExamplePayloadProperty::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("setTitle:"), &pool, (title.assume_nonmut_perform(),));
+        }
+        drop(title);
+        assert_eq!(retain_count(title_ptr), 1);
+        drop(ex);
+        assert!(is_deallocated(title_ptr));
+    }
+
+    mod example_structs {
+        use objr::bindings::*;
+        #[derive(ObjcEncode)]
+        #[repr(C)]
+        pub struct Span {
+            pub start: u16,
+            pub weights: [f32; 3],
+        }
+        #[repr(C)]
+        pub struct NSRange {
+            pub location: usize,
+            pub length: usize,
+        }
+        objc_subclass! {
+            pub struct ExampleStructs {
+                @class(ExampleStructs)
+                @superclass(NSObject)
+                payload: (),
+                methods: [
+                    "-(NSRange) rangeOfSpan:(Span) span scale:(CGFloat) scale" => unsafe range_of_span,
+                    "-(void) setFrame:(CGRect) frame" => unsafe set_frame
+                ]
+            }
+        }
+        extern "C" fn range_of_span(_objc_self: &ExampleStructs, _sel: Sel, span: Span, scale: f64) -> NSRange {
+            NSRange { location: span.start as usize, length: (span.weights.iter().sum::<f32>() as f64 * scale) as usize }
+        }
+        extern "C" fn set_frame(_objc_self: &ExampleStructs, _sel: Sel, _frame: [f64; 4]) { }
+    }
+
+    #[test] fn struct_type_encodings() {
+        use objr::bindings::*;
+        use example_structs::ExampleStructs;
+        use std::ffi::{c_void, CStr};
+        #[cfg(not(feature="mock-runtime"))]
+        #[link(name="objc", kind="dylib")]
+        extern "C" {
+            fn class_getInstanceMethod(class: *mut c_void, name: Sel) -> *const c_void;
+            fn method_getTypeEncoding(method: *const c_void) -> *const std::os::raw::c_char;
+        }
+        #[cfg(feature="mock-runtime")]
+        use crate::mock::runtime::{class_getInstanceMethod, method_getTypeEncoding};
+        let encoding = |selector: &str| unsafe {
+            let method = class_getInstanceMethod(ExampleStructs::class() as *const _ as *mut c_void, Sel::from_str(selector));
+            assert!(!method.is_null());
+            CStr::from_ptr(method_getTypeEncoding(method)).to_owned()
+        };
+        assert_eq!(encoding("rangeOfSpan:scale:").as_c_str(), CStr::from_bytes_with_nul(b"{_NSRange=QQ}40@0:8{Span=S[3f]}16d32\0").unwrap());
+        assert_eq!(encoding("setFrame:").to_str().unwrap(), "v48@0:8{CGRect={CGPoint=dd}{CGSize=dd}}16");
+    }
+
     mod distinct_names {
         use objr::bindings::*;
         objc_subclass! {
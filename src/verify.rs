@@ -0,0 +1,226 @@
+//SPDX-License-Identifier: MIT OR Apache-2.0
+//! Checks message sends against the runtime's type encoding for the method, with the `verify-sends` feature.
+//!
+//! The `perform` family transmutes `objc_msgSend` to whatever signature the caller asks for, so a binding that
+//! passes `i64` where the method takes `double` puts the argument in the wrong register, and nothing notices.
+//! With this feature, each send looks up the method's type encoding and compares it with the encodings of the Rust types,
+//! panicking on a mismatch.
+//!
+//! Types are compared by how they're passed, rather than exactly.  So `BOOL` matches `bool`, signed matches unsigned,
+//! and any kind of pointer matches any other.  Methods the runtime has no type encoding for, including methods that are forwarded,
+//! aren't checked.
+
+use std::ffi::{c_void, CStr};
+use crate::bindings::{AnyClass, Encoding, Sel};
+use crate::encode::frame_size;
+
+#[cfg(not(feature="mock-runtime"))]
+use std::os::raw::c_char;
+#[cfg(not(feature="mock-runtime"))]
+#[link(name="objc", kind="dylib")]
+extern "C" {
+    fn object_getClass(object: *mut c_void) -> *mut c_void;
+    fn class_getSuperclass(class: *const AnyClass) -> *const AnyClass;
+    fn class_isMetaClass(class: *mut c_void) -> bool;
+    fn class_getName(class: *mut c_void) -> *const c_char;
+    fn class_getInstanceMethod(class: *mut c_void, name: Sel) -> *const c_void;
+    fn method_getTypeEncoding(method: *const c_void) -> *const c_char;
+    fn sel_getName(sel: Sel) -> *const c_char;
+}
+#[cfg(feature="mock-runtime")]
+use crate::mock::runtime::{object_getClass, class_getSuperclass, class_isMetaClass, class_getName, class_getInstanceMethod, method_getTypeEncoding, sel_getName};
+
+///Reads one type from the front of `encoding`.
+///
+/// Returns the type as written, a canonical form in which types that are passed the same way are equal, and the rest of the encoding.
+fn read_type(encoding: &str) -> Option<(&str, String, &str)> {
+    //qualifiers like `r` for const don't affect how the type is passed
+    let start = encoding.trim_start_matches(['r', 'n', 'N', 'o', 'O', 'R', 'V']);
+    let first = start.chars().next()?;
+    let mut rest = &start[first.len_utf8()..];
+    let canonical = match first {
+        'c' | 'C' | 'B' => "1".to_owned(),
+        's' | 'S' => "2".to_owned(),
+        //`l` is a 32-bit long even on 64-bit platforms, where clang encodes `long` as `q`
+        'i' | 'I' | 'l' | 'L' => "4".to_owned(),
+        'q' | 'Q' => "8".to_owned(),
+        'f' | 'd' | 'D' | 'v' => first.to_string(),
+        '#' | ':' | '*' | '?' => "p".to_owned(),
+        '@' => {
+            //`@?` is a block, and `@"NSString"` names the class
+            if let Some(block) = rest.strip_prefix('?') {
+                rest = block;
+            }
+            else if let Some(class) = rest.strip_prefix('"') {
+                rest = &class[class.find('"')? + 1..];
+            }
+            "p".to_owned()
+        }
+        '^' => {
+            let (_, _, after) = read_type(rest)?;
+            rest = after;
+            "p".to_owned()
+        }
+        'b' => {
+            let width = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+            let canonical = format!("b{}", &rest[..rest.len() - width.len()]);
+            rest = width;
+            canonical
+        }
+        '[' => {
+            let element = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+            let count = &rest[..rest.len() - element.len()];
+            let (_, element_canonical, after) = read_type(element)?;
+            rest = after.strip_prefix(']')?;
+            format!("[{}{}]", count, element_canonical)
+        }
+        '{' | '(' => {
+            let close = if first == '{' { '}' } else { ')' };
+            //the name is not part of how the type is passed
+            let name_end = rest.find(['=', close])?;
+            rest = &rest[name_end..];
+            let mut fields = String::new();
+            if let Some(after) = rest.strip_prefix('=') {
+                rest = after;
+                while !rest.starts_with(close) {
+                    let (_, field, after) = read_type(rest)?;
+                    fields.push_str(&field);
+                    rest = after;
+                }
+            }
+            rest = &rest[1..];
+            format!("{}{}{}", first, fields, close)
+        }
+        other => other.to_string(),
+    };
+    Some((&encoding[..encoding.len() - rest.len()], canonical, rest))
+}
+
+///Reads the return and argument types out of a method type encoding like `v24@0:8i16f20`.
+fn read_method_types(encoding: &str) -> Option<Vec<(&str, String)>> {
+    let mut types = Vec::new();
+    let mut rest = encoding;
+    while !rest.is_empty() {
+        let (written, canonical, after) = read_type(rest)?;
+        types.push((written, canonical));
+        //skip the frame size or argument offset
+        rest = after.trim_start_matches(|c: char| c.is_ascii_digit() || c == '-');
+    }
+    Some(types)
+}
+
+///The method type encoding for a send, like `v24@0:8i16f20`.  `arguments` include `self` and `_cmd`.
+fn method_type_encoding(return_type: &Encoding, arguments: &[(Encoding, usize)]) -> String {
+    let frame: usize = arguments.iter().map(|(_, size)| frame_size(*size)).sum();
+    let mut encoding = format!("{}{}", return_type, frame);
+    let mut offset = 0;
+    for (argument, size) in arguments {
+        encoding.push_str(&format!("{}{}", argument, offset));
+        offset += frame_size(*size);
+    }
+    encoding
+}
+
+///Compares the types of a send with the runtime's type encoding for the method.
+///
+/// Returns a description of the differences, if there are any.
+fn compare(method_encoding: &str, send_encoding: &str) -> Option<String> {
+    //if we can't read the runtime's encoding, we can't check it
+    let expected = read_method_types(method_encoding)?;
+    let got = read_method_types(send_encoding).expect("Can't read our own encoding");
+    if expected.len() != got.len() {
+        return Some(format!("\n    the method takes {} arguments, but was sent {}", expected.len() - 1, got.len() - 1));
+    }
+    let mut differences = String::new();
+    for (index, (expected, got)) in expected.iter().zip(got.iter()).enumerate() {
+        if expected.1 != got.1 {
+            let what = if index == 0 { "return type".to_owned() } else { format!("argument {}", index - 1) };
+            differences.push_str(&format!("\n    {}: expected `{}`, got `{}`", what, expected.0, got.0));
+        }
+    }
+    if differences.is_empty() { None } else { Some(differences) }
+}
+
+///Checks a message send against the method's type encoding, panicking on a mismatch.
+///
+/// `super_class` is the class passed to `objc_msgSendSuper2` for super sends, whose superclass has the method.
+/// `arguments` are the encodings and sizes of the arguments after `_cmd`.
+pub(crate) unsafe fn verify_send(receiver: *mut c_void, super_class: Option<*const AnyClass>, sel: Sel, return_type: Encoding, arguments: &[(Encoding, usize)]) {
+    if receiver.is_null() {
+        return;
+    }
+    let class = match super_class {
+        Some(class) => class_getSuperclass(class) as *mut c_void,
+        None => object_getClass(receiver),
+    };
+    let method = class_getInstanceMethod(class, sel);
+    if method.is_null() {
+        return;
+    }
+    let method_encoding = method_getTypeEncoding(method);
+    if method_encoding.is_null() {
+        return;
+    }
+    let method_encoding = CStr::from_ptr(method_encoding).to_string_lossy();
+    let mut all_arguments = vec![(Encoding::Primitive("@"), 8), (Encoding::Primitive(":"), 8)];
+    all_arguments.extend_from_slice(arguments);
+    let send_encoding = method_type_encoding(&return_type, &all_arguments);
+    if let Some(differences) = compare(&method_encoding, &send_encoding) {
+        panic!("verify-sends: {}[{} {}] has type encoding `{}`, but was sent as `{}`{}",
+            if class_isMetaClass(class) { "+" } else { "-" },
+            CStr::from_ptr(class_getName(class)).to_string_lossy(), CStr::from_ptr(sel_getName(sel)).to_string_lossy(), method_encoding, send_encoding, differences);
+    }
+}
+
+#[test] fn read_types() {
+    let types = read_method_types("{_NSRange=QQ}40@0:8r^{Span=S[3f]}16@\"NSString\"24@?32").unwrap();
+    let written: Vec<&str> = types.iter().map(|t| t.0).collect();
+    assert_eq!(written, vec!["{_NSRange=QQ}", "@", ":", "r^{Span=S[3f]}", "@\"NSString\"", "@?"]);
+    let canonical: Vec<&str> = types.iter().map(|t| t.1.as_str()).collect();
+    assert_eq!(canonical, vec!["{88}", "p", "p", "p", "p", "p"]);
+    assert_eq!(read_method_types("(?=ib3{?})16@0:8").unwrap()[0].1, "(4b3{})");
+}
+
+#[test] fn compare_sends() {
+    //BOOL is `c` on x86_64, and objects can be sent as `*mut c_void`
+    assert_eq!(compare("c24@0:8@16", "B24@0:8^v16"), None);
+    assert_eq!(compare("@24@0:8d16", "@24@0:8q16").unwrap(), "\n    argument 2: expected `d`, got `q`");
+    assert_eq!(compare("v16@0:8", "v20@0:8i16").unwrap(), "\n    the method takes 2 arguments, but was sent 3");
+    assert_eq!(compare("v20@0:8l16", "v20@0:8i16"), None);
+    assert_eq!(compare("v24@0:8l16", "v24@0:8q16").unwrap(), "\n    argument 2: expected `l`, got `q`");
+}
+
+#[cfg(test)] mod example {
+    use objr::bindings::*;
+    objc_subclass! {
+        pub struct ExampleVerify {
+            @class(ExampleVerify)
+            @superclass(NSObject)
+            payload: (),
+            methods: [
+                "-(double) scaled:(double) x" => unsafe scaled
+            ]
+        }
+    }
+    extern "C" fn scaled(_objc_self: &ExampleVerify, _sel: Sel, x: f64) -> f64 {
+        x * 2.0
+    }
+}
+
+#[test] fn verified_send() {
+    use objr::bindings::*;
+    use example::ExampleVerify;
+    let pool = unsafe{ AutoreleasePool::new() };
+    let ex = ExampleVerify::class().alloc_init(&pool);
+    let result: f64 = unsafe{ ExampleVerify::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("scaled:"), &pool, (2.5f64,)) };
+    assert_eq!(result, 5.0);
+}
+
+#[test] #[should_panic(expected="verify-sends: -[ExampleVerify scaled:] has type encoding `d24@0:8d16`, but was sent as `d24@0:8q16`\n    argument 2: expected `d`, got `q`")]
+fn mismatched_send() {
+    use objr::bindings::*;
+    use example::ExampleVerify;
+    let pool = unsafe{ AutoreleasePool::new() };
+    let ex = ExampleVerify::class().alloc_init(&pool);
+    let _: f64 = unsafe{ ExampleVerify::perform_primitive(ex.assume_nonmut_perform(), Sel::from_str("scaled:"), &pool, (2i64,)) };
+}